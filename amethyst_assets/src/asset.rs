@@ -1,4 +1,6 @@
-use crate::{storage::ProcessingState, FormatRegisteredData, Handle, Reload, SingleFile, Source};
+use crate::{
+    storage::ProcessingState, AssetId, FormatRegisteredData, Handle, Reload, SingleFile, Source,
+};
 use amethyst_core::ecs::storage::UnprotectedStorage;
 use amethyst_error::{Error, ResultExt};
 use std::{fmt::Debug, ops::Deref, sync::Arc};
@@ -55,6 +57,14 @@ pub trait Format<D: 'static>: objekt::Clone + Debug + Send + Sync + 'static {
     /// A unique identifier for this format.
     fn name(&self) -> &'static str;
 
+    /// Other assets the imported asset depends on, e.g. the texture of a sprite sheet.
+    ///
+    /// The `Loader` records these in its `Dependencies`, so the asset is reloaded
+    /// whenever one of them is.
+    fn dependencies(&self) -> Vec<AssetId> {
+        Vec::new()
+    }
+
     /// Produces asset data from given bytes.
     /// This method is a simplified version of `format`.
     /// This format assumes that the asset name is the full path and the asset is only
@@ -86,12 +96,15 @@ pub trait Format<D: 'static>: objekt::Clone + Debug + Send + Sync + 'static {
         #[cfg(feature = "profiler")]
         profile_scope!("import_asset");
         if let Some(boxed_format) = create_reload {
+            let generation = source.changes().map(|changes| changes.generation());
             let (b, m) = source
                 .load_with_metadata(&name)
                 .with_context(|_| crate::error::Error::Source)?;
             Ok(FormatValue {
                 data: self.import_simple(b)?,
-                reload: Some(Box::new(SingleFile::new(boxed_format, m, name, source))),
+                reload: Some(Box::new(
+                    SingleFile::new(boxed_format, m, name, source).with_generation(generation),
+                )),
             })
         } else {
             let b = source
@@ -123,6 +136,9 @@ impl<D: 'static> Format<D> for Box<dyn Format<D>> {
     fn name(&self) -> &'static str {
         self.deref().name()
     }
+    fn dependencies(&self) -> Vec<AssetId> {
+        self.deref().dependencies()
+    }
     fn import_simple(&self, bytes: Vec<u8>) -> Result<D, Error> {
         self.deref().import_simple(bytes)
     }
@@ -141,6 +157,9 @@ impl<D: 'static> Format<D> for Box<dyn SerializableFormat<D>> {
     fn name(&self) -> &'static str {
         self.deref().name()
     }
+    fn dependencies(&self) -> Vec<AssetId> {
+        self.deref().dependencies()
+    }
     fn import_simple(&self, bytes: Vec<u8>) -> Result<D, Error> {
         self.deref().import_simple(bytes)
     }
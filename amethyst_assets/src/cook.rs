@@ -0,0 +1,464 @@
+//! Offline processing ("cooking") of assets into a binary cache.
+//!
+//! Cooking runs a `Format` once and stores the resulting asset data in a `CookCache`,
+//! serialized with `bincode`. Cache entries are keyed by a hash of the source bytes and
+//! a hash of the format, its options and the data type, so an entry is only used as
+//! long as neither the source file nor the way it is imported changes.
+//!
+//! The cache can be filled ahead of time with a `Cooker` (e.g. from a build script or the
+//! `amethyst_cook` binary) and is used at runtime by wrapping a format in `Cooked`, or by
+//! loading through `Loader::load_cooked`.
+
+use std::{
+    any::type_name,
+    fmt, fs,
+    hash::Hasher,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use fnv::{FnvHashMap, FnvHasher};
+use log::{debug, trace, warn};
+use serde::{de::DeserializeOwned, Serialize};
+
+use amethyst_error::{format_err, Error, ResultExt};
+
+use crate::{AssetId, Format};
+
+/// Magic bytes at the start of every cache entry.
+const MAGIC: &[u8; 4] = b"ACOK";
+/// Bumped whenever the layout of cache entries changes.
+const VERSION: u32 = 1;
+
+/// Identifies a cache entry.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct CookKey {
+    /// Hash of the source bytes.
+    pub source: u64,
+    /// Hash of the format, its options and the type of data it produces.
+    pub options: u64,
+}
+
+impl CookKey {
+    /// Computes the key of `bytes` imported with `format`.
+    pub fn new<D, F>(format: &F, bytes: &[u8]) -> Result<Self, Error>
+    where
+        D: 'static,
+        F: Format<D> + Serialize,
+    {
+        let options = ron::ser::to_string(format)
+            .with_context(|_| format_err!("Failed to serialize options of {}", format.name()))?;
+
+        let mut source = FnvHasher::default();
+        source.write(bytes);
+
+        let mut hasher = FnvHasher::default();
+        hasher.write_u32(VERSION);
+        hasher.write(format.name().as_bytes());
+        hasher.write(type_name::<D>().as_bytes());
+        hasher.write(options.as_bytes());
+
+        Ok(CookKey {
+            source: source.finish(),
+            options: hasher.finish(),
+        })
+    }
+
+    fn file_name(&self) -> String {
+        format!("{:016x}{:016x}.cooked", self.source, self.options)
+    }
+}
+
+/// A directory holding cooked asset data.
+///
+/// Entries are content addressed, so the same cache can be shared by all sources and
+/// the cooked data of a file is found again no matter where it is loaded from.
+#[derive(Clone, Debug)]
+pub struct CookCache {
+    dir: PathBuf,
+}
+
+impl CookCache {
+    /// Creates a cache storing its entries in `dir`. The directory is created on demand.
+    pub fn new<P>(dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        CookCache { dir: dir.into() }
+    }
+
+    /// The directory holding the entries.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns `true` if there is an entry for `key`.
+    pub fn contains(&self, key: CookKey) -> bool {
+        self.dir.join(key.file_name()).is_file()
+    }
+
+    /// Loads the data stored for `key`.
+    ///
+    /// Returns `None` if there is no entry, or if it can't be read; a broken entry is
+    /// just treated as a cache miss and overwritten by the next `store`.
+    pub fn load<D>(&self, key: CookKey) -> Option<D>
+    where
+        D: DeserializeOwned,
+    {
+        let path = self.dir.join(key.file_name());
+        let bytes = fs::read(&path).ok()?;
+        let data = bytes
+            .get(..8)
+            .filter(|header| &header[..4] == MAGIC && header[4..] == VERSION.to_le_bytes())
+            .ok_or_else(|| "invalid header".to_string())
+            .and_then(|_| bincode::deserialize(&bytes[8..]).map_err(|e| e.to_string()));
+
+        match data {
+            Ok(data) => Some(data),
+            Err(e) => {
+                warn!("Ignoring broken cooked asset {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Stores `data` for `key`, replacing any existing entry.
+    pub fn store<D>(&self, key: CookKey, data: &D) -> Result<(), Error>
+    where
+        D: Serialize,
+    {
+        let path = self.dir.join(key.file_name());
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, data)
+            .with_context(|_| format_err!("Failed to serialize cooked asset {:?}", path))?;
+
+        // Write to a temporary file first, so a concurrent reader never sees half an entry.
+        let tmp = path.with_extension("tmp");
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&tmp, &bytes))
+            .and_then(|_| fs::rename(&tmp, &path))
+            .with_context(|_| format_err!("Failed to write cooked asset {:?}", path))
+    }
+
+    /// Imports `bytes` with `format`, using the cached data if there is an entry for them.
+    /// On a cache miss, the imported data is stored for the next time.
+    pub fn cook<D, F>(&self, format: &F, bytes: Vec<u8>) -> Result<D, Error>
+    where
+        D: Serialize + DeserializeOwned + 'static,
+        F: Format<D> + Serialize,
+    {
+        let key = CookKey::new(format, &bytes)?;
+        if let Some(data) = self.load(key) {
+            trace!("Using cooked {} asset {:?}", format.name(), key);
+            return Ok(data);
+        }
+
+        let data = format.import_simple(bytes)?;
+        if let Err(e) = self.store(key, &data) {
+            warn!("{}", e);
+        }
+        Ok(data)
+    }
+}
+
+/// A format which loads cooked data from a `CookCache` when it is fresh, and imports
+/// with the wrapped format otherwise.
+///
+/// Only formats implementing `Format::import_simple` can be cooked, as the cache is
+/// keyed by the bytes of a single source file.
+///
+/// ```rust,ignore
+/// let cache = Arc::new(CookCache::new("target/cooked"));
+/// loader.load("mesh/cone.obj", Cooked::new(ObjFormat, cache), (), &storage);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cooked<F> {
+    format: F,
+    cache: Arc<CookCache>,
+}
+
+impl<F> Cooked<F> {
+    /// Wraps `format`, caching its output in `cache`.
+    pub fn new(format: F, cache: Arc<CookCache>) -> Self {
+        Cooked { format, cache }
+    }
+}
+
+impl<D, F> Format<D> for Cooked<F>
+where
+    D: Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: Format<D> + Clone + Serialize,
+{
+    fn name(&self) -> &'static str {
+        self.format.name()
+    }
+
+    fn dependencies(&self) -> Vec<AssetId> {
+        self.format.dependencies()
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<D, Error> {
+        self.cache.cook(&self.format, bytes)
+    }
+}
+
+/// What `Cooker::cook_file` did with a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CookStatus {
+    /// The file was imported and the result stored in the cache.
+    Cooked,
+    /// The cache already had an up to date entry for the file.
+    Fresh,
+    /// No format is registered for the file's extension.
+    Skipped,
+}
+
+/// Summary of a `Cooker::cook_dir` run.
+#[derive(Debug, Default)]
+pub struct CookReport {
+    /// Number of files imported and stored in the cache.
+    pub cooked: usize,
+    /// Number of files which were already up to date.
+    pub fresh: usize,
+    /// Number of files without a registered format.
+    pub skipped: usize,
+    /// Files which couldn't be cooked, with the reason why.
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+impl fmt::Display for CookReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cooked, {} up to date, {} skipped, {} failed",
+            self.cooked,
+            self.fresh,
+            self.skipped,
+            self.failed.len(),
+        )
+    }
+}
+
+type CookFn = Box<dyn Fn(&CookCache, Vec<u8>) -> Result<CookStatus, Error> + Send + Sync>;
+
+/// Cooks whole asset directories ahead of time.
+///
+/// Formats are registered per file extension; every file with a registered extension
+/// is imported and stored in the cache, unless it is already up to date. To hit the
+/// cache at runtime, assets have to be loaded with the same format and options.
+///
+/// ```rust,ignore
+/// let report = Cooker::new(CookCache::new("target/cooked"))
+///     .with_format::<MeshData, _>("obj", ObjFormat)
+///     .with_format::<TextureData, _>("png", ImageFormat::default())
+///     .cook_dir("assets")?;
+/// println!("{}", report);
+/// ```
+pub struct Cooker {
+    cache: CookCache,
+    formats: FnvHashMap<String, CookFn>,
+}
+
+impl Cooker {
+    /// Creates a cooker without any formats, storing its output in `cache`.
+    pub fn new(cache: CookCache) -> Self {
+        Cooker {
+            cache,
+            formats: Default::default(),
+        }
+    }
+
+    /// Registers `format` for files with the given extension.
+    pub fn with_format<D, F>(mut self, extension: &str, format: F) -> Self
+    where
+        D: Serialize + DeserializeOwned + 'static,
+        F: Format<D> + Serialize,
+    {
+        self.add_format::<D, F>(extension, format);
+        self
+    }
+
+    /// Registers `format` for files with the given extension, replacing any format
+    /// registered for it before.
+    pub fn add_format<D, F>(&mut self, extension: &str, format: F)
+    where
+        D: Serialize + DeserializeOwned + 'static,
+        F: Format<D> + Serialize,
+    {
+        let cook = move |cache: &CookCache, bytes: Vec<u8>| {
+            let key = CookKey::new(&format, &bytes)?;
+            if cache.contains(key) {
+                return Ok(CookStatus::Fresh);
+            }
+            let data = format.import_simple(bytes)?;
+            cache.store(key, &data)?;
+            Ok(CookStatus::Cooked)
+        };
+        self.formats
+            .insert(extension.to_lowercase(), Box::new(cook));
+    }
+
+    /// The cache this cooker stores its output in.
+    pub fn cache(&self) -> &CookCache {
+        &self.cache
+    }
+
+    /// Cooks a single file.
+    pub fn cook_file<P>(&self, path: P) -> Result<CookStatus, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let cook = match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.formats.get(&ext.to_lowercase()))
+        {
+            Some(cook) => cook,
+            None => return Ok(CookStatus::Skipped),
+        };
+
+        let bytes =
+            fs::read(path).with_context(|_| format_err!("Failed to read asset {:?}", path))?;
+        cook(&self.cache, bytes).with_context(|_| format_err!("Failed to cook asset {:?}", path))
+    }
+
+    /// Cooks all files in `dir` and its subdirectories.
+    ///
+    /// Files which fail to cook are collected in the report instead of aborting the run;
+    /// only failing to list a directory is an error.
+    pub fn cook_dir<P>(&self, dir: P) -> Result<CookReport, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut report = CookReport::default();
+        let mut dirs = vec![dir.as_ref().to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries = fs::read_dir(&dir)
+                .with_context(|_| format_err!("Failed to read directory {:?}", dir))?;
+            for entry in entries {
+                let path = entry
+                    .with_context(|_| format_err!("Failed to read directory {:?}", dir))?
+                    .path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                match self.cook_file(&path) {
+                    Ok(CookStatus::Cooked) => {
+                        debug!("Cooked {:?}", path);
+                        report.cooked += 1;
+                    }
+                    Ok(CookStatus::Fresh) => report.fresh += 1,
+                    Ok(CookStatus::Skipped) => report.skipped += 1,
+                    Err(e) => report.failed.push((path, e)),
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl fmt::Debug for Cooker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cooker")
+            .field("cache", &self.cache)
+            .field("formats", &self.formats.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use amethyst_error::Error;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{Format, RonFormat};
+
+    use super::{CookCache, CookKey, CookStatus, Cooked, Cooker};
+
+    static IMPORTS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Counts its imports, to tell cache hits from misses.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Counting(u32);
+
+    impl Format<Vec<u32>> for Counting {
+        fn name(&self) -> &'static str {
+            "Counting"
+        }
+
+        fn import_simple(&self, bytes: Vec<u8>) -> Result<Vec<u32>, Error> {
+            IMPORTS.fetch_add(1, Ordering::SeqCst);
+            Ok(bytes.into_iter().map(|b| u32::from(b) * self.0).collect())
+        }
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("amethyst_cook_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn keys_depend_on_source_and_options() {
+        let a = CookKey::new::<Vec<u32>, _>(&Counting(1), b"ab").unwrap();
+        assert_eq!(a, CookKey::new::<Vec<u32>, _>(&Counting(1), b"ab").unwrap());
+        assert_ne!(a, CookKey::new::<Vec<u32>, _>(&Counting(2), b"ab").unwrap());
+        assert_ne!(
+            a,
+            CookKey::new::<Vec<u32>, _>(&Counting(1), b"abc").unwrap()
+        );
+        assert_ne!(
+            a.options,
+            CookKey::new::<Vec<u32>, _>(&RonFormat, b"ab")
+                .unwrap()
+                .options
+        );
+    }
+
+    #[test]
+    fn cooked_directory_is_loaded_from_cache() {
+        let assets = temp_dir("assets");
+        let cache_dir = temp_dir("cache");
+        fs::create_dir_all(assets.join("sub")).unwrap();
+        fs::write(assets.join("a.num"), [1, 2]).unwrap();
+        fs::write(assets.join("sub/b.NUM"), [3]).unwrap();
+        fs::write(assets.join("readme.txt"), "not an asset").unwrap();
+
+        let cooker = Cooker::new(CookCache::new(&cache_dir)).with_format("num", Counting(10));
+        let report = cooker.cook_dir(&assets).unwrap();
+        assert_eq!((2, 0, 1), (report.cooked, report.fresh, report.skipped));
+        assert!(report.failed.is_empty());
+        let report = cooker.cook_dir(&assets).unwrap();
+        assert_eq!((0, 2), (report.cooked, report.fresh));
+
+        let format = Cooked::new(Counting(10), Arc::new(CookCache::new(&cache_dir)));
+        let before = IMPORTS.load(Ordering::SeqCst);
+        assert_eq!(vec![10, 20], format.import_simple(vec![1, 2]).unwrap());
+        assert_eq!(before, IMPORTS.load(Ordering::SeqCst));
+
+        // A changed file is imported again, and the result cached for the next cook.
+        assert_eq!(vec![40], format.import_simple(vec![4]).unwrap());
+        fs::write(assets.join("sub/b.NUM"), [4]).unwrap();
+        assert_eq!(
+            CookStatus::Fresh,
+            cooker.cook_file(assets.join("sub/b.NUM")).unwrap()
+        );
+
+        let _ = fs::remove_dir_all(&assets);
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+}
@@ -0,0 +1,330 @@
+//! Tracking of dependencies between assets.
+
+use std::{any::TypeId, cell::RefCell, sync::Arc};
+
+use fnv::{FnvHashMap, FnvHashSet};
+use parking_lot::Mutex;
+
+use crate::{Asset, Handle};
+
+thread_local! {
+    static SCOPE: RefCell<Vec<AssetId>> = RefCell::new(Vec::new());
+}
+
+/// Leaves the innermost dependency scope when dropped, even if loading panicked.
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        SCOPE.with(|scope| scope.borrow_mut().pop());
+    }
+}
+
+/// Identifies a single asset independent of its type.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct AssetId {
+    type_id: TypeId,
+    type_name: &'static str,
+    id: u32,
+}
+
+impl AssetId {
+    /// Returns the id of the asset `handle` points to.
+    pub fn of<A: Asset>(handle: &Handle<A>) -> Self {
+        Self::from_id::<A>(handle.id())
+    }
+
+    pub(crate) fn from_id<A: Asset>(id: u32) -> Self {
+        AssetId {
+            type_id: TypeId::of::<A>(),
+            type_name: A::NAME,
+            id,
+        }
+    }
+
+    /// Returns the handle id of the asset.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the `Asset::NAME` of the asset's type.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Returns `true` if the asset is of type `A`.
+    pub fn is<A: Asset>(&self) -> bool {
+        self.type_id == TypeId::of::<A>()
+    }
+}
+
+/// The dependency graph of all assets loaded through a `Loader`.
+///
+/// An asset depends on another one if it can't be used without it, e.g. a sprite sheet
+/// depends on its texture and a prefab on the sub assets it loads. Edges are recorded
+///
+/// * from `Format::dependencies` when an asset is loaded,
+/// * for all assets loaded while an asset is processed (e.g. prefab sub assets), and
+/// * for all assets loaded inside of `Dependencies::scope`.
+///
+/// When an asset is hot reloaded, its direct dependents are reloaded as well, which in
+/// turn reloads their dependents. When an asset fails to load, the failure is reported
+/// for all assets depending on it.
+///
+/// The graph can be obtained with `Loader::dependencies`.
+#[derive(Clone, Debug, Default)]
+pub struct Dependencies {
+    inner: Arc<Mutex<Graph>>,
+}
+
+#[derive(Debug, Default)]
+struct Graph {
+    dependencies: FnvHashMap<AssetId, FnvHashSet<AssetId>>,
+    dependents: FnvHashMap<AssetId, FnvHashSet<AssetId>>,
+    failed: FnvHashMap<AssetId, String>,
+    stale: FnvHashSet<AssetId>,
+}
+
+impl Dependencies {
+    /// Creates an empty dependency graph.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records that `dependent` depends on `dependency`.
+    pub fn add(&self, dependent: AssetId, dependency: AssetId) {
+        if dependent == dependency {
+            return;
+        }
+
+        let mut graph = self.inner.lock();
+        graph
+            .dependencies
+            .entry(dependent)
+            .or_default()
+            .insert(dependency);
+        graph
+            .dependents
+            .entry(dependency)
+            .or_default()
+            .insert(dependent);
+    }
+
+    /// Returns the assets `asset` directly depends on.
+    pub fn dependencies_of(&self, asset: AssetId) -> Vec<AssetId> {
+        Self::collect(&self.inner.lock().dependencies, asset)
+    }
+
+    /// Returns the assets directly depending on `asset`.
+    pub fn dependents_of(&self, asset: AssetId) -> Vec<AssetId> {
+        Self::collect(&self.inner.lock().dependents, asset)
+    }
+
+    /// Returns all assets depending on `asset`, directly or indirectly.
+    pub fn transitive_dependents_of(&self, asset: AssetId) -> Vec<AssetId> {
+        Self::transitive(&self.inner.lock().dependents, asset)
+    }
+
+    /// Returns all assets `asset` depends on, directly or indirectly, which failed
+    /// to load together with the error message of the failure.
+    pub fn failed_dependencies_of(&self, asset: AssetId) -> Vec<(AssetId, String)> {
+        let graph = self.inner.lock();
+        Self::transitive(&graph.dependencies, asset)
+            .into_iter()
+            .filter_map(|dependency| {
+                graph
+                    .failed
+                    .get(&dependency)
+                    .map(|error| (dependency, error.clone()))
+            })
+            .collect()
+    }
+
+    /// Runs `f`, recording all assets this thread loads in the meantime as
+    /// dependencies of `dependent`.
+    pub fn scope<F, R>(dependent: AssetId, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        SCOPE.with(|scope| scope.borrow_mut().push(dependent));
+        let _guard = ScopeGuard;
+        f()
+    }
+
+    /// The asset the current thread is loading dependencies for.
+    pub(crate) fn current_scope() -> Option<AssetId> {
+        SCOPE.with(|scope| scope.borrow().last().cloned())
+    }
+
+    /// Marks the direct dependents of `asset` for reloading.
+    pub(crate) fn reloaded(&self, asset: AssetId) {
+        let mut graph = self.inner.lock();
+        let dependents = Self::collect(&graph.dependents, asset);
+        graph.stale.extend(dependents);
+    }
+
+    /// Takes the ids of all assets of type `A` which need to be reloaded,
+    /// because one of their dependencies was reloaded.
+    pub(crate) fn take_stale<A: Asset>(&self) -> Vec<u32> {
+        let mut graph = self.inner.lock();
+        if graph.stale.is_empty() {
+            return Vec::new();
+        }
+
+        let stale: Vec<_> = graph
+            .stale
+            .iter()
+            .filter(|a| a.is::<A>())
+            .cloned()
+            .collect();
+        for asset in &stale {
+            graph.stale.remove(asset);
+        }
+        stale.into_iter().map(|asset| asset.id).collect()
+    }
+
+    /// Records that `asset` loaded successfully.
+    pub(crate) fn loaded(&self, asset: AssetId) {
+        self.inner.lock().failed.remove(&asset);
+    }
+
+    /// Records that `asset` failed to load, returning all assets depending on it.
+    pub(crate) fn failed(&self, asset: AssetId, error: String) -> Vec<AssetId> {
+        let mut graph = self.inner.lock();
+        graph.failed.insert(asset, error);
+        Self::transitive(&graph.dependents, asset)
+    }
+
+    /// Removes `asset` from the graph, e.g. because its handle id was freed.
+    pub(crate) fn remove(&self, asset: AssetId) {
+        let mut graph = self.inner.lock();
+        graph.failed.remove(&asset);
+        graph.stale.remove(&asset);
+        for dependency in graph.dependencies.remove(&asset).unwrap_or_default() {
+            if let Some(dependents) = graph.dependents.get_mut(&dependency) {
+                dependents.remove(&asset);
+            }
+        }
+        for dependent in graph.dependents.remove(&asset).unwrap_or_default() {
+            if let Some(dependencies) = graph.dependencies.get_mut(&dependent) {
+                dependencies.remove(&asset);
+            }
+        }
+    }
+
+    fn collect(edges: &FnvHashMap<AssetId, FnvHashSet<AssetId>>, asset: AssetId) -> Vec<AssetId> {
+        edges
+            .get(&asset)
+            .map(|set| set.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn transitive(
+        edges: &FnvHashMap<AssetId, FnvHashSet<AssetId>>,
+        asset: AssetId,
+    ) -> Vec<AssetId> {
+        let mut visited = FnvHashSet::default();
+        let mut result = Vec::new();
+        let mut stack = vec![asset];
+        while let Some(next) = stack.pop() {
+            for &edge in edges.get(&next).into_iter().flatten() {
+                if edge != asset && visited.insert(edge) {
+                    result.push(edge);
+                    stack.push(edge);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amethyst_core::ecs::VecStorage;
+
+    use crate::Asset;
+
+    use super::{AssetId, Dependencies};
+
+    struct Sheet;
+    impl Asset for Sheet {
+        const NAME: &'static str = "Sheet";
+        type Data = ();
+        type HandleStorage = VecStorage<crate::Handle<Self>>;
+    }
+
+    struct Image;
+    impl Asset for Image {
+        const NAME: &'static str = "Image";
+        type Data = ();
+        type HandleStorage = VecStorage<crate::Handle<Self>>;
+    }
+
+    #[test]
+    fn tracks_dependents_and_failures_transitively() {
+        let deps = Dependencies::new();
+        let image = AssetId::from_id::<Image>(0);
+        let sheet = AssetId::from_id::<Sheet>(0);
+        let other_sheet = AssetId::from_id::<Sheet>(1);
+        deps.add(sheet, image);
+        deps.add(other_sheet, sheet);
+
+        assert_eq!(vec![sheet], deps.dependents_of(image));
+        assert_eq!(vec![image], deps.dependencies_of(sheet));
+        assert_eq!(2, deps.transitive_dependents_of(image).len());
+
+        let affected = deps.failed(image, "broken".into());
+        assert_eq!(2, affected.len());
+        assert_eq!(
+            vec![(image, "broken".to_string())],
+            deps.failed_dependencies_of(other_sheet)
+        );
+
+        deps.remove(sheet);
+        assert!(deps.dependents_of(image).is_empty());
+        assert!(deps.failed_dependencies_of(other_sheet).is_empty());
+    }
+
+    #[test]
+    fn reloads_mark_direct_dependents_stale() {
+        let deps = Dependencies::new();
+        let image = AssetId::from_id::<Image>(3);
+        let sheet = AssetId::from_id::<Sheet>(7);
+        deps.add(sheet, image);
+
+        deps.reloaded(image);
+        assert!(deps.take_stale::<Image>().is_empty());
+        assert_eq!(vec![7], deps.take_stale::<Sheet>());
+        assert!(deps.take_stale::<Sheet>().is_empty());
+    }
+
+    #[test]
+    fn scope_is_per_thread_and_nested() {
+        let outer = AssetId::from_id::<Sheet>(1);
+        let inner = AssetId::from_id::<Sheet>(2);
+
+        assert_eq!(None, Dependencies::current_scope());
+        Dependencies::scope(outer, || {
+            assert_eq!(Some(outer), Dependencies::current_scope());
+            Dependencies::scope(inner, || {
+                assert_eq!(Some(inner), Dependencies::current_scope());
+            });
+            assert_eq!(Some(outer), Dependencies::current_scope());
+            std::thread::spawn(|| assert_eq!(None, Dependencies::current_scope()))
+                .join()
+                .unwrap();
+        });
+        assert_eq!(None, Dependencies::current_scope());
+    }
+
+    #[test]
+    fn scope_is_left_on_panic() {
+        let dependent = AssetId::from_id::<Sheet>(1);
+
+        let result = std::panic::catch_unwind(|| {
+            Dependencies::scope(dependent, || panic!("failed to load dependency"))
+        });
+        assert!(result.is_err());
+        assert_eq!(None, Dependencies::current_scope());
+    }
+}
@@ -1,6 +1,51 @@
-use crate::Format;
+//! Formats for loading any type implementing `Deserialize`, and converting between them.
+//!
+//! Besides the text formats `RonFormat` and `JsonFormat`, data can be stored in the compact
+//! binary formats `BincodeFormat` and `MsgPackFormat`. All of them implement `WritableFormat`,
+//! so assets can be converted between them with `convert`, e.g. in a build step turning RON
+//! prefabs into binary ones:
+//!
+//! ```rust,ignore
+//! let ron = std::fs::read("assets/prefab/level.ron")?;
+//! let bin = convert::<Prefab<MyPrefabData>, _, _>(ron, &RonFormat, &BincodeFormat)?;
+//! std::fs::write("assets/prefab/level.bin", bin)?;
+//! ```
+//!
+//! The formats are generic over the data they load, so to use them in a dynamically
+//! deserialized format (e.g. an `AssetPrefab`), register them for the data type:
+//!
+//! ```rust,ignore
+//! amethyst_assets::register_format!("Bincode", BincodeFormat as MyData);
+//! ```
+
+use std::{fmt, sync::Arc};
+
+use crate::{Format, FormatValue, Reload, SingleFile, Source, WritableSource};
+use amethyst_config::migration::Migrations;
 use amethyst_error::{format_err, Error, ResultExt};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// A format which can also write the data it loads, so assets can be converted
+/// between formats.
+pub trait WritableFormat<D: 'static>: Format<D> {
+    /// Produces bytes which `import_simple` turns back into `data`.
+    fn write(&self, data: &D) -> Result<Vec<u8>, Error>;
+}
+
+/// Converts `bytes` in the `from` format into the `to` format, by loading and
+/// writing the data as type `D`.
+pub fn convert<D, F, T>(bytes: Vec<u8>, from: &F, to: &T) -> Result<Vec<u8>, Error>
+where
+    D: 'static,
+    F: Format<D>,
+    T: WritableFormat<D>,
+{
+    let data = from
+        .import_simple(bytes)
+        .with_context(|_| format_err!("Failed to convert from {}", from.name()))?;
+    to.write(&data)
+        .with_context(|_| format_err!("Failed to convert to {}", to.name()))
+}
 
 /// Format for loading from RON files. Mostly useful for prefabs.
 /// This type cannot be used for tagged deserialization.
@@ -20,15 +65,132 @@ where
     }
 
     fn import_simple(&self, bytes: Vec<u8>) -> Result<D, Error> {
-        use ron::de::Deserializer;
-        let mut d = Deserializer::from_bytes(&bytes)
-            .with_context(|_| format_err!("Failed deserializing Ron file"))?;
-        let val =
-            D::deserialize(&mut d).with_context(|_| format_err!("Failed parsing Ron file"))?;
-        d.end()
-            .with_context(|_| format_err!("Failed parsing Ron file"))?;
+        from_ron(&bytes)
+    }
+}
 
-        Ok(val)
+/// Parses a whole RON file, like `RonFormat` does.
+pub(crate) fn from_ron<D>(bytes: &[u8]) -> Result<D, Error>
+where
+    D: for<'a> Deserialize<'a>,
+{
+    use ron::de::Deserializer;
+    let mut d = Deserializer::from_bytes(bytes)
+        .with_context(|_| format_err!("Failed deserializing Ron file"))?;
+    let val = D::deserialize(&mut d).with_context(|_| format_err!("Failed parsing Ron file"))?;
+    d.end()
+        .with_context(|_| format_err!("Failed parsing Ron file"))?;
+
+    Ok(val)
+}
+
+impl<D> WritableFormat<D> for RonFormat
+where
+    D: Serialize + for<'a> Deserialize<'a> + Send + Sync + 'static,
+{
+    fn write(&self, data: &D) -> Result<Vec<u8>, Error> {
+        ron::ser::to_string_pretty(data, Default::default())
+            .map(String::into_bytes)
+            .with_context(|_| format_err!("Failed serializing Ron file"))
+    }
+}
+
+/// Format for loading RON files of versioned types, upgrading files written for older
+/// versions with the `Migrations` of `amethyst_config`.
+///
+/// ```rust,ignore
+/// let migrations = Migrations::new(2)
+///     .with(0, rename_name_to_title)
+///     .with(1, merge_width_and_height);
+/// loader.load("window.ron", MigratedRonFormat::new(migrations), ());
+/// ```
+///
+/// Migrated files are written back to the source set with `with_rewrite_target`, under
+/// their asset name, so they are only migrated once.
+#[derive(Clone)]
+pub struct MigratedRonFormat {
+    migrations: Arc<Migrations>,
+    target: Option<Arc<dyn WritableSource>>,
+}
+
+impl MigratedRonFormat {
+    /// Creates a format upgrading files with the given migrations.
+    pub fn new(migrations: Migrations) -> Self {
+        MigratedRonFormat {
+            migrations: Arc::new(migrations),
+            target: None,
+        }
+    }
+
+    /// Sets the source migrated files are written back to.
+    ///
+    /// Without one, migrated files are left as they are.
+    pub fn with_rewrite_target<S>(mut self, target: S) -> Self
+    where
+        S: WritableSource,
+    {
+        self.target = Some(Arc::new(target));
+        self
+    }
+
+    fn migrate<D>(&self, name: Option<&str>, bytes: &[u8]) -> Result<D, Error>
+    where
+        D: Serialize + DeserializeOwned,
+    {
+        let migrated = self
+            .migrations
+            .load::<D>(bytes)
+            .with_context(|_| format_err!("Failed migrating Ron file"))?;
+        if let (Some(from), Some(name), Some(target)) = (migrated.from, name, &self.target) {
+            let s = self
+                .migrations
+                .write(&migrated.data)
+                .with_context(|_| format_err!("Failed serializing migrated Ron file"))?;
+            target.store(name, s.as_bytes()).with_context(|_| {
+                format_err!("Failed rewriting {} migrated from version {}", name, from)
+            })?;
+        }
+        Ok(migrated.data)
+    }
+}
+
+impl fmt::Debug for MigratedRonFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MigratedRonFormat")
+            .field("migrations", &self.migrations)
+            .field("rewrite_target", &self.target.is_some())
+            .finish()
+    }
+}
+
+impl<D> Format<D> for MigratedRonFormat
+where
+    D: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        "MigratedRon"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<D, Error> {
+        self.migrate(None, &bytes)
+    }
+
+    fn import(
+        &self,
+        name: String,
+        source: Arc<dyn Source>,
+        create_reload: Option<Box<dyn Format<D>>>,
+    ) -> Result<FormatValue<D>, Error> {
+        let generation = source.changes().map(|changes| changes.generation());
+        let (bytes, modified) = source
+            .load_with_metadata(&name)
+            .with_context(|_| crate::error::Error::Source)?;
+        let data = self.migrate(Some(&name), &bytes)?;
+        let reload = create_reload.map(|format| {
+            Box::new(SingleFile::new(format, modified, name, source).with_generation(generation))
+                as Box<dyn Reload<D>>
+        });
+        Ok(FormatValue { data, reload })
     }
 }
 
@@ -57,3 +219,222 @@ where
         Ok(val)
     }
 }
+
+#[cfg(feature = "json")]
+impl<D> WritableFormat<D> for JsonFormat
+where
+    D: Serialize + for<'a> Deserialize<'a> + Send + Sync + 'static,
+{
+    fn write(&self, data: &D) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec_pretty(data)
+            .with_context(|_| format_err!("Failed serializing Json file"))
+    }
+}
+
+/// Format for loading from bincode files, a compact binary encoding.
+///
+/// Bincode isn't self-describing, so the data has to be written with the exact same
+/// type it is loaded as; see `convert` for producing such files from RON.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BincodeFormat;
+
+impl<D> Format<D> for BincodeFormat
+where
+    D: DeserializeOwned + Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        "Bincode"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<D, Error> {
+        bincode::deserialize(&bytes).with_context(|_| format_err!("Failed parsing Bincode file"))
+    }
+}
+
+impl<D> WritableFormat<D> for BincodeFormat
+where
+    D: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn write(&self, data: &D) -> Result<Vec<u8>, Error> {
+        bincode::serialize(data).with_context(|_| format_err!("Failed serializing Bincode file"))
+    }
+}
+
+/// Format for loading from MessagePack files, a compact, self-describing binary encoding.
+///
+/// Structs are written as maps with field names, so just like RON, MessagePack files
+/// stay loadable when fields with a default are added.
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MsgPackFormat;
+
+#[cfg(feature = "msgpack")]
+impl<D> Format<D> for MsgPackFormat
+where
+    D: DeserializeOwned + Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        "MsgPack"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<D, Error> {
+        rmp_serde::from_read_ref(&bytes)
+            .with_context(|_| format_err!("Failed parsing MessagePack file"))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<D> WritableFormat<D> for MsgPackFormat
+where
+    D: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn write(&self, data: &D) -> Result<Vec<u8>, Error> {
+        rmp_serde::to_vec_named(data)
+            .with_context(|_| format_err!("Failed serializing MessagePack file"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use parking_lot::Mutex;
+    use serde::{Deserialize, Serialize};
+
+    use amethyst_config::migration::Migrations;
+    use amethyst_error::{format_err, Error};
+
+    use crate::{self as amethyst_assets, Format, SerializableFormat, Source, WritableSource};
+
+    use super::{convert, BincodeFormat, MigratedRonFormat, RonFormat, WritableFormat};
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Circle(f32),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Level {
+        name: String,
+        shapes: Vec<Shape>,
+        parent: Option<usize>,
+    }
+    amethyst_assets::register_format_type!(Level);
+    amethyst_assets::register_format!("Bincode", BincodeFormat as Level);
+
+    const LEVEL: &str = r#"(
+        name: "intro",
+        shapes: [Circle(1.5), Rect(w: 2, h: 3)],
+        parent: Some(0),
+    )"#;
+
+    fn level() -> Level {
+        Level {
+            name: "intro".into(),
+            shapes: vec![Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }],
+            parent: Some(0),
+        }
+    }
+
+    #[test]
+    fn converts_ron_to_bincode_and_back() {
+        let bin =
+            convert::<Level, _, _>(LEVEL.as_bytes().to_vec(), &RonFormat, &BincodeFormat).unwrap();
+        assert_eq!(
+            level(),
+            Format::<Level>::import_simple(&BincodeFormat, bin.clone()).unwrap()
+        );
+
+        let ron = convert::<Level, _, _>(bin, &BincodeFormat, &RonFormat).unwrap();
+        assert_eq!(
+            level(),
+            Format::<Level>::import_simple(&RonFormat, ron).unwrap()
+        );
+    }
+
+    #[test]
+    fn binary_format_is_registered_for_dynamic_loading() {
+        let format: Box<dyn SerializableFormat<Level>> =
+            ron::de::from_str("(\"Bincode\", ())").expect("Bincode format should be registered");
+        let bytes = WritableFormat::<Level>::write(&BincodeFormat, &level()).unwrap();
+        assert_eq!(level(), format.import_simple(bytes).unwrap());
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn converts_ron_to_msgpack_and_back() {
+        use super::MsgPackFormat;
+
+        let msgpack =
+            convert::<Level, _, _>(LEVEL.as_bytes().to_vec(), &RonFormat, &MsgPackFormat).unwrap();
+        let ron = convert::<Level, _, _>(msgpack, &MsgPackFormat, &RonFormat).unwrap();
+        assert_eq!(
+            level(),
+            Format::<Level>::import_simple(&RonFormat, ron).unwrap()
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct Files(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+    impl Source for Files {
+        fn modified(&self, _: &str) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            self.0
+                .lock()
+                .get(path)
+                .cloned()
+                .ok_or_else(|| format_err!("{} not found", path))
+        }
+    }
+
+    impl WritableSource for Files {
+        fn store(&self, path: &str, bytes: &[u8]) -> Result<(), Error> {
+            self.0.lock().insert(path.to_string(), bytes.to_vec());
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Window {
+        version: u32,
+        title: String,
+        shapes: Vec<Shape>,
+    }
+
+    #[test]
+    fn migrates_and_rewrites_old_files() {
+        let files = Files::default();
+        files
+            .store(
+                "window.ron",
+                b"(name: \"intro\", shapes: [Rect(w: 1, h: 2)])",
+            )
+            .unwrap();
+        let migrations = Migrations::new(1).with(0, |value| {
+            let name = value.remove_field("name").unwrap();
+            value.insert_field("title", name);
+            Ok(())
+        });
+        let format = MigratedRonFormat::new(migrations).with_rewrite_target(files.clone());
+
+        let window =
+            Format::<Window>::import(&format, "window.ron".into(), Arc::new(files.clone()), None)
+                .unwrap()
+                .data;
+        let expected = Window {
+            version: 1,
+            title: "intro".into(),
+            shapes: vec![Shape::Rect { w: 1, h: 2 }],
+        };
+        assert_eq!(expected, window);
+        assert_eq!(
+            expected,
+            Format::<Window>::import_simple(&RonFormat, files.load("window.ron").unwrap()).unwrap()
+        );
+    }
+}
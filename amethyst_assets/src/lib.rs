@@ -11,36 +11,66 @@
 
 #[cfg(feature = "json")]
 pub use crate::formats::JsonFormat;
+#[cfg(feature = "msgpack")]
+pub use crate::formats::MsgPackFormat;
+#[cfg(feature = "saveload")]
+pub use crate::saveload::{Persistent, SaveGames, SaveLoadBundle, SaveMarker, SaveMarkerAllocator};
+#[cfg(feature = "http")]
+pub use crate::source::Http;
 pub use crate::{
     asset::{Asset, Format, FormatValue, ProcessableAsset, SerializableFormat},
     cache::Cache,
+    cook::{CookCache, CookKey, CookReport, CookStatus, Cooked, Cooker},
+    dependency::{AssetId, Dependencies},
     dyn_format::FormatRegisteredData,
-    formats::RonFormat,
+    formats::{convert, BincodeFormat, MigratedRonFormat, RonFormat, WritableFormat},
     helper::AssetLoaderSystemData,
-    loader::Loader,
+    loader::{Deduplication, Loader},
+    manifest::{Manifest, ManifestBundle, ManifestEntry},
     prefab::{
-        AssetPrefab, Prefab, PrefabData, PrefabLoader, PrefabLoaderSystem, PrefabLoaderSystemDesc,
+        AssetPrefab, InheritedPrefab, InheritedPrefabFormat, Prefab, PrefabData, PrefabExtract,
+        PrefabExtractor, PrefabLoader, PrefabLoaderSystem, PrefabLoaderSystemDesc, PrefabMerge,
     },
-    progress::{Completion, Progress, ProgressCounter, Tracker},
+    progress::{
+        AssetLoadStats, Completion, LoadReport, LoadStats, Progress, ProgressCounter, Tracker,
+    },
+    queue::LoadPriority,
     reload::{HotReloadBundle, HotReloadStrategy, HotReloadSystem, Reload, SingleFile},
-    source::{Directory, Source},
-    storage::{AssetStorage, Handle, ProcessingState, Processor, WeakHandle},
+    saveload::SaveLoadEvent,
+    source::{
+        Archive, ArchiveReader, Archives, Directory, Embedded, EmbeddedFiles, Overlay, PakBuilder,
+        Source, SourceChanges, WritableSource,
+    },
+    storage::{
+        AssetStats, AssetStorage, Handle, MemoryBudget, ProcessingState, Processor, StorageStats,
+        WeakHandle,
+    },
+    validate::{
+        deserialize_asset_format, deserialize_asset_path, ValidationReport, ValidationStatus,
+        Validator,
+    },
 };
 
 pub use rayon::ThreadPool;
 
 mod asset;
 mod cache;
+mod cook;
+mod dependency;
 mod dyn_format;
 mod error;
 mod formats;
 mod helper;
 mod loader;
+mod manifest;
 mod prefab;
 mod progress;
+mod queue;
 mod reload;
+mod saveload;
 mod source;
 mod storage;
+mod validate;
 
 // used in macros. Private API otherwise.
 #[doc(hidden)]
@@ -1,23 +1,72 @@
-use std::{borrow::Borrow, hash::Hash, path::PathBuf, sync::Arc};
+use std::{
+    any::TypeId,
+    borrow::Borrow,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
+};
 
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHasher};
 use log::debug;
+use parking_lot::Mutex;
 use rayon::ThreadPool;
+use serde::{de::DeserializeOwned, Serialize};
 
 use amethyst_error::ResultExt;
 #[cfg(feature = "profiler")]
 use thread_profiler::profile_scope;
 
 use crate::{
+    cook::{CookCache, Cooked},
     error::Error,
-    storage::{AssetStorage, Handle, Processed},
-    Asset, Directory, Format, FormatValue, Progress, Source,
+    progress::{LoadStats, Tracker},
+    queue::{LoadPriority, LoadQueue},
+    storage::{AssetStorage, Handle, Processed, WeakHandle},
+    Asset, AssetId, Dependencies, Directory, Format, FormatValue, Progress, Source, SourceChanges,
 };
 
+/// How the `Loader` detects that an asset has already been requested, in which case
+/// it returns the existing `Handle` instead of loading the asset again.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Deduplication {
+    /// Every request loads the asset. This is the default.
+    Off,
+    /// Requests with the same source, format (including its options) and path are
+    /// deduplicated. Paths are compared canonicalized, so `"textures/../a.png"` and
+    /// `"./a.png"` refer to the same asset, but loaded as requested.
+    Path,
+    /// Requests with the same format and identical file contents are deduplicated,
+    /// regardless of where the files are.
+    ///
+    /// Each file is read on the thread requesting it in order to hash it, which blocks
+    /// that thread, even if the request turns out to be a duplicate. Requests to remote
+    /// sources (see `Source::is_remote`) are deduplicated by path instead.
+    Content,
+}
+
+impl Default for Deduplication {
+    fn default() -> Self {
+        Deduplication::Off
+    }
+}
+
 /// The asset loader, holding the sources and a reference to the `ThreadPool`.
+///
+/// Loads are queued by `LoadPriority` and started by the worker threads highest priority
+/// first. A load which hasn't completed yet is cancelled once all handles to its asset
+/// are dropped; progress trackers are notified with `Tracker::cancel` in that case.
 pub struct Loader {
+    cook_cache: Option<Arc<CookCache>>,
+    deduplicated: Deduplicated,
+    deduplication: Deduplication,
+    dependencies: Dependencies,
     hot_reload: bool,
     pool: Arc<ThreadPool>,
+    queue: LoadQueue,
     sources: FnvHashMap<String, Arc<dyn Source>>,
 }
 
@@ -37,8 +86,13 @@ impl Loader {
         S: Source,
     {
         let mut loader = Loader {
+            cook_cache: None,
+            deduplicated: Default::default(),
+            deduplication: Deduplication::Off,
+            dependencies: Dependencies::new(),
             hot_reload: true,
             pool,
+            queue: LoadQueue::default(),
             sources: Default::default(),
         };
 
@@ -73,6 +127,54 @@ impl Loader {
         self.hot_reload = value;
     }
 
+    /// Sets the cache `load_cooked` reads cooked assets from. Without a cache,
+    /// `load_cooked` behaves just like `load`.
+    pub fn set_cook_cache(&mut self, cache: Option<CookCache>) {
+        self.cook_cache = cache.map(Arc::new);
+    }
+
+    /// Sets how requests for assets which are already loaded or loading are detected.
+    ///
+    /// Deduplicated requests return the existing `Handle` and their progress completes
+    /// together with the original load.
+    pub fn set_deduplication(&mut self, deduplication: Deduplication) {
+        self.deduplication = deduplication;
+    }
+
+    /// Returns how requests for the same asset are deduplicated.
+    pub fn deduplication(&self) -> Deduplication {
+        self.deduplication
+    }
+
+    /// Returns the dependency graph of all assets loaded through this `Loader`.
+    pub fn dependencies(&self) -> &Dependencies {
+        &self.dependencies
+    }
+
+    /// Changes the priority of a load which hasn't been started by a worker thread yet.
+    ///
+    /// Returns `false` if there is no such load, e.g. because it has already started.
+    pub fn set_priority<A>(&self, handle: &Handle<A>, priority: LoadPriority) -> bool
+    where
+        A: Asset,
+    {
+        self.queue.set_priority(AssetId::of(handle), priority)
+    }
+
+    /// Returns the priority of the load of `handle`'s asset, if it is still waiting
+    /// for a worker thread.
+    pub fn priority<A>(&self, handle: &Handle<A>) -> Option<LoadPriority>
+    where
+        A: Asset,
+    {
+        self.queue.priority(AssetId::of(handle))
+    }
+
+    /// Returns the number of loads still waiting for a worker thread.
+    pub fn num_queued(&self) -> usize {
+        self.queue.len()
+    }
+
     /// Loads an asset with a given format from the default (directory) source.
     /// If you want to load from a custom source instead, use `load_from`.
     ///
@@ -95,6 +197,52 @@ impl Loader {
         self.load_from::<A, F, _, _, _>(name, format, "", progress, storage)
     }
 
+    /// Loads an asset with a given format from the default source, using the cooked data
+    /// in the cache set with `set_cook_cache` if it is up to date.
+    ///
+    /// See `Cooker` for how to fill the cache ahead of time.
+    pub fn load_cooked<A, F, N, P>(
+        &self,
+        name: N,
+        format: F,
+        progress: P,
+        storage: &AssetStorage<A>,
+    ) -> Handle<A>
+    where
+        A: Asset,
+        A::Data: Serialize + DeserializeOwned,
+        F: Format<A::Data> + Clone + Serialize,
+        N: Into<String>,
+        P: Progress,
+    {
+        match self.cook_cache {
+            Some(ref cache) => {
+                self.load(name, Cooked::new(format, cache.clone()), progress, storage)
+            }
+            None => self.load(name, format, progress, storage),
+        }
+    }
+
+    /// Loads an asset with a given format and priority from the default (directory) source.
+    ///
+    /// See `load_from_with_priority` for more information.
+    pub fn load_with_priority<A, F, N, P>(
+        &self,
+        name: N,
+        format: F,
+        priority: LoadPriority,
+        progress: P,
+        storage: &AssetStorage<A>,
+    ) -> Handle<A>
+    where
+        A: Asset,
+        F: Format<A::Data>,
+        N: Into<String>,
+        P: Progress,
+    {
+        self.load_from_with_priority::<A, F, _, _, _>(name, format, "", priority, progress, storage)
+    }
+
     /// Loads an asset with a given id and format from a custom source.
     /// The actual work is done in a worker thread, thus this method immediately returns a handle.
     ///
@@ -113,6 +261,38 @@ impl Loader {
         name: N,
         format: F,
         source: &S,
+        progress: P,
+        storage: &AssetStorage<A>,
+    ) -> Handle<A>
+    where
+        A: Asset,
+        F: Format<A::Data>,
+        N: Into<String>,
+        P: Progress,
+        S: AsRef<str> + Eq + Hash + ?Sized,
+        String: Borrow<S>,
+    {
+        self.load_from_with_priority::<A, F, _, _, _>(
+            name,
+            format,
+            source,
+            LoadPriority::NORMAL,
+            progress,
+            storage,
+        )
+    }
+
+    /// Loads an asset with a given id, format and priority from a custom source.
+    ///
+    /// Works like `load_from`, except that the load is started before all pending loads
+    /// with a lower priority. The priority can be changed with `set_priority` as long
+    /// as the load hasn't been started.
+    pub fn load_from_with_priority<A, F, N, P, S>(
+        &self,
+        name: N,
+        format: F,
+        source: &S,
+        priority: LoadPriority,
         mut progress: P,
         storage: &AssetStorage<A>,
     ) -> Handle<A>
@@ -126,34 +306,93 @@ impl Loader {
     {
         #[cfg(feature = "profiler")]
         profile_scope!("load_asset_from");
-        use crate::progress::Tracker;
 
         let name = name.into();
-        let source = source.as_ref();
+        let source_id = source.as_ref();
+        let mut source = self.source(source_id);
 
         let format_name = format.name();
-        let source_name = match source {
+        let source_name = match source_id {
             "" => "[default source]",
             other => other,
         };
 
+        let key = match self.deduplication {
+            Deduplication::Off => None,
+            Deduplication::Path => Some(format!(
+                "{}\0{}\0{:?}",
+                source_id,
+                canonical_path(&name),
+                format
+            )),
+            Deduplication::Content if source.is_remote() => Some(format!(
+                "{}\0{}\0{:?}",
+                source_id,
+                canonical_path(&name),
+                format
+            )),
+            Deduplication::Content => {
+                // If the file can't be read, the load reports the error as usual.
+                source.load(&name).ok().map(|bytes| {
+                    let mut hasher = FnvHasher::default();
+                    hasher.write(&bytes);
+                    let key = format!("{:016x}\0{}\0{:?}", hasher.finish(), bytes.len(), format);
+                    source = Arc::new(Preloaded {
+                        name: name.clone(),
+                        bytes: Mutex::new(Some(bytes)),
+                        source: source.clone(),
+                    });
+                    key
+                })
+            }
+        };
+
+        // Held until the new handle is registered, so concurrent requests can't both miss.
+        let mut deduplicated = self.deduplicated.0.lock();
+        let key = key.map(|key| (TypeId::of::<A>(), key));
+        let existing = key
+            .as_ref()
+            .and_then(|key| deduplicated.handles.get(key))
+            .and_then(|(_, id)| WeakHandle::<A>::from_raw(id.clone()).upgrade())
+            .filter(|handle| !storage.failed_id(handle.id()));
+        if let Some(handle) = existing {
+            debug!(
+                "{:?}: Asset {:?} from source {:?} has already been requested (handle id: {:?})",
+                A::NAME,
+                name,
+                source_name,
+                handle,
+            );
+            progress.add_assets(1);
+            storage.processed.push(Processed::Waiting {
+                id: handle.id(),
+                name,
+                tracker: Box::new(progress.create_tracker()),
+            });
+            return handle;
+        }
+
         let handle = storage.allocate();
+        if let Some(key) = key {
+            storage.attach_deduplicated(&self.deduplicated);
+            deduplicated.insert(key, AssetId::of(&handle), handle.downgrade().into_raw());
+        }
+        drop(deduplicated);
 
         debug!(
-            "{:?}: Loading asset {:?} with format {:?} from source {:?} (handle id: {:?})",
+            "{:?}: Loading asset {:?} with format {:?} from source {:?} (handle id: {:?}, priority: {:?})",
             A::NAME,
             name,
             format_name,
             source_name,
             handle,
+            priority,
         );
 
-        progress.add_assets(1);
-        let tracker = progress.create_tracker();
+        self.track_dependencies(&handle, format.dependencies(), storage);
 
-        let source = self.source(source);
-        let handle_clone = handle.clone();
-        let processed = storage.processed.clone();
+        progress.add_assets(1);
+        let tracker = Box::new(progress.create_tracker()) as Box<dyn Tracker>;
 
         let hot_reload = if self.hot_reload {
             Some(objekt::clone_box(&format) as Box<dyn Format<A::Data>>)
@@ -161,24 +400,26 @@ impl Loader {
             None
         };
 
-        let cl = move || {
-            #[cfg(feature = "profiler")]
-            profile_scope!("load_asset_from_worker");
-            let data = format
-                .import(name.clone(), source, hot_reload)
-                .with_context(|_| Error::Format(format_name));
-            let tracker = Box::new(tracker) as Box<dyn Tracker>;
-
-            processed.push(Processed::NewAsset {
-                data,
-                handle,
-                name,
-                tracker,
-            });
-        };
-        self.pool.spawn(cl);
+        self.spawn(
+            &handle,
+            priority,
+            name,
+            tracker,
+            storage,
+            move |name, stats| {
+                #[cfg(feature = "profiler")]
+                profile_scope!("load_asset_from_worker");
+                let source = Arc::new(Metered::new(source));
+                let started = Instant::now();
+                let data = format
+                    .import(name, source.clone(), hot_reload)
+                    .with_context(|_| Error::Format(format_name));
+                source.finish(started.elapsed(), stats);
+                data
+            },
+        );
 
-        handle_clone
+        handle
     }
 
     /// Load an asset from data and return a handle.
@@ -196,10 +437,12 @@ impl Loader {
         let tracker = progress.create_tracker();
         let tracker = Box::new(tracker);
         let handle = storage.allocate();
+        self.track_dependencies(&handle, Vec::new(), storage);
         storage.processed.push(Processed::NewAsset {
             data: Ok(FormatValue::data(data)),
             handle: handle.clone(),
             name: "<Data>".into(),
+            stats: LoadStats::default(),
             tracker,
         });
 
@@ -219,26 +462,101 @@ impl Loader {
         F: FnOnce() -> A::Data + Send + Sync + 'static,
     {
         progress.add_assets(1);
-        let tracker = progress.create_tracker();
-        let tracker = Box::new(tracker);
+        let tracker = Box::new(progress.create_tracker());
         let handle = storage.allocate();
-        let processed = storage.processed.clone();
+        self.track_dependencies(&handle, Vec::new(), storage);
 
-        self.pool.spawn({
-            let handle = handle.clone();
-            move || {
-                processed.push(Processed::NewAsset {
-                    data: Ok(FormatValue::data(data())),
-                    handle: handle.clone(),
-                    name: "<Data>".into(),
-                    tracker,
-                });
-            }
-        });
+        self.spawn(
+            &handle,
+            LoadPriority::NORMAL,
+            "<Data>".into(),
+            tracker,
+            storage,
+            move |_, stats| {
+                let started = Instant::now();
+                let data = data();
+                stats.import = started.elapsed();
+                Ok(FormatValue::data(data))
+            },
+        );
 
         handle
     }
 
+    /// Queues `import` for a worker thread. The queued load only keeps a weak reference
+    /// to `handle`, so it is cancelled if all handles are dropped before it starts.
+    fn spawn<A, I>(
+        &self,
+        handle: &Handle<A>,
+        priority: LoadPriority,
+        name: String,
+        tracker: Box<dyn Tracker>,
+        storage: &AssetStorage<A>,
+        import: I,
+    ) where
+        A: Asset,
+        I: FnOnce(String, &mut LoadStats) -> Result<FormatValue<A::Data>, amethyst_error::Error>
+            + Send
+            + 'static,
+    {
+        let id = handle.id();
+        let weak = handle.downgrade();
+        let processed = storage.processed.clone();
+        let requested = Instant::now();
+
+        self.queue.spawn(
+            &self.pool,
+            AssetId::of(handle),
+            priority,
+            move || match weak.upgrade() {
+                Some(handle) => {
+                    let mut stats = LoadStats {
+                        queued: requested.elapsed(),
+                        ..Default::default()
+                    };
+                    let data = import(name.clone(), &mut stats);
+                    processed.push(Processed::NewAsset {
+                        data,
+                        handle,
+                        name,
+                        stats,
+                        tracker,
+                    });
+                }
+                None => processed.push(Processed::Cancelled { id, name, tracker }),
+            },
+        );
+    }
+
+    fn track_dependencies<A>(
+        &self,
+        handle: &Handle<A>,
+        dependencies: Vec<AssetId>,
+        storage: &AssetStorage<A>,
+    ) where
+        A: Asset,
+    {
+        storage.attach_dependencies(&self.dependencies);
+
+        let asset = AssetId::of(handle);
+        for dependency in dependencies {
+            self.dependencies.add(asset, dependency);
+        }
+        if let Some(dependent) = Dependencies::current_scope() {
+            self.dependencies.add(dependent, asset);
+        }
+    }
+
+    /// Sum of the change generations of all sources pushing change notifications.
+    /// Increases whenever any of those sources reports a change.
+    pub(crate) fn changes_generation(&self) -> u64 {
+        self.sources
+            .values()
+            .filter_map(|source| source.changes())
+            .map(|changes| changes.generation())
+            .sum()
+    }
+
     fn source(&self, source: &str) -> Arc<dyn Source> {
         self.sources
             .get(source)
@@ -246,3 +564,348 @@ impl Loader {
             .clone()
     }
 }
+
+/// Normalizes separators and resolves `.` and `..` components, so paths naming the same
+/// file compare equal. Only used to compare paths, the sources get the requested ones.
+///
+/// Absolute paths keep their leading `/`, `\` is only a separator on Windows.
+fn canonical_path(path: &str) -> String {
+    let separators: &[char] = if cfg!(windows) { &['/', '\\'] } else { &['/'] };
+    let root = if path.starts_with(separators) {
+        "/"
+    } else {
+        ""
+    };
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split(separators) {
+        match component {
+            "" | "." => {}
+            ".." if components.last().map(|c| *c != "..").unwrap_or(false) => {
+                components.pop();
+            }
+            other => components.push(other),
+        }
+    }
+    format!("{}{}", root, components.join("/"))
+}
+
+/// Measures the bytes read from a source and the time spent reading them.
+struct Metered {
+    bytes: AtomicU64,
+    nanos: AtomicU64,
+    source: Arc<dyn Source>,
+}
+
+impl Metered {
+    fn new(source: Arc<dyn Source>) -> Self {
+        Metered {
+            bytes: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+            source,
+        }
+    }
+
+    fn measure<T, F>(&self, f: F) -> Result<T, amethyst_error::Error>
+    where
+        F: FnOnce(&dyn Source) -> Result<T, amethyst_error::Error>,
+    {
+        let started = Instant::now();
+        let result = f(&*self.source);
+        self.nanos
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+
+    fn count(&self, bytes: &[u8]) {
+        self.bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+    }
+
+    /// Splits the `elapsed` time of an import into source and format time.
+    fn finish(&self, elapsed: Duration, stats: &mut LoadStats) {
+        stats.bytes = self.bytes.load(Ordering::Relaxed);
+        stats.source = Duration::from_nanos(self.nanos.load(Ordering::Relaxed));
+        stats.import = elapsed.checked_sub(stats.source).unwrap_or_default();
+    }
+}
+
+impl Source for Metered {
+    fn modified(&self, path: &str) -> Result<u64, amethyst_error::Error> {
+        self.measure(|source| source.modified(path))
+    }
+
+    fn load(&self, path: &str) -> Result<Vec<u8>, amethyst_error::Error> {
+        let bytes = self.measure(|source| source.load(path))?;
+        self.count(&bytes);
+        Ok(bytes)
+    }
+
+    fn load_with_metadata(&self, path: &str) -> Result<(Vec<u8>, u64), amethyst_error::Error> {
+        let (bytes, modified) = self.measure(|source| source.load_with_metadata(path))?;
+        self.count(&bytes);
+        Ok((bytes, modified))
+    }
+
+    fn changes(&self) -> Option<SourceChanges> {
+        self.source.changes()
+    }
+
+    fn is_remote(&self) -> bool {
+        self.source.is_remote()
+    }
+}
+
+/// Handles of deduplicated loads by their keys, shared with the storages, which remove the
+/// entries of assets once their handle ids are freed.
+#[derive(Clone, Default)]
+pub(crate) struct Deduplicated(Arc<Mutex<DeduplicatedLoads>>);
+
+#[derive(Default)]
+struct DeduplicatedLoads {
+    handles: FnvHashMap<(TypeId, String), (AssetId, Weak<u32>)>,
+    keys: FnvHashMap<AssetId, (TypeId, String)>,
+}
+
+impl DeduplicatedLoads {
+    fn insert(&mut self, key: (TypeId, String), asset: AssetId, handle: Weak<u32>) {
+        if let Some((replaced, _)) = self.handles.insert(key.clone(), (asset, handle)) {
+            self.keys.remove(&replaced);
+        }
+        self.keys.insert(asset, key);
+    }
+}
+
+impl Deduplicated {
+    /// Removes the entry of `asset`, whose handle id is about to be reused.
+    pub(crate) fn forget(&self, asset: AssetId) {
+        let mut loads = self.0.lock();
+        if let Some(key) = loads.keys.remove(&asset) {
+            loads.handles.remove(&key);
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.0.lock().handles.len()
+    }
+}
+
+/// Serves the bytes read for hashing to the first load of an asset, so `Deduplication::Content`
+/// doesn't read every file twice. Later loads, e.g. for hot reloading, go to the actual source.
+struct Preloaded {
+    name: String,
+    bytes: Mutex<Option<Vec<u8>>>,
+    source: Arc<dyn Source>,
+}
+
+impl Preloaded {
+    fn take(&self, path: &str) -> Option<Vec<u8>> {
+        if path == self.name {
+            self.bytes.lock().take()
+        } else {
+            None
+        }
+    }
+}
+
+impl Source for Preloaded {
+    fn modified(&self, path: &str) -> Result<u64, amethyst_error::Error> {
+        self.source.modified(path)
+    }
+
+    fn load(&self, path: &str) -> Result<Vec<u8>, amethyst_error::Error> {
+        match self.take(path) {
+            Some(bytes) => Ok(bytes),
+            None => self.source.load(path),
+        }
+    }
+
+    fn load_with_metadata(&self, path: &str) -> Result<(Vec<u8>, u64), amethyst_error::Error> {
+        match self.take(path) {
+            Some(bytes) => Ok((bytes, self.source.modified(path)?)),
+            None => self.source.load_with_metadata(path),
+        }
+    }
+
+    fn changes(&self) -> Option<SourceChanges> {
+        self.source.changes()
+    }
+
+    fn is_remote(&self) -> bool {
+        self.source.is_remote()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::Arc,
+        thread::{self, ThreadId},
+        time::Duration,
+    };
+
+    use parking_lot::Mutex;
+    use rayon::ThreadPoolBuilder;
+
+    use amethyst_core::ecs::VecStorage;
+    use amethyst_error::{format_err, Error};
+
+    use crate::{Asset, AssetStorage, Handle, ProcessingState, ProgressCounter, RonFormat, Source};
+
+    use super::{canonical_path, Deduplication, Loader};
+
+    struct Text(String);
+
+    impl Asset for Text {
+        const NAME: &'static str = "Text";
+        type Data = String;
+        type HandleStorage = VecStorage<Handle<Self>>;
+    }
+
+    struct Files;
+
+    impl Source for Files {
+        fn modified(&self, _: &str) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            match canonical_path(path).as_str() {
+                "a.ron" | "copy.ron" => Ok(b"\"shared\"".to_vec()),
+                "b.ron" => Ok(b"\"other\"".to_vec()),
+                _ => Err(format_err!("{} not found", path)),
+            }
+        }
+    }
+
+    #[test]
+    fn canonicalizes_paths() {
+        assert_eq!("a/b.png", canonical_path("./a//b.png"));
+        assert_eq!("b.png", canonical_path("a/../b.png"));
+        assert_eq!("../b.png", canonical_path("../b.png"));
+        assert_eq!("/abs/b.png", canonical_path("/abs/./b.png"));
+        assert_ne!(canonical_path("/abs/b.png"), canonical_path("abs/b.png"));
+        if cfg!(windows) {
+            assert_eq!("a/b.png", canonical_path("a\\c\\..\\b.png"));
+        } else {
+            assert_eq!("a\\c\\..\\b.png", canonical_path("a\\c\\..\\b.png"));
+        }
+    }
+
+    #[test]
+    fn deduplicates_requests_for_the_same_asset() {
+        let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+        let mut loader = Loader::with_default_source(Files, pool.into());
+        let mut storage = AssetStorage::<Text>::new();
+        let mut progress = ProgressCounter::new();
+
+        loader.set_deduplication(Deduplication::Path);
+        let a = loader.load("dir/../a.ron", RonFormat, &mut progress, &storage);
+        assert_eq!(
+            a,
+            loader.load("./a.ron", RonFormat, &mut progress, &storage)
+        );
+        let copy = loader.load("copy.ron", RonFormat, &mut progress, &storage);
+        assert_ne!(a, copy);
+
+        loader.set_deduplication(Deduplication::Content);
+        let content = loader.load("a.ron", RonFormat, &mut progress, &storage);
+        assert_eq!(
+            content,
+            loader.load("copy.ron", RonFormat, &mut progress, &storage)
+        );
+        let b = loader.load("b.ron", RonFormat, &mut progress, &storage);
+        assert_ne!(content, b);
+
+        for frame in 0..100 {
+            if progress.num_loading() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+            let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+            storage.process(|s| Ok(ProcessingState::Loaded(Text(s))), frame, &pool, None);
+        }
+
+        assert_eq!(6, progress.num_assets());
+        assert_eq!(6, progress.num_finished());
+        // Deduplicated requests don't read anything.
+        assert_eq!(31, progress.bytes_loaded());
+        assert_eq!("shared", storage.get(&a).unwrap().0);
+        assert_eq!("shared", storage.get(&copy).unwrap().0);
+        assert_eq!("shared", storage.get(&content).unwrap().0);
+        assert_eq!("other", storage.get(&b).unwrap().0);
+    }
+
+    fn process(storage: &mut AssetStorage<Text>, progress: &ProgressCounter) {
+        for frame in 0..100 {
+            let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+            storage.process(|s| Ok(ProcessingState::Loaded(Text(s))), frame, &pool, None);
+            if progress.num_loading() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn forgets_deduplicated_loads_with_their_handles() {
+        let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+        let mut loader = Loader::with_default_source(Files, pool.into());
+        let mut storage = AssetStorage::<Text>::new();
+        let mut progress = ProgressCounter::new();
+
+        loader.set_deduplication(Deduplication::Path);
+        let a = loader.load("a.ron", RonFormat, &mut progress, &storage);
+        let b = loader.load("b.ron", RonFormat, &mut progress, &storage);
+        process(&mut storage, &progress);
+        assert_eq!(2, loader.deduplicated.len());
+
+        drop(a);
+        process(&mut storage, &progress);
+        assert_eq!(1, loader.deduplicated.len());
+        let a = loader.load("a.ron", RonFormat, &mut progress, &storage);
+        assert_ne!(a, b);
+        assert_eq!(2, loader.deduplicated.len());
+    }
+
+    /// Remote source recording the threads loading from it.
+    struct Remote(Arc<Mutex<Vec<ThreadId>>>);
+
+    impl Source for Remote {
+        fn modified(&self, path: &str) -> Result<u64, Error> {
+            Files.modified(path)
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            self.0.lock().push(thread::current().id());
+            Files.load(path)
+        }
+
+        fn is_remote(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn deduplicates_remote_content_by_path() {
+        let threads = Arc::new(Mutex::new(Vec::new()));
+        let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+        let mut loader = Loader::with_default_source(Remote(threads.clone()), pool.into());
+        let mut storage = AssetStorage::<Text>::new();
+        let mut progress = ProgressCounter::new();
+
+        loader.set_deduplication(Deduplication::Content);
+        let a = loader.load("a.ron", RonFormat, &mut progress, &storage);
+        assert_eq!(
+            a,
+            loader.load("./a.ron", RonFormat, &mut progress, &storage)
+        );
+        let copy = loader.load("copy.ron", RonFormat, &mut progress, &storage);
+        assert_ne!(a, copy);
+        process(&mut storage, &progress);
+
+        assert_eq!("shared", storage.get(&a).unwrap().0);
+        let threads = threads.lock();
+        assert_eq!(2, threads.len());
+        assert!(!threads.contains(&thread::current().id()));
+    }
+}
@@ -0,0 +1,411 @@
+//! Manifests loading groups of assets, e.g. the assets of a level or DLC.
+
+use std::{
+    any::{Any, TypeId},
+    fmt,
+};
+
+use fnv::FnvHashMap;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+use amethyst_core::{
+    ecs::{
+        prelude::{DispatcherBuilder, RunNow, World, WorldExt},
+        storage::VecStorage,
+    },
+    ArcThreadPool, SystemBundle, Time,
+};
+use amethyst_error::{format_err, Error};
+
+use crate::{
+    Asset, AssetStorage, Completion, Format, Handle, HotReloadStrategy, Loader, ProcessingState,
+    ProgressCounter,
+};
+
+/// Asset loading a group of assets listed in a RON file:
+///
+/// ```ron
+/// (
+///     assets: [
+///         (path: "texture/logo.png", format: "png"),
+///         (path: "audio/theme.ogg", format: "ogg", source: Some("dlc")),
+///     ],
+/// )
+/// ```
+///
+/// Each entry names a format registered with `ManifestBundle::with_format`, which decides
+/// the type the asset is loaded as, and optionally the source to load it from.
+///
+/// The manifest is loaded once all of its assets are loaded, so a single `ProgressCounter`
+/// passed to `Loader::load` tracks the whole group. It fails if any of its assets fails.
+/// The manifest holds the handles of its assets, so they are unloaded once all handles to
+/// the manifest are dropped.
+///
+/// ### Example
+///
+/// ```rust,ignore
+/// let manifest = loader.load("levels/forest.ron", RonFormat, &mut progress, &manifests);
+///
+/// // Once `progress` is complete:
+/// let trees = manifests
+///     .get(&manifest)
+///     .and_then(|manifest| manifest.handle::<Texture>("texture/tree.png"));
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    assets: Vec<ManifestEntry>,
+    #[serde(skip)]
+    handles: Vec<Box<dyn Any + Send + Sync>>,
+    #[serde(skip)]
+    counter: Option<ProgressCounter>,
+}
+
+/// Asset listed in a `Manifest`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    /// Path of the asset.
+    pub path: String,
+    /// Name of the format the asset is loaded with.
+    pub format: String,
+    /// Source the asset is loaded from, the default source if `None`.
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl Manifest {
+    /// Creates a manifest of the given assets.
+    pub fn new(assets: Vec<ManifestEntry>) -> Self {
+        Manifest {
+            assets,
+            handles: Vec::new(),
+            counter: None,
+        }
+    }
+
+    /// Returns the assets listed in the manifest.
+    pub fn entries(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.assets.iter()
+    }
+
+    /// Returns the handle of the asset loaded from `path`, if the manifest lists it with a
+    /// format for assets of type `A`.
+    ///
+    /// Only returns handles once the manifest is loaded.
+    pub fn handle<A>(&self, path: &str) -> Option<Handle<A>>
+    where
+        A: Asset,
+    {
+        self.assets
+            .iter()
+            .zip(&self.handles)
+            .filter(|(entry, _)| entry.path == path)
+            .find_map(|(_, handle)| handle.downcast_ref::<Handle<A>>())
+            .cloned()
+    }
+
+    /// Returns the handles of all assets of type `A` listed in the manifest.
+    pub fn handles<A>(&self) -> impl Iterator<Item = &Handle<A>>
+    where
+        A: Asset,
+    {
+        self.handles
+            .iter()
+            .filter_map(|handle| handle.downcast_ref::<Handle<A>>())
+    }
+}
+
+impl Asset for Manifest {
+    const NAME: &'static str = "MANIFEST";
+    type Data = Self;
+    type HandleStorage = VecStorage<Handle<Self>>;
+}
+
+type LoadFn = Box<
+    dyn Fn(&World, &Loader, &ManifestEntry, &mut ProgressCounter) -> Box<dyn Any + Send + Sync>
+        + Send
+        + Sync,
+>;
+
+/// Bundle adding the system which loads the assets listed in `Manifest`s.
+///
+/// Formats are registered with the name manifests refer to them by. Manifests can't list
+/// other manifests.
+#[derive(Default)]
+pub struct ManifestBundle {
+    system: ManifestSystem,
+}
+
+impl ManifestBundle {
+    /// Creates a bundle without any formats.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers `format` under `name`, loading assets of type `A`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if another format has been registered under `name`, or if `A` is
+    /// `Manifest`.
+    pub fn with_format<A, F>(mut self, name: &str, format: F) -> Self
+    where
+        A: Asset,
+        F: Format<A::Data> + Clone + Sync,
+    {
+        self.system.register::<A, F>(name, format);
+        self
+    }
+}
+
+impl fmt::Debug for ManifestBundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.system.fmt(f)
+    }
+}
+
+impl<'a, 'b> SystemBundle<'a, 'b> for ManifestBundle {
+    fn build(
+        self,
+        _world: &mut World,
+        dispatcher: &mut DispatcherBuilder<'a, 'b>,
+    ) -> Result<(), Error> {
+        dispatcher.add_thread_local(self.system);
+        Ok(())
+    }
+}
+
+/// Processes `Manifest`s, starting the loads of their assets.
+///
+/// Runs thread local, as it accesses the storages of all registered asset types.
+#[derive(Default)]
+struct ManifestSystem {
+    formats: FnvHashMap<String, LoadFn>,
+}
+
+impl ManifestSystem {
+    fn register<A, F>(&mut self, name: &str, format: F)
+    where
+        A: Asset,
+        F: Format<A::Data> + Clone + Sync,
+    {
+        assert!(
+            TypeId::of::<A>() != TypeId::of::<Manifest>(),
+            "Manifests can't list other manifests"
+        );
+        let load: LoadFn = Box::new(move |world, loader, entry, progress| {
+            let storage = world.read_resource::<AssetStorage<A>>();
+            let handle = match entry.source {
+                Some(ref source) => loader.load_from(
+                    entry.path.as_str(),
+                    format.clone(),
+                    source.as_str(),
+                    progress,
+                    &storage,
+                ),
+                None => loader.load(entry.path.as_str(), format.clone(), progress, &storage),
+            };
+            Box::new(handle)
+        });
+        if self.formats.insert(name.to_string(), load).is_some() {
+            panic!("Format {:?} has already been registered", name);
+        }
+    }
+
+    /// Starts loading the assets of `manifest`.
+    fn start(&self, world: &World, loader: &Loader, manifest: &mut Manifest) -> Result<(), Error> {
+        let mut progress = ProgressCounter::new();
+        let mut handles = Vec::with_capacity(manifest.assets.len());
+        for entry in &manifest.assets {
+            let load = self.formats.get(&entry.format).ok_or_else(|| {
+                format_err!(
+                    "Asset {:?} uses unknown format {:?}",
+                    entry.path,
+                    entry.format
+                )
+            })?;
+            handles.push(load(world, loader, entry, &mut progress));
+        }
+        manifest.handles = handles;
+        manifest.counter = Some(progress);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ManifestSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManifestSystem")
+            .field("formats", &self.formats.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<'a> RunNow<'a> for ManifestSystem {
+    fn run_now(&mut self, world: &'a World) {
+        #[cfg(feature = "profiler")]
+        profile_scope!("manifest_system");
+
+        let loader = world.read_resource::<Loader>();
+        let time = world.read_resource::<Time>();
+        let pool = world.read_resource::<ArcThreadPool>();
+        let strategy = world.try_fetch::<HotReloadStrategy>();
+        world.write_resource::<AssetStorage<Manifest>>().process(
+            |mut manifest| {
+                if manifest.counter.is_none() {
+                    self.start(world, &loader, &mut manifest)?;
+                }
+                let counter = manifest.counter.as_ref().expect("Loading has been started");
+                match counter.complete() {
+                    Completion::Complete => Ok(ProcessingState::Loaded(manifest)),
+                    Completion::Failed => {
+                        error!("Failed loading manifest asset: {:?}", counter.errors());
+                        Err(Error::from_string("Failed loading manifest asset"))
+                    }
+                    Completion::Loading => Ok(ProcessingState::Loading(manifest)),
+                }
+            },
+            time.frame_number(),
+            &pool,
+            strategy.as_deref(),
+        );
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        world
+            .entry::<AssetStorage<Manifest>>()
+            .or_insert_with(AssetStorage::default);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rayon::ThreadPoolBuilder;
+    use serde::Deserialize;
+
+    use amethyst_core::{
+        ecs::{prelude::RunNow, storage::VecStorage, World, WorldExt},
+        Time,
+    };
+
+    use crate::{
+        Asset, AssetStorage, Completion, Embedded, Format, Handle, Loader, ProcessableAsset,
+        ProgressCounter, RonFormat,
+    };
+
+    use super::{Manifest, ManifestSystem};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Level(u32);
+
+    impl Asset for Level {
+        const NAME: &'static str = "LEVEL";
+        type Data = Self;
+        type HandleStorage = VecStorage<Handle<Self>>;
+    }
+
+    static FILES: &[(&str, &[u8])] = &[("forest.ron", b"(1)"), ("cave.ron", b"(2)")];
+    static DLC: &[(&str, &[u8])] = &[("desert.ron", b"(3)")];
+
+    fn run(system: &mut ManifestSystem, world: &World) {
+        system.run_now(world);
+        let pool = world.read_resource::<Arc<rayon::ThreadPool>>();
+        world.write_resource::<AssetStorage<Level>>().process(
+            ProcessableAsset::process,
+            0,
+            &pool,
+            None,
+        );
+    }
+
+    #[test]
+    fn loads_and_unloads_group() {
+        let mut world = World::new();
+        let pool = Arc::new(ThreadPoolBuilder::default().build().unwrap());
+        let mut loader = Loader::with_default_source(Embedded::from_files(FILES, 0), pool.clone());
+        loader.add_source("dlc", Embedded::from_files(DLC, 0));
+        world.insert(pool);
+        world.insert(loader);
+        world.insert(Time::default());
+        world.insert(AssetStorage::<Level>::default());
+        let mut system = ManifestSystem::default();
+        system.register::<Level, _>("level", RonFormat);
+        RunNow::setup(&mut system, &mut world);
+
+        let manifest: Manifest = RonFormat
+            .import_simple(
+                br#"(
+                    assets: [
+                        (path: "forest.ron", format: "level"),
+                        (path: "cave.ron", format: "level"),
+                        (path: "desert.ron", format: "level", source: Some("dlc")),
+                    ],
+                )"#
+                .to_vec(),
+            )
+            .unwrap();
+        let handle = world.read_resource::<Loader>().load_from_data(
+            manifest,
+            (),
+            &world.read_resource::<AssetStorage<Manifest>>(),
+        );
+
+        while world
+            .read_resource::<AssetStorage<Manifest>>()
+            .get(&handle)
+            .is_none()
+        {
+            run(&mut system, &world);
+        }
+        let levels = world.read_resource::<AssetStorage<Level>>();
+        let manifests = world.read_resource::<AssetStorage<Manifest>>();
+        let manifest = manifests.get(&handle).unwrap();
+        assert_eq!(3, manifest.handles::<Level>().count());
+        let desert = manifest.handle::<Level>("desert.ron").unwrap();
+        assert_eq!(Some(&Level(3)), levels.get(&desert));
+        assert!(manifest.handle::<Manifest>("desert.ron").is_none());
+        drop((levels, manifests, desert));
+
+        drop(handle);
+        run(&mut system, &world);
+        run(&mut system, &world);
+        assert_eq!(
+            0,
+            world.read_resource::<AssetStorage<Level>>().stats().count
+        );
+    }
+
+    #[test]
+    fn fails_for_unknown_formats() {
+        let mut world = World::new();
+        let pool = Arc::new(ThreadPoolBuilder::default().build().unwrap());
+        world.insert(Loader::with_default_source(
+            Embedded::from_files(FILES, 0),
+            pool.clone(),
+        ));
+        world.insert(pool);
+        world.insert(Time::default());
+        let mut system = ManifestSystem::default();
+        RunNow::setup(&mut system, &mut world);
+
+        let manifest: Manifest = RonFormat
+            .import_simple(br#"(assets: [(path: "forest.ron", format: "level")])"#.to_vec())
+            .unwrap();
+        let mut progress = ProgressCounter::new();
+        let handle = world.read_resource::<Loader>().load_from_data(
+            manifest,
+            &mut progress,
+            &world.read_resource::<AssetStorage<Manifest>>(),
+        );
+        system.run_now(&world);
+        assert!(world
+            .read_resource::<AssetStorage<Manifest>>()
+            .get(&handle)
+            .is_none());
+        assert_eq!(Completion::Failed, progress.complete());
+    }
+}
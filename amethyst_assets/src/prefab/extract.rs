@@ -0,0 +1,256 @@
+//! Extracting prefabs from entities in the world.
+
+use fnv::FnvHashMap;
+
+use amethyst_core::{
+    ecs::{
+        prelude::{Entities, Entity, Join, ReadStorage, ResourceId, SystemData, World},
+        BitSet,
+    },
+    Named, Parent, Transform,
+};
+use amethyst_error::{format_err, Error};
+
+use super::{Prefab, PrefabEntity};
+
+/// Inverse of `PrefabData`: reads the data of a single entity back from the world.
+///
+/// Can be derived together with `PrefabData` by adding `#[prefab(Extract)]` to the type.
+/// Implemented for `Option`s, which extract `None` instead of failing, so aggregate prefab
+/// data made of `Option` fields can be extracted from any entity.
+pub trait PrefabExtract<'a>: Sized {
+    /// `SystemData` needed to read the data.
+    type SystemData: SystemData<'a>;
+
+    /// Reads the data of `entity`, returning `None` if it lacks the components the data
+    /// is made of.
+    ///
+    /// `entities` are all entities being extracted, in the order of the prefab's entities.
+    fn extract(
+        entity: Entity,
+        system_data: &Self::SystemData,
+        entities: &[Entity],
+    ) -> Result<Option<Self>, Error>;
+}
+
+impl<'a, T> PrefabExtract<'a> for Option<T>
+where
+    T: PrefabExtract<'a>,
+{
+    type SystemData = T::SystemData;
+
+    fn extract(
+        entity: Entity,
+        system_data: &Self::SystemData,
+        entities: &[Entity],
+    ) -> Result<Option<Self>, Error> {
+        T::extract(entity, system_data, entities).map(Some)
+    }
+}
+
+impl<'a> PrefabExtract<'a> for Transform {
+    type SystemData = ReadStorage<'a, Transform>;
+
+    fn extract(
+        entity: Entity,
+        storage: &Self::SystemData,
+        _: &[Entity],
+    ) -> Result<Option<Self>, Error> {
+        Ok(storage.get(entity).cloned())
+    }
+}
+
+impl<'a> PrefabExtract<'a> for Named {
+    type SystemData = ReadStorage<'a, Named>;
+
+    fn extract(
+        entity: Entity,
+        storage: &Self::SystemData,
+        _: &[Entity],
+    ) -> Result<Option<Self>, Error> {
+        Ok(storage.get(entity).cloned())
+    }
+}
+
+macro_rules! impl_extract {
+    ($($ty:ident:$i:tt),*) => {
+        impl<'a, $($ty),*> PrefabExtract<'a> for ( $( $ty , )* )
+            where $( $ty : PrefabExtract<'a> ),*
+        {
+            type SystemData = (
+                $(
+                    $ty::SystemData,
+                )*
+            );
+
+            #[allow(unused_variables)]
+            fn extract(
+                entity: Entity,
+                system_data: &Self::SystemData,
+                entities: &[Entity],
+            ) -> Result<Option<Self>, Error> {
+                Ok(Some((
+                    $(
+                        match $ty::extract(entity, &system_data.$i, entities)? {
+                            Some(data) => data,
+                            None => return Ok(None),
+                        },
+                    )*
+                )))
+            }
+        }
+    };
+}
+
+impl_extract!();
+impl_extract!(A:0);
+impl_extract!(A:0, B:1);
+impl_extract!(A:0, B:1, C:2);
+impl_extract!(A:0, B:1, C:2, D:3);
+impl_extract!(A:0, B:1, C:2, D:3, E:4);
+impl_extract!(A:0, B:1, C:2, D:3, E:4, F:5);
+impl_extract!(A:0, B:1, C:2, D:3, E:4, F:5, G:6);
+impl_extract!(A:0, B:1, C:2, D:3, E:4, F:5, G:6, H:7);
+
+/// Helper structure for extracting prefabs from the world, e.g. for level editors or
+/// save games.
+///
+/// ### Example
+///
+/// ```rust,ignore
+/// let prefab = world.exec(|extractor: PrefabExtractor<'_, MyPrefabData>| {
+///     extractor.extract(level_root)
+/// })?;
+/// let ron = RonFormat.write(&prefab)?;
+/// ```
+#[derive(SystemData)]
+pub struct PrefabExtractor<'a, T>
+where
+    T: PrefabExtract<'a>,
+{
+    entities: Entities<'a>,
+    parents: ReadStorage<'a, Parent>,
+    data: <T as PrefabExtract<'a>>::SystemData,
+}
+
+impl<'a, T> PrefabExtractor<'a, T>
+where
+    T: PrefabExtract<'a>,
+{
+    /// Extracts `root` and all of its descendants into a prefab, with `root` as the
+    /// main entity.
+    ///
+    /// Descendants are ordered breadth first, siblings by entity id. Entities lacking the
+    /// components of `T` are kept without data, so the hierarchy stays intact.
+    pub fn extract(&self, root: Entity) -> Result<Prefab<T>, Error> {
+        if !self.entities.is_alive(root) {
+            return Err(format_err!("Entity {:?} is not alive", root));
+        }
+
+        let mut children = FnvHashMap::<Entity, Vec<Entity>>::default();
+        for (entity, parent) in (&*self.entities, &self.parents).join() {
+            children.entry(parent.entity).or_default().push(entity);
+        }
+
+        let mut order = vec![root];
+        let mut parents = vec![None];
+        let mut visited = BitSet::new();
+        visited.add(root.id());
+        let mut index = 0;
+        while index < order.len() {
+            if let Some(children) = children.get_mut(&order[index]) {
+                children.sort_by_key(|child| child.id());
+                for &child in children.iter() {
+                    if !visited.add(child.id()) {
+                        order.push(child);
+                        parents.push(Some(index));
+                    }
+                }
+            }
+            index += 1;
+        }
+
+        let mut prefab = Prefab {
+            tag: None,
+            entities: Vec::with_capacity(order.len()),
+            counter: None,
+            ancestors: Vec::new(),
+            origin: None,
+        };
+        for (&entity, parent) in order.iter().zip(parents) {
+            let data = T::extract(entity, &self.data, &order)?;
+            prefab.entities.push(PrefabEntity::new(parent, data));
+        }
+        Ok(prefab)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst_core::{
+        ecs::{Builder, World, WorldExt},
+        Named, Parent, Transform,
+    };
+
+    use crate::{Format, RonFormat, WritableFormat};
+
+    use super::{Prefab, PrefabExtractor};
+
+    type Data = (Option<Named>, Option<Transform>);
+
+    #[test]
+    fn extracts_hierarchy_and_writes_it_as_ron() {
+        let mut world = World::new();
+        world.register::<Named>();
+        world.register::<Parent>();
+        world.register::<Transform>();
+
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(1.0, 2.0, 3.0);
+        let root = world
+            .create_entity()
+            .with(Named::new("table"))
+            .with(transform)
+            .build();
+        world
+            .create_entity()
+            .with(Named::new("leg"))
+            .with(Parent { entity: root })
+            .build();
+        let lamp = world.create_entity().with(Parent { entity: root }).build();
+        world
+            .create_entity()
+            .with(Named::new("bulb"))
+            .with(Parent { entity: lamp })
+            .build();
+        world.create_entity().with(Named::new("unrelated")).build();
+        world.maintain();
+
+        let prefab = world
+            .exec(|extractor: PrefabExtractor<'_, Data>| extractor.extract(root))
+            .unwrap();
+        let ron = RonFormat.write(&prefab).unwrap();
+        let prefab: Prefab<Data> = RonFormat.import_simple(ron).unwrap();
+
+        let entities = prefab
+            .entities()
+            .map(|entity| {
+                let (name, transform) = entity.data().unwrap();
+                (
+                    entity.parent,
+                    name.as_ref().map(|name| name.name.to_string()),
+                    transform.as_ref().map(|t| t.translation().x),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (None, Some("table".to_string()), Some(1.0)),
+                (Some(0), Some("leg".to_string()), None),
+                (Some(0), None, None),
+                (Some(2), Some("bulb".to_string()), None),
+            ],
+            entities
+        );
+    }
+}
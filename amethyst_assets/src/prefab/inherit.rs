@@ -0,0 +1,477 @@
+//! Prefabs extending other prefabs.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use amethyst_core::{Named, Transform};
+use amethyst_error::{format_err, Error, ResultExt};
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+use crate::{Asset, AssetPrefab, Format, FormatValue, Reload, Source};
+
+use super::{Prefab, PrefabEntity};
+
+/// Combines prefab data of a base prefab with the data of a prefab overriding it.
+///
+/// Can be derived with `#[derive(PrefabMerge)]`, which merges every field on its own, so
+/// an override only has to specify the components that differ from the base.
+/// `Option`s are replaced only if the override is `Some`, while plain components are
+/// replaced as a whole.
+pub trait PrefabMerge {
+    /// Applies the data of `patch` on top of `self`.
+    fn merge(&mut self, patch: Self);
+}
+
+impl<T> PrefabMerge for Option<T> {
+    fn merge(&mut self, patch: Self) {
+        if patch.is_some() {
+            *self = patch;
+        }
+    }
+}
+
+impl PrefabMerge for Transform {
+    fn merge(&mut self, patch: Self) {
+        *self = patch;
+    }
+}
+
+impl PrefabMerge for Named {
+    fn merge(&mut self, patch: Self) {
+        *self = patch;
+    }
+}
+
+impl<A, F> PrefabMerge for AssetPrefab<A, F>
+where
+    A: Asset,
+    F: Format<A::Data>,
+{
+    fn merge(&mut self, patch: Self) {
+        *self = patch;
+    }
+}
+
+macro_rules! impl_merge {
+    ($($ty:ident:$i:tt),*) => {
+        impl<$($ty),*> PrefabMerge for ( $( $ty , )* )
+            where $( $ty : PrefabMerge ),*
+        {
+            #[allow(unused_variables)]
+            fn merge(&mut self, patch: Self) {
+                $(self.$i.merge(patch.$i);)*
+            }
+        }
+    };
+}
+
+impl_merge!();
+impl_merge!(A:0);
+impl_merge!(A:0, B:1);
+impl_merge!(A:0, B:1, C:2);
+impl_merge!(A:0, B:1, C:2, D:3);
+impl_merge!(A:0, B:1, C:2, D:3, E:4);
+impl_merge!(A:0, B:1, C:2, D:3, E:4, F:5);
+impl_merge!(A:0, B:1, C:2, D:3, E:4, F:5, G:6);
+impl_merge!(A:0, B:1, C:2, D:3, E:4, F:5, G:6, H:7);
+
+/// Serialized form of a prefab which can extend a `base` prefab.
+///
+/// The base is resolved when loading through `InheritedPrefabFormat`, the prefab is then:
+///
+/// - the entities of the base (which itself may extend another prefab),
+/// - with the `overrides` merged into the base entities with the given index,
+/// - followed by the new `entities`. Parent indices count the base entities first.
+///
+/// ### Example
+///
+/// ```ron
+/// #![enable(implicit_some)]
+/// (
+///     base: "prefab/goblin.ron",
+///     overrides: {
+///         // only replace the weapon of the main entity, keep the rest of the goblin
+///         0: (data: (weapon: Bow(range: 12.0))),
+///     },
+///     entities: [
+///         (parent: 0, data: (name: "quiver")),
+///     ],
+/// )
+/// ```
+///
+/// A file without `base` and `overrides` is an ordinary prefab.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, bound(deserialize = "T: for<'a> Deserialize<'a>"))]
+pub struct InheritedPrefab<T> {
+    /// Name of the prefab this one extends.
+    pub base: Option<String>,
+    /// Changes to the entities of the base, by index.
+    pub overrides: BTreeMap<usize, PrefabEntity<T>>,
+    /// Entities added after the ones of the base.
+    pub entities: Vec<PrefabEntity<T>>,
+}
+
+impl<T> Default for InheritedPrefab<T> {
+    fn default() -> Self {
+        InheritedPrefab {
+            base: None,
+            overrides: BTreeMap::new(),
+            entities: Vec::new(),
+        }
+    }
+}
+
+/// Format for loading `InheritedPrefab`s into a `Prefab`, using format `F` for every file
+/// in the chain of base prefabs.
+///
+/// Hot reloading reloads the prefab when any of its bases change.
+///
+/// ### Example
+///
+/// ```rust,ignore
+/// let handle = world.exec(|loader: PrefabLoader<'_, MyPrefabData>| {
+///     loader.load_inherited("prefab/goblin_archer.ron", RonFormat, ())
+/// });
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InheritedPrefabFormat<F>(pub F);
+
+impl<T, F> Format<Prefab<T>> for InheritedPrefabFormat<F>
+where
+    T: PrefabMerge + Send + Sync + 'static,
+    F: Format<InheritedPrefab<T>> + Clone,
+{
+    fn name(&self) -> &'static str {
+        "InheritedPrefab"
+    }
+
+    fn import(
+        &self,
+        name: String,
+        source: Arc<dyn Source>,
+        create_reload: Option<Box<dyn Format<Prefab<T>>>>,
+    ) -> Result<FormatValue<Prefab<T>>, Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("import_inherited_prefab");
+
+        let mut files = Vec::new();
+        let data = resolve(
+            &self.0,
+            &name,
+            &*source,
+            create_reload.is_some(),
+            &mut files,
+        )?;
+        Ok(FormatValue {
+            data,
+            reload: create_reload.map(|format| {
+                Box::new(InheritedReload {
+                    generation: source.changes().map(|changes| changes.generation()),
+                    format,
+                    name,
+                    files,
+                    source,
+                }) as Box<dyn Reload<Prefab<T>>>
+            }),
+        })
+    }
+}
+
+/// Loads `name` and, recursively, its bases, recording every file with its
+/// modification time in `files`.
+fn resolve<T, F>(
+    format: &F,
+    name: &str,
+    source: &dyn Source,
+    metadata: bool,
+    files: &mut Vec<(String, u64)>,
+) -> Result<Prefab<T>, Error>
+where
+    T: PrefabMerge + 'static,
+    F: Format<InheritedPrefab<T>>,
+{
+    if files.iter().any(|(file, _)| file == name) {
+        let chain = files
+            .iter()
+            .map(|(file, _)| file.as_str())
+            .collect::<Vec<_>>();
+        return Err(format_err!(
+            "Prefab {:?} extends itself: {} -> {}",
+            name,
+            chain.join(" -> "),
+            name
+        ));
+    }
+
+    let (bytes, modified) = if metadata {
+        source.load_with_metadata(name)
+    } else {
+        source.load(name).map(|bytes| (bytes, 0))
+    }
+    .with_context(|_| crate::error::Error::Source)?;
+    files.push((name.to_owned(), modified));
+
+    let InheritedPrefab {
+        base,
+        overrides,
+        entities,
+    } = format
+        .import_simple(bytes)
+        .with_context(|_| format_err!("Failed parsing prefab {:?}", name))?;
+
+    let mut prefab = match base {
+        Some(base) => resolve(format, &base, source, metadata, files)
+            .with_context(|_| format_err!("Failed loading base of prefab {:?}", name))?,
+        None => Prefab {
+            tag: None,
+            entities: Vec::new(),
+            counter: None,
+            ancestors: Vec::new(),
+            origin: None,
+        },
+    };
+
+    let len = prefab.entities.len();
+    for (index, patch) in overrides {
+        let entity = prefab.entities.get_mut(index).ok_or_else(|| {
+            format_err!(
+                "Prefab {:?} overrides entity {}, but its base only has {} entities",
+                name,
+                index,
+                len
+            )
+        })?;
+        if patch.parent.is_some() {
+            entity.parent = patch.parent;
+        }
+        if patch.prefab.is_some() {
+            entity.prefab = patch.prefab;
+        }
+        match (&mut entity.data, patch.data) {
+            (Some(data), Some(patch)) => data.merge(patch),
+            (data, patch @ Some(_)) => *data = patch,
+            (_, None) => {}
+        }
+    }
+    prefab.entities.extend(entities);
+
+    Ok(prefab)
+}
+
+/// Reloads an inherited prefab whenever any file in its chain of bases changes.
+struct InheritedReload<T> {
+    format: Box<dyn Format<Prefab<T>>>,
+    generation: Option<u64>,
+    name: String,
+    files: Vec<(String, u64)>,
+    source: Arc<dyn Source>,
+}
+
+impl<T: 'static> Clone for InheritedReload<T> {
+    fn clone(&self) -> Self {
+        InheritedReload {
+            format: self.format.clone(),
+            generation: self.generation,
+            name: self.name.clone(),
+            files: self.files.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
+
+impl<T> Reload<Prefab<T>> for InheritedReload<T>
+where
+    T: Send + Sync + 'static,
+{
+    fn needs_reload(&self) -> bool {
+        match (self.generation, self.source.changes()) {
+            (Some(generation), Some(changes)) => self
+                .files
+                .iter()
+                .any(|(file, _)| changes.changed_since(file, generation)),
+            _ => self.files.iter().any(|(file, modified)| {
+                *modified != 0 && self.source.modified(file).unwrap_or(0) > *modified
+            }),
+        }
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn format(&self) -> &'static str {
+        self.format.name()
+    }
+
+    fn reload(self: Box<Self>) -> Result<FormatValue<Prefab<T>>, Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("reload_inherited_prefab");
+
+        let InheritedReload {
+            format,
+            name,
+            source,
+            ..
+        } = *self;
+        format.import(name, source, Some(objekt::clone(&format)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use parking_lot::Mutex;
+    use serde::{Deserialize, Serialize};
+
+    use amethyst_error::{format_err, Error};
+
+    use crate::{Format, RonFormat, Source};
+
+    use super::{InheritedPrefabFormat, Prefab, PrefabMerge};
+
+    #[derive(Default)]
+    struct Files(Mutex<HashMap<&'static str, (&'static str, u64)>>);
+
+    impl Files {
+        fn new(files: &[(&'static str, &'static str)]) -> Self {
+            Files(Mutex::new(
+                files
+                    .iter()
+                    .map(|&(name, content)| (name, (content, 1)))
+                    .collect(),
+            ))
+        }
+    }
+
+    impl Source for Files {
+        fn modified(&self, path: &str) -> Result<u64, Error> {
+            self.0
+                .lock()
+                .get(path)
+                .map(|&(_, modified)| modified)
+                .ok_or_else(|| format_err!("{} not found", path))
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            self.0
+                .lock()
+                .get(path)
+                .map(|(content, _)| content.as_bytes().to_vec())
+                .ok_or_else(|| format_err!("{} not found", path))
+        }
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct Creature {
+        name: Option<String>,
+        health: Option<u32>,
+        weapon: Option<String>,
+    }
+
+    impl PrefabMerge for Creature {
+        fn merge(&mut self, patch: Self) {
+            self.name.merge(patch.name);
+            self.health.merge(patch.health);
+            self.weapon.merge(patch.weapon);
+        }
+    }
+
+    const GOBLIN: &str = r#"#![enable(implicit_some)]
+(
+    entities: [
+        (data: (name: "goblin", health: 10, weapon: "club")),
+        (parent: 0, data: (name: "hat")),
+    ],
+)"#;
+
+    const ARCHER: &str = r#"#![enable(implicit_some)]
+(
+    base: "goblin.ron",
+    overrides: { 0: (data: (name: "goblin archer", weapon: "bow")) },
+    entities: [(parent: 0, data: (name: "quiver"))],
+)"#;
+
+    fn names(prefab: &Prefab<Creature>) -> Vec<(Option<usize>, Option<&str>)> {
+        prefab
+            .entities()
+            .map(|entity| {
+                (
+                    entity.parent,
+                    entity.data().and_then(|data| data.name.as_deref()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolves_base_and_overrides() {
+        let source: Arc<dyn Source> = Arc::new(Files::new(&[
+            ("goblin.ron", GOBLIN),
+            ("archer.ron", ARCHER),
+            (
+                "veteran.ron",
+                "(base: Some(\"archer.ron\"), overrides: { 0: (data: Some((health: Some(20)))) })",
+            ),
+        ]));
+        let format = InheritedPrefabFormat(RonFormat);
+
+        let prefab = format
+            .import("veteran.ron".into(), source, None)
+            .unwrap()
+            .data;
+        assert_eq!(
+            vec![
+                (None, Some("goblin archer")),
+                (Some(0), Some("hat")),
+                (Some(0), Some("quiver")),
+            ],
+            names(&prefab)
+        );
+        assert_eq!(
+            Some(&Creature {
+                name: Some("goblin archer".into()),
+                health: Some(20),
+                weapon: Some("bow".into()),
+            }),
+            prefab.entities().next().unwrap().data()
+        );
+    }
+
+    #[test]
+    fn detects_cycles_and_reloads_on_base_changes() {
+        let files = Arc::new(Files::new(&[
+            ("goblin.ron", GOBLIN),
+            ("archer.ron", ARCHER),
+            ("a.ron", "(base: Some(\"b.ron\"))"),
+            ("b.ron", "(base: Some(\"a.ron\"))"),
+        ]));
+        let source: Arc<dyn Source> = files.clone();
+        let format = InheritedPrefabFormat(RonFormat);
+
+        let error =
+            Format::<Prefab<Creature>>::import(&format, "a.ron".into(), source.clone(), None)
+                .err()
+                .expect("cyclic prefabs should fail to load");
+        assert!(error
+            .causes()
+            .any(|cause| cause.to_string().contains("a.ron -> b.ron -> a.ron")));
+
+        let reload = format
+            .import("archer.ron".into(), source, Some(Box::new(format.clone())))
+            .unwrap()
+            .reload
+            .unwrap();
+        assert!(!reload.needs_reload());
+        files.0.lock().insert("goblin.ron", ("(entities: [()])", 2));
+        assert!(reload.needs_reload());
+        let prefab = reload.reload().unwrap().data;
+        assert_eq!(
+            vec![(None, Some("goblin archer")), (Some(0), Some("quiver"))],
+            names(&prefab)
+        );
+    }
+}
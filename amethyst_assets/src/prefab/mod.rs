@@ -1,4 +1,4 @@
-use std::marker::PhantomData;
+use std::{borrow::Borrow, hash::Hash, marker::PhantomData};
 
 use serde::{Deserialize, Serialize};
 
@@ -12,9 +12,19 @@ use crate::{
     Asset, AssetStorage, Format, Handle, Loader, Progress, ProgressCounter, SerializableFormat,
 };
 
-pub use self::system::{PrefabLoaderSystem, PrefabLoaderSystemDesc};
+pub use self::{
+    extract::{PrefabExtract, PrefabExtractor},
+    inherit::{InheritedPrefab, InheritedPrefabFormat, PrefabMerge},
+    nested::PrefabRef,
+    system::{PrefabLoaderSystem, PrefabLoaderSystemDesc},
+};
+
+use self::nested::{Origin, OriginFormat};
 
+mod extract;
 mod impls;
+mod inherit;
+mod nested;
 mod system;
 
 /// Trait for loading a prefabs data for a single entity
@@ -105,12 +115,18 @@ pub trait PrefabData<'a> {
 ///
 /// - `T`: `PrefabData`
 #[derive(Default, Deserialize, Serialize)]
+#[serde(bound(deserialize = "T: for<'a> Deserialize<'a>"))]
 pub struct Prefab<T> {
     #[serde(skip)]
     tag: Option<u64>,
     entities: Vec<PrefabEntity<T>>,
     #[serde(skip)]
     counter: Option<ProgressCounter>,
+    /// Names of the prefabs which referenced this one, outermost first.
+    #[serde(skip)]
+    ancestors: Vec<String>,
+    #[serde(skip)]
+    origin: Option<Origin<T>>,
 }
 
 /// Prefab data container for a single entity
@@ -119,10 +135,11 @@ pub struct Prefab<T> {
 ///
 /// - `T`: `PrefabData`
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(default)]
+#[serde(default, bound(deserialize = "T: for<'a> Deserialize<'a>"))]
 pub struct PrefabEntity<T> {
     parent: Option<usize>,
     data: Option<T>,
+    prefab: Option<PrefabRef<T>>,
 }
 
 impl<T> Default for PrefabEntity<T> {
@@ -134,7 +151,11 @@ impl<T> Default for PrefabEntity<T> {
 impl<T> PrefabEntity<T> {
     /// New prefab entity
     pub fn new(parent: Option<usize>, data: Option<T>) -> Self {
-        PrefabEntity { parent, data }
+        PrefabEntity {
+            parent,
+            data,
+            prefab: None,
+        }
     }
 
     /// Set parent index
@@ -147,6 +168,16 @@ impl<T> PrefabEntity<T> {
         self.data = Some(data);
     }
 
+    /// Set the prefab to instantiate as a child of this entity
+    pub fn set_prefab(&mut self, prefab: PrefabRef<T>) {
+        self.prefab = Some(prefab);
+    }
+
+    /// Get the prefab instantiated as a child of this entity
+    pub fn prefab(&self) -> Option<&PrefabRef<T>> {
+        self.prefab.as_ref()
+    }
+
     /// Get immutable access to the data
     pub fn data(&self) -> Option<&T> {
         self.data.as_ref()
@@ -198,6 +229,8 @@ impl<T> Prefab<T> {
             tag: None,
             entities: vec![PrefabEntity::default()],
             counter: None,
+            ancestors: Vec::new(),
+            origin: None,
         }
     }
 
@@ -207,6 +240,8 @@ impl<T> Prefab<T> {
             tag: None,
             entities: vec![PrefabEntity::new(None, Some(data))],
             counter: None,
+            ancestors: Vec::new(),
+            origin: None,
         }
     }
 
@@ -367,7 +402,14 @@ where
     #[serde(skip)]
     Handle(Handle<A>),
     /// From file, (name, format)
-    File(String, F),
+    File(
+        #[serde(deserialize_with = "crate::deserialize_asset_path")] String,
+        #[serde(
+            deserialize_with = "crate::deserialize_asset_format::<A::Data, _, _>",
+            bound(deserialize = "F: Deserialize<'de>")
+        )]
+        F,
+    ),
     /// Placeholder during loading
     #[serde(skip)]
     Placeholder,
@@ -445,13 +487,57 @@ where
     T: Send + Sync + 'static,
 {
     /// Load prefab from source
+    ///
+    /// Nested prefabs (see `PrefabRef`) are loaded with the same format and from the
+    /// same source.
     pub fn load<F, N, P>(&self, name: N, format: F, progress: P) -> Handle<Prefab<T>>
     where
         F: Format<<Prefab<T> as Asset>::Data>,
         N: Into<String>,
         P: Progress,
     {
-        self.loader.load(name, format, progress, &self.storage)
+        self.loader
+            .load(name, OriginFormat(format), progress, &self.storage)
+    }
+
+    /// Load prefab from the source with the given id, see `Loader::add_source`.
+    ///
+    /// Nested prefabs (see `PrefabRef`) are loaded with the same format and from the
+    /// same source.
+    pub fn load_from<F, N, P, S>(
+        &self,
+        name: N,
+        format: F,
+        source: &S,
+        progress: P,
+    ) -> Handle<Prefab<T>>
+    where
+        F: Format<<Prefab<T> as Asset>::Data>,
+        N: Into<String>,
+        P: Progress,
+        S: AsRef<str> + Eq + Hash + ?Sized,
+        String: Borrow<S>,
+    {
+        self.loader
+            .load_from(name, OriginFormat(format), source, progress, &self.storage)
+    }
+
+    /// Load prefab which may extend other prefabs, see `InheritedPrefab`.
+    ///
+    /// `format` is used for loading the prefab and all of its bases.
+    pub fn load_inherited<F, N, P>(&self, name: N, format: F, progress: P) -> Handle<Prefab<T>>
+    where
+        T: PrefabMerge,
+        F: Format<InheritedPrefab<T>> + Clone,
+        N: Into<String>,
+        P: Progress,
+    {
+        self.loader.load(
+            name,
+            OriginFormat(InheritedPrefabFormat(format)),
+            progress,
+            &self.storage,
+        )
     }
 
     /// Load prefab from explicit data
@@ -465,16 +551,17 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, thread, time::Duration};
 
     use rayon::ThreadPoolBuilder;
 
     use amethyst_core::{
-        ecs::{Builder, RunNow, World, WorldExt},
-        SystemDesc, Time, Transform,
+        ecs::{Builder, Join, RunNow, World, WorldExt},
+        Parent, SystemDesc, Time, Transform,
     };
+    use amethyst_error::format_err;
 
-    use crate::Loader;
+    use crate::{convert, BincodeFormat, Format, Loader, RonFormat, Source};
 
     use super::*;
 
@@ -505,4 +592,268 @@ mod tests {
         );
         assert!(world.read_storage::<Transform>().get(root_entity).is_some());
     }
+
+    struct Files;
+
+    impl Source for Files {
+        fn modified(&self, _: &str) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            let content = match path {
+                "room.ron" => "(entities: [(), (parent: Some(0), prefab: Some(\"lamp.ron\"))])",
+                "lamp.ron" => "(entities: [(), (parent: Some(0))])",
+                "loop.ron" => "(entities: [(prefab: Some(\"loop.ron\"))])",
+                _ => return Err(format_err!("{} not found", path)),
+            };
+            Ok(content.as_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn instantiates_nested_prefabs() {
+        let mut world = World::new();
+        let pool = Arc::new(ThreadPoolBuilder::default().build().unwrap());
+        world.insert(pool.clone());
+        world.insert(Loader::with_default_source(Files, pool));
+        world.insert(Time::default());
+        let mut system = PrefabLoaderSystemDesc::<MyPrefab>::default().build(&mut world);
+        RunNow::setup(&mut system, &mut world);
+
+        let mut room_progress = ProgressCounter::new();
+        let mut loop_progress = ProgressCounter::new();
+        let (room, cyclic) = world.exec(|loader: PrefabLoader<'_, MyPrefab>| {
+            (
+                loader.load("room.ron", RonFormat, &mut room_progress),
+                loader.load("loop.ron", RonFormat, &mut loop_progress),
+            )
+        });
+        let root_entity = world.create_entity().with(room).build();
+        world.create_entity().with(cyclic).build();
+
+        for _ in 0..100 {
+            system.run_now(&world);
+            if room_progress.num_loading() == 0 && loop_progress.num_loading() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        system.run_now(&world);
+
+        // The room only counts as loaded with the lamp nested in it.
+        assert!(room_progress.is_complete());
+        assert_eq!(1, loop_progress.num_failed());
+
+        // root <- room entity <- lamp root <- lamp entity
+        let parents = world.read_storage::<Parent>();
+        let mut entity = (&world.entities(), &parents)
+            .join()
+            .find(|(entity, _)| !(&parents).join().any(|p| p.entity == *entity))
+            .map(|(entity, _)| entity)
+            .unwrap();
+        let mut depth = 0;
+        while let Some(parent) = parents.get(entity) {
+            entity = parent.entity;
+            depth += 1;
+        }
+        assert_eq!(root_entity, entity);
+        assert_eq!(3, depth);
+    }
+
+    struct Binary {
+        room: Vec<u8>,
+        lamp: Vec<u8>,
+    }
+
+    impl Source for Binary {
+        fn modified(&self, _: &str) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            match path {
+                "room.bin" => Ok(self.room.clone()),
+                "lamp.bin" => Ok(self.lamp.clone()),
+                _ => Err(format_err!("{} not found", path)),
+            }
+        }
+    }
+
+    #[test]
+    fn loads_nested_prefabs_with_the_origin_of_the_referencing_one() {
+        let bin = |ron: &str| {
+            convert::<Prefab<MyPrefab>, _, _>(ron.as_bytes().to_vec(), &RonFormat, &BincodeFormat)
+                .unwrap()
+        };
+        let mut world = World::new();
+        let pool = Arc::new(ThreadPoolBuilder::default().build().unwrap());
+        world.insert(pool.clone());
+        let mut loader = Loader::with_default_source(Files, pool);
+        loader.add_source(
+            "bin",
+            Binary {
+                room: bin("(entities: [(), (parent: Some(0), prefab: Some(\"lamp.bin\"))])"),
+                lamp: bin("(entities: [(), (parent: Some(0))])"),
+            },
+        );
+        world.insert(loader);
+        world.insert(Time::default());
+        let mut system = PrefabLoaderSystemDesc::<MyPrefab>::default().build(&mut world);
+        RunNow::setup(&mut system, &mut world);
+
+        let mut progress = ProgressCounter::new();
+        let room = world.exec(|loader: PrefabLoader<'_, MyPrefab>| {
+            loader.load_from("room.bin", BincodeFormat, "bin", &mut progress)
+        });
+        world.create_entity().with(room).build();
+        for _ in 0..100 {
+            system.run_now(&world);
+            if progress.num_loading() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        system.run_now(&world);
+
+        assert!(progress.is_complete(), "{:?}", progress.errors());
+        // room entity, lamp root and lamp entity
+        assert_eq!(3, world.read_storage::<Parent>().join().count());
+    }
+
+    #[test]
+    fn round_trips_through_bincode() {
+        let ron = "(entities: [(data: Some((translation: (1.0, 2.0, 3.0)))), \
+                   (parent: Some(0), prefab: Some(\"lamp.ron\"))])";
+        let bin =
+            convert::<Prefab<MyPrefab>, _, _>(ron.as_bytes().to_vec(), &RonFormat, &BincodeFormat)
+                .unwrap();
+        let prefab: Prefab<MyPrefab> = BincodeFormat.import_simple(bin).unwrap();
+
+        let entities = prefab.entities().collect::<Vec<_>>();
+        assert_eq!(2, entities.len());
+        assert_eq!(2.0, entities[0].data().unwrap().translation().y);
+        assert!(entities[0].prefab().is_none());
+        assert_eq!(Some(0), entities[1].parent);
+        assert_eq!(
+            "File(\"lamp.ron\")",
+            format!("{:?}", entities[1].prefab().unwrap())
+        );
+    }
+
+    fn translations(xs: &[f32]) -> Prefab<MyPrefab> {
+        let mut prefab = Prefab::new();
+        for (index, &x) in xs.iter().enumerate() {
+            let mut transform = Transform::default();
+            transform.set_translation_x(x);
+            if index == 0 {
+                prefab.main(Some(transform));
+            } else {
+                prefab.add(Some(0), Some(transform));
+            }
+        }
+        prefab
+    }
+
+    #[test]
+    fn applies_reloads_to_instances() {
+        let mut world = World::new();
+        let pool = Arc::new(ThreadPoolBuilder::default().build().unwrap());
+        world.insert(pool.clone());
+        world.insert(Loader::new(".", pool));
+        world.insert(Time::default());
+        let mut system = PrefabLoaderSystemDesc::<MyPrefab>::default()
+            .with_reload_instances(true)
+            .build(&mut world);
+        RunNow::setup(&mut system, &mut world);
+
+        let handle = world.read_resource::<Loader>().load_from_data(
+            translations(&[0.0, 1.0, 2.0]),
+            (),
+            &world.read_resource::<AssetStorage<Prefab<MyPrefab>>>(),
+        );
+        let root_entity = world.create_entity().with(handle.clone()).build();
+        system.run_now(&world);
+        world.maintain();
+        let children = |world: &World| {
+            let parents = world.read_storage::<Parent>();
+            let transforms = world.read_storage::<Transform>();
+            let mut xs = (&world.entities(), &parents, &transforms)
+                .join()
+                .filter(|(_, parent, _)| parent.entity == root_entity)
+                .map(|(_, _, transform)| transform.translation().x)
+                .collect::<Vec<_>>();
+            xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            xs
+        };
+        assert_eq!(vec![1.0, 2.0], children(&world));
+
+        {
+            let mut storage = world.write_resource::<AssetStorage<Prefab<MyPrefab>>>();
+            let mut prefab = translations(&[5.0, 6.0]);
+            prefab.tag = Some(1);
+            storage.replace(&handle, prefab);
+        }
+        system.run_now(&world);
+        world.maintain();
+        assert_eq!(vec![6.0], children(&world));
+        assert_eq!(
+            5.0,
+            world
+                .read_storage::<Transform>()
+                .get(root_entity)
+                .unwrap()
+                .translation()
+                .x
+        );
+        assert_eq!(2, world.read_storage::<Transform>().join().count());
+    }
+
+    #[test]
+    fn reloads_instances_with_deleted_children() {
+        let mut world = World::new();
+        let pool = Arc::new(ThreadPoolBuilder::default().build().unwrap());
+        world.insert(pool.clone());
+        world.insert(Loader::new(".", pool));
+        world.insert(Time::default());
+        let mut system = PrefabLoaderSystemDesc::<MyPrefab>::default()
+            .with_reload_instances(true)
+            .build(&mut world);
+        RunNow::setup(&mut system, &mut world);
+
+        let handle = world.read_resource::<Loader>().load_from_data(
+            translations(&[0.0, 1.0, 2.0]),
+            (),
+            &world.read_resource::<AssetStorage<Prefab<MyPrefab>>>(),
+        );
+        let root_entity = world.create_entity().with(handle.clone()).build();
+        system.run_now(&world);
+        world.maintain();
+
+        let child = (&world.entities(), &world.read_storage::<Parent>())
+            .join()
+            .map(|(entity, _)| entity)
+            .next()
+            .unwrap();
+        world.delete_entity(child).unwrap();
+
+        {
+            let mut storage = world.write_resource::<AssetStorage<Prefab<MyPrefab>>>();
+            let mut prefab = translations(&[0.0, 3.0, 4.0]);
+            prefab.tag = Some(1);
+            storage.replace(&handle, prefab);
+        }
+        system.run_now(&world);
+        world.maintain();
+
+        let parents = world.read_storage::<Parent>();
+        let transforms = world.read_storage::<Transform>();
+        let mut xs = (&world.entities(), &parents, &transforms)
+            .join()
+            .filter(|(_, parent, _)| parent.entity == root_entity)
+            .map(|(_, _, transform)| transform.translation().x)
+            .collect::<Vec<_>>();
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(vec![3.0, 4.0], xs);
+    }
 }
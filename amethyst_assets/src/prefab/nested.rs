@@ -0,0 +1,386 @@
+//! Prefabs instantiating other prefabs.
+
+use std::{fmt, sync::Arc};
+
+use fnv::FnvHashMap;
+use serde::{ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+use amethyst_error::{format_err, Error};
+
+use crate::{
+    formats::from_ron, progress::ProgressCounterTracker, AssetStorage, Format, FormatValue, Handle,
+    Loader, Progress, Source,
+};
+
+use super::Prefab;
+
+/// Reference from a `PrefabEntity` to another prefab, which `PrefabLoaderSystem`
+/// instantiates as a child of the referencing entity.
+///
+/// In a prefab file it's written as the name of the referenced prefab, which is loaded
+/// together with the referencing one, so it's accounted for in the `ProgressCounter` of
+/// the referencing prefab. It's loaded with the format and from the source of the
+/// referencing prefab, if that one has been loaded through `PrefabLoader`, otherwise as
+/// a RON prefab from the default source:
+///
+/// ```ron
+/// #![enable(implicit_some)]
+/// (
+///     entities: [
+///         (data: (name: "table")),
+///         (parent: 0, prefab: "prefab/lamp.ron"),
+///     ],
+/// )
+/// ```
+///
+/// A prefab referencing itself, directly or through other prefabs, fails to load.
+pub struct PrefabRef<T>(Reference<T>);
+
+enum Reference<T> {
+    File {
+        name: String,
+        /// Import of prefabs whose referencing prefab hasn't been loaded through
+        /// `PrefabLoader`.
+        import: fn(Vec<u8>) -> Result<Prefab<T>, Error>,
+    },
+    Pending(u64),
+    Handle(Handle<Prefab<T>>),
+}
+
+impl<T> PrefabRef<T> {
+    /// Reference to the prefab file with the given name.
+    pub fn file<N>(name: N) -> Self
+    where
+        N: Into<String>,
+        T: for<'a> Deserialize<'a>,
+    {
+        PrefabRef(Reference::File {
+            name: name.into(),
+            import: |bytes| from_ron(&bytes),
+        })
+    }
+
+    /// Reference to an already loaded prefab.
+    pub fn handle(handle: Handle<Prefab<T>>) -> Self {
+        PrefabRef(Reference::Handle(handle))
+    }
+
+    /// Returns the handle of the referenced prefab, once its loading has been started.
+    pub fn get_handle(&self) -> Option<&Handle<Prefab<T>>> {
+        match self.0 {
+            Reference::Handle(ref handle) => Some(handle),
+            _ => None,
+        }
+    }
+}
+
+impl<T> fmt::Debug for PrefabRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Reference::File { ref name, .. } => f.debug_tuple("File").field(name).finish(),
+            Reference::Pending(id) => f.debug_tuple("Pending").field(&id).finish(),
+            Reference::Handle(ref handle) => f.debug_tuple("Handle").field(handle).finish(),
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for PrefabRef<T>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = crate::deserialize_asset_path(deserializer)?;
+        crate::validate::record_import(validate::<T>);
+        Ok(PrefabRef::file(name))
+    }
+}
+
+/// Import of referenced prefabs by `Validator`.
+fn validate<T>(bytes: Vec<u8>) -> Result<(), Error>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    from_ron::<Prefab<T>>(&bytes).map(|_| ())
+}
+
+impl<T> Serialize for PrefabRef<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            Reference::File { ref name, .. } => serializer.serialize_str(name),
+            _ => Err(S::Error::custom(
+                "Only prefab references to files can be serialized",
+            )),
+        }
+    }
+}
+
+/// Load of a nested prefab, requested while processing the referencing prefab.
+///
+/// The `AssetStorage` is borrowed while processing, so the loads are only started
+/// afterwards and the handles are handed to the referencing prefab on the next frame.
+pub(crate) struct NestedLoad<T> {
+    id: u64,
+    name: String,
+    format: NestedFormat<T>,
+    tracker: ProgressCounterTracker,
+}
+
+impl<T> NestedLoad<T>
+where
+    T: Send + Sync + 'static,
+{
+    /// Starts the load, returning the id of the request and the handle of the nested prefab.
+    pub(crate) fn start(
+        self,
+        loader: &Loader,
+        storage: &AssetStorage<Prefab<T>>,
+    ) -> (u64, Handle<Prefab<T>>) {
+        let handle = loader.load(self.name, self.format, Started(self.tracker), storage);
+        (self.id, handle)
+    }
+}
+
+/// Progress which has already been counted by the referencing prefab.
+struct Started(ProgressCounterTracker);
+
+impl Progress for Started {
+    type Tracker = ProgressCounterTracker;
+
+    fn add_assets(&mut self, _: usize) {}
+
+    fn create_tracker(self) -> ProgressCounterTracker {
+        self.0
+    }
+}
+
+/// Format and source a prefab has been loaded with, used for its nested prefabs too.
+pub(crate) struct Origin<T> {
+    format: Box<dyn Format<Prefab<T>>>,
+    source: Arc<dyn Source>,
+}
+
+impl<T> Clone for Origin<T> {
+    fn clone(&self) -> Self {
+        Origin {
+            format: self.format.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
+
+/// Format of the prefabs loaded through `PrefabLoader`, which remembers their origin.
+#[derive(Debug)]
+pub(crate) struct OriginFormat<F>(pub F);
+
+impl<F: objekt::Clone> Clone for OriginFormat<F> {
+    fn clone(&self) -> Self {
+        OriginFormat(objekt::clone(&self.0))
+    }
+}
+
+impl<T, F> Format<Prefab<T>> for OriginFormat<F>
+where
+    T: Send + Sync + 'static,
+    F: Format<Prefab<T>>,
+{
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<Prefab<T>, Error> {
+        self.0.import_simple(bytes)
+    }
+
+    fn import(
+        &self,
+        name: String,
+        source: Arc<dyn Source>,
+        create_reload: Option<Box<dyn Format<Prefab<T>>>>,
+    ) -> Result<FormatValue<Prefab<T>>, Error> {
+        let mut value = self.0.import(name, source.clone(), create_reload)?;
+        value.data.origin = Some(Origin {
+            format: Box::new(objekt::clone(&self.0)),
+            source,
+        });
+        Ok(value)
+    }
+}
+
+/// Format of nested prefabs whose referencing prefab hasn't been loaded through
+/// `PrefabLoader`.
+struct Import<T>(fn(Vec<u8>) -> Result<Prefab<T>, Error>);
+
+impl<T> Clone for Import<T> {
+    fn clone(&self) -> Self {
+        Import(self.0)
+    }
+}
+
+impl<T> fmt::Debug for Import<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Import").finish()
+    }
+}
+
+impl<T> Format<Prefab<T>> for Import<T>
+where
+    T: Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        "Ron"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<Prefab<T>, Error> {
+        (self.0)(bytes)
+    }
+}
+
+/// Loads a nested prefab from the origin of the referencing prefab, remembering which
+/// prefabs led to it for detecting cycles.
+struct NestedFormat<T> {
+    format: Box<dyn Format<Prefab<T>>>,
+    /// Source of the referencing prefab, `None` for the one the load is started from.
+    source: Option<Arc<dyn Source>>,
+    ancestors: Vec<String>,
+}
+
+impl<T> Clone for NestedFormat<T> {
+    fn clone(&self) -> Self {
+        NestedFormat {
+            format: self.format.clone(),
+            source: self.source.clone(),
+            ancestors: self.ancestors.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for NestedFormat<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NestedFormat")
+            .field("format", &self.format)
+            .field("ancestors", &self.ancestors)
+            .finish()
+    }
+}
+
+impl<T> Format<Prefab<T>> for NestedFormat<T>
+where
+    T: Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        "NestedPrefab"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<Prefab<T>, Error> {
+        let mut prefab = self.format.import_simple(bytes)?;
+        prefab.ancestors = self.ancestors.clone();
+        Ok(prefab)
+    }
+
+    fn import(
+        &self,
+        name: String,
+        source: Arc<dyn Source>,
+        create_reload: Option<Box<dyn Format<Prefab<T>>>>,
+    ) -> Result<FormatValue<Prefab<T>>, Error> {
+        let source = self.source.clone().unwrap_or(source);
+        let mut value = self.format.import(name, source.clone(), create_reload)?;
+        value.data.ancestors = self.ancestors.clone();
+        value.data.origin = Some(Origin {
+            format: self.format.clone(),
+            source,
+        });
+        Ok(value)
+    }
+}
+
+impl<T> Prefab<T>
+where
+    T: Send + Sync + 'static,
+{
+    /// Requests loads for all nested prefabs referenced by file, counting them in the
+    /// progress of this prefab.
+    ///
+    /// Returns `true` if any load was requested.
+    pub(crate) fn request_nested(
+        &mut self,
+        next_id: &mut u64,
+        loads: &mut Vec<NestedLoad<T>>,
+    ) -> Result<bool, Error> {
+        let ancestors = &self.ancestors;
+        let origin = &self.origin;
+        let mut progress = self
+            .counter
+            .as_mut()
+            .expect("Sub asset loading has not been triggered");
+        let mut ret = false;
+        for reference in self
+            .entities
+            .iter_mut()
+            .filter_map(|entity| entity.prefab.as_mut())
+        {
+            if let Reference::File { ref name, import } = reference.0 {
+                if ancestors.contains(name) {
+                    return Err(format_err!(
+                        "Prefab references itself: {} -> {}",
+                        ancestors.join(" -> "),
+                        name
+                    ));
+                }
+                progress.add_assets(1);
+                let mut chain = ancestors.clone();
+                chain.push(name.clone());
+                loads.push(NestedLoad {
+                    id: *next_id,
+                    name: name.clone(),
+                    format: match origin {
+                        Some(origin) => NestedFormat {
+                            format: origin.format.clone(),
+                            source: Some(origin.source.clone()),
+                            ancestors: chain,
+                        },
+                        None => NestedFormat {
+                            format: Box::new(Import(import)),
+                            source: None,
+                            ancestors: chain,
+                        },
+                    },
+                    tracker: (&mut *progress).create_tracker(),
+                });
+                reference.0 = Reference::Pending(*next_id);
+                *next_id += 1;
+                ret = true;
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Replaces requested loads by the handles of the nested prefabs.
+    ///
+    /// Returns `false` if some loads haven't been started yet.
+    pub(crate) fn resolve_nested(
+        &mut self,
+        started: &mut FnvHashMap<u64, Handle<Prefab<T>>>,
+    ) -> bool {
+        let mut resolved = true;
+        for reference in self
+            .entities
+            .iter_mut()
+            .filter_map(|entity| entity.prefab.as_mut())
+        {
+            if let Reference::Pending(id) = reference.0 {
+                match started.remove(&id) {
+                    Some(handle) => reference.0 = Reference::Handle(handle),
+                    None => resolved = false,
+                }
+            }
+        }
+        resolved
+    }
+}
@@ -1,12 +1,13 @@
 use std::{collections::HashMap, marker::PhantomData};
 
 use derivative::Derivative;
+use fnv::FnvHashMap;
 use log::error;
 
 use amethyst_core::{
     ecs::{
-        storage::ComponentEvent, BitSet, Entities, Entity, Join, Read, ReadExpect, ReadStorage,
-        ReaderId, System, SystemData, World, Write, WriteStorage,
+        storage::ComponentEvent, world::EntitiesRes, BitSet, Entities, Entity, Join, Read,
+        ReadExpect, ReadStorage, ReaderId, System, SystemData, World, Write, WriteStorage,
     },
     ArcThreadPool, Parent, SystemDesc, Time,
 };
@@ -15,15 +16,26 @@ use amethyst_error::{format_err, Error, ResultExt};
 #[cfg(feature = "profiler")]
 use thread_profiler::profile_scope;
 
-use crate::{AssetStorage, Completion, Handle, HotReloadStrategy, ProcessingState};
+use crate::{AssetStorage, Completion, Handle, HotReloadStrategy, Loader, ProcessingState};
 
-use super::{Prefab, PrefabData, PrefabTag};
+use super::{nested::NestedLoad, Prefab, PrefabData, PrefabTag};
 
 /// Builds a `PrefabLoaderSystem`.
 #[derive(Derivative, Debug)]
 #[derivative(Default(bound = ""))]
 pub struct PrefabLoaderSystemDesc<T> {
     marker: PhantomData<T>,
+    reload_instances: bool,
+}
+
+impl<T> PrefabLoaderSystemDesc<T> {
+    /// Makes the system apply hot reloaded prefabs to the entities already created from them.
+    ///
+    /// See `PrefabLoaderSystem::set_reload_instances`.
+    pub fn with_reload_instances(mut self, reload_instances: bool) -> Self {
+        self.reload_instances = reload_instances;
+        self
+    }
 }
 
 impl<'a, 'b, T> SystemDesc<'a, 'b, PrefabLoaderSystem<T>> for PrefabLoaderSystemDesc<T>
@@ -35,7 +47,9 @@ where
 
         let insert_reader = WriteStorage::<Handle<Prefab<T>>>::fetch(&world).register_reader();
 
-        PrefabLoaderSystem::new(insert_reader)
+        let mut system = PrefabLoaderSystem::new(insert_reader);
+        system.set_reload_instances(self.reload_instances);
+        system
     }
 }
 
@@ -46,11 +60,15 @@ where
 /// - `T`: `PrefabData`
 pub struct PrefabLoaderSystem<T> {
     _m: PhantomData<T>,
-    entities: Vec<Entity>,
+    reload_instances: bool,
+    instances: Vec<Instance>,
     finished: Vec<Entity>,
     to_process: BitSet,
     insert_reader: ReaderId<ComponentEvent>,
     next_tag: u64,
+    nested_loads: Vec<NestedLoad<T>>,
+    next_nested_load: u64,
+    started_nested_loads: FnvHashMap<u64, Handle<Prefab<T>>>,
 }
 
 impl<'a, T> PrefabLoaderSystem<T>
@@ -61,15 +79,45 @@ where
     pub fn new(insert_reader: ReaderId<ComponentEvent>) -> Self {
         Self {
             _m: PhantomData,
-            entities: Vec::default(),
+            reload_instances: false,
+            instances: Vec::default(),
             finished: Vec::default(),
             to_process: BitSet::default(),
             insert_reader,
             next_tag: 0,
+            nested_loads: Vec::default(),
+            next_nested_load: 0,
+            started_nested_loads: FnvHashMap::default(),
+        }
+    }
+
+    /// Sets whether hot reloaded prefabs are applied to the entities already created from them.
+    ///
+    /// The entities are matched with the entities of the reloaded prefab by index: their
+    /// prefab data is added again and their parents are updated, entities the prefab
+    /// doesn't have anymore are deleted and new ones are created. Prefabs nested in the
+    /// reloaded prefab are instantiated again.
+    ///
+    /// `PrefabData` can only add components, so components whose data was removed from
+    /// the prefab stay on the entities.
+    pub fn set_reload_instances(&mut self, reload_instances: bool) {
+        self.reload_instances = reload_instances;
+        if !reload_instances {
+            self.instances.clear();
         }
     }
 }
 
+/// Entities created from a prefab, tracked for applying reloads.
+struct Instance {
+    handle: u32,
+    version: u32,
+    /// The entities of the prefab, starting with the root entity.
+    entities: Vec<Entity>,
+    /// Entities created for nested prefabs.
+    nested: Vec<Entity>,
+}
+
 impl<'a, T> System<'a> for PrefabLoaderSystem<T>
 where
     T: PrefabData<'a> + Send + Sync + 'static,
@@ -77,6 +125,7 @@ where
     #[allow(clippy::type_complexity)]
     type SystemData = (
         Entities<'a>,
+        ReadExpect<'a, Loader>,
         Write<'a, AssetStorage<Prefab<T>>>,
         ReadStorage<'a, Handle<Prefab<T>>>,
         Read<'a, Time>,
@@ -93,6 +142,7 @@ where
 
         let (
             entities,
+            loader,
             mut prefab_storage,
             prefab_handles,
             time,
@@ -107,12 +157,18 @@ where
             |mut d| {
                 d.tag = Some(self.next_tag);
                 self.next_tag += 1;
-                if !d.loading()
-                    && !d
+                if !d.loading() {
+                    let sub_assets = d
                         .load_sub_assets(&mut prefab_system_data)
-                        .with_context(|_| format_err!("Failed starting sub asset loading"))?
-                {
-                    return Ok(ProcessingState::Loaded(d));
+                        .with_context(|_| format_err!("Failed starting sub asset loading"))?;
+                    let nested = d
+                        .request_nested(&mut self.next_nested_load, &mut self.nested_loads)
+                        .with_context(|_| format_err!("Failed starting nested prefab loading"))?;
+                    if !sub_assets && !nested {
+                        return Ok(ProcessingState::Loaded(d));
+                    }
+                } else if !d.resolve_nested(&mut self.started_nested_loads) {
+                    return Ok(ProcessingState::Loading(d));
                 }
                 match d.progress().complete() {
                     Completion::Complete => Ok(ProcessingState::Loaded(d)),
@@ -127,6 +183,13 @@ where
             &**pool,
             strategy,
         );
+        // Prefabs requesting nested loads have been requeued and pick up their handles
+        // during the next `process`.
+        self.started_nested_loads.clear();
+        for load in self.nested_loads.drain(..) {
+            let (id, handle) = load.start(&loader, &prefab_storage);
+            self.started_nested_loads.insert(id, handle);
+        }
         prefab_handles
             .channel()
             .read(&mut self.insert_reader)
@@ -135,58 +198,64 @@ where
                     self.to_process.add(*id);
                 }
             });
+        let mut instantiation = Instantiation {
+            prefab_storage: &prefab_storage,
+            entities: &entities,
+            parents: &mut parents,
+            tags: &mut tags,
+            prefab_system_data: &mut prefab_system_data,
+            instantiating: Vec::new(),
+        };
+
+        if self.reload_instances {
+            self.instances.retain(|instance| {
+                let root = instance.entities[0];
+                entities.is_alive(root)
+                    && prefab_handles.get(root).map(Handle::id) == Some(instance.handle)
+            });
+            for instance in &mut self.instances {
+                let handle = prefab_handles
+                    .get(instance.entities[0])
+                    .expect("Unreachable: Instances without handle have been removed");
+                let version = match prefab_storage.get_version(handle) {
+                    Some(version) if version != instance.version => version,
+                    _ => continue,
+                };
+                let len = prefab_storage
+                    .get(handle)
+                    .map_or(1, |prefab| prefab.entities.len().max(1));
+                for entity in instance
+                    .nested
+                    .drain(..)
+                    .chain(instance.entities.drain(len.min(instance.entities.len())..))
+                {
+                    // Entities may have been deleted in the meantime.
+                    let _ = entities.delete(entity);
+                }
+                instance.entities = instantiation.instantiate(
+                    instance.entities[0],
+                    handle,
+                    &instance.entities,
+                    &mut instance.nested,
+                );
+                instance.version = version;
+            }
+        }
+
         self.finished.clear();
         for (root_entity, handle, _) in (&*entities, &prefab_handles, &self.to_process).join() {
-            if let Some(prefab) = prefab_storage.get(handle) {
+            if let Some(version) = prefab_storage.get_version(handle) {
                 self.finished.push(root_entity);
-                // create entities
-                self.entities.clear();
-                self.entities.push(root_entity);
-
-                let mut children = HashMap::new();
-                for entity_data in prefab.entities.iter().skip(1) {
-                    let new_entity = entities.create();
-                    self.entities.push(new_entity);
-                    if let Some(parent) = entity_data.parent {
-                        parents
-                            .insert(
-                                new_entity,
-                                Parent {
-                                    entity: self.entities[parent],
-                                },
-                            )
-                            .expect("Unable to insert `Parent` for prefab");
-
-                        children
-                            .entry(parent)
-                            .or_insert_with(Vec::new)
-                            .push(new_entity);
-                    }
-                    tags.insert(
-                        new_entity,
-                        PrefabTag::new(
-                            prefab.tag.expect(
-                                "Unreachable: Every loaded prefab should have a `PrefabTag`",
-                            ),
-                        ),
-                    )
-                    .expect("Unable to insert `PrefabTag` for prefab entity");
-                }
-                // create components
-                for (index, entity_data) in prefab.entities.iter().enumerate() {
-                    if let Some(ref prefab_data) = &entity_data.data {
-                        prefab_data
-                            .add_to_entity(
-                                self.entities[index],
-                                &mut prefab_system_data,
-                                &self.entities,
-                                children
-                                    .get(&index)
-                                    .map(|children| &children[..])
-                                    .unwrap_or(&[]),
-                            )
-                            .expect("Unable to add prefab system data to entity");
-                    }
+                let mut nested = Vec::new();
+                let created =
+                    instantiation.instantiate(root_entity, handle, &[root_entity], &mut nested);
+                if self.reload_instances {
+                    self.instances.push(Instance {
+                        handle: handle.id(),
+                        version,
+                        entities: created,
+                        nested,
+                    });
                 }
             }
         }
@@ -196,3 +265,126 @@ where
         }
     }
 }
+
+/// Creates entities from prefabs.
+struct Instantiation<'s, 'a, T>
+where
+    T: PrefabData<'a> + Send + Sync + 'static,
+{
+    prefab_storage: &'s AssetStorage<Prefab<T>>,
+    entities: &'s EntitiesRes,
+    parents: &'s mut WriteStorage<'a, Parent>,
+    tags: &'s mut WriteStorage<'a, PrefabTag<T>>,
+    prefab_system_data: &'s mut T::SystemData,
+    /// The prefabs which are being instantiated further up, to stop prefabs which
+    /// (through handles set in code) are nested in themselves.
+    instantiating: Vec<u32>,
+}
+
+impl<'s, 'a, T> Instantiation<'s, 'a, T>
+where
+    T: PrefabData<'a> + Send + Sync + 'static,
+{
+    /// Creates the entities of the prefab below `root_entity`, followed by the prefabs
+    /// nested in it, and returns the entities of the prefab.
+    ///
+    /// The entities in `existing` are reused for the prefab entities with the same index,
+    /// unless they have been deleted. All entities created for nested prefabs are added to
+    /// `nested`.
+    fn instantiate(
+        &mut self,
+        root_entity: Entity,
+        handle: &Handle<Prefab<T>>,
+        existing: &[Entity],
+        nested: &mut Vec<Entity>,
+    ) -> Vec<Entity> {
+        let prefab = match self.prefab_storage.get(handle) {
+            Some(prefab) => prefab,
+            None => {
+                error!("Nested prefab (handle id: {:?}) is not loaded", handle.id());
+                return vec![root_entity];
+            }
+        };
+        if self.instantiating.contains(&handle.id()) {
+            error!(
+                "Prefab (handle id: {:?}) is nested in itself, skipping it",
+                handle.id()
+            );
+            return vec![root_entity];
+        }
+        self.instantiating.push(handle.id());
+        let tag = prefab
+            .tag
+            .expect("Unreachable: Every loaded prefab should have a `PrefabTag`");
+
+        // create entities
+        let mut created = vec![root_entity];
+        let mut children = HashMap::new();
+        for (index, entity_data) in prefab.entities.iter().enumerate().skip(1) {
+            let new_entity = existing
+                .get(index)
+                .cloned()
+                .filter(|entity| self.entities.is_alive(*entity))
+                .unwrap_or_else(|| self.entities.create());
+            created.push(new_entity);
+            if let Some(parent) = entity_data.parent {
+                self.parents
+                    .insert(
+                        new_entity,
+                        Parent {
+                            entity: created[parent],
+                        },
+                    )
+                    .expect("Unable to insert `Parent` for prefab");
+
+                children
+                    .entry(parent)
+                    .or_insert_with(Vec::new)
+                    .push(new_entity);
+            } else {
+                self.parents.remove(new_entity);
+            }
+            self.tags
+                .insert(new_entity, PrefabTag::new(tag))
+                .expect("Unable to insert `PrefabTag` for prefab entity");
+        }
+        // create components
+        for (index, entity_data) in prefab.entities.iter().enumerate() {
+            if let Some(ref prefab_data) = &entity_data.data {
+                prefab_data
+                    .add_to_entity(
+                        created[index],
+                        self.prefab_system_data,
+                        &created,
+                        children
+                            .get(&index)
+                            .map(|children| &children[..])
+                            .unwrap_or(&[]),
+                    )
+                    .expect("Unable to add prefab system data to entity");
+            }
+        }
+        // create nested prefabs
+        for (index, entity_data) in prefab.entities.iter().enumerate() {
+            if let Some(nested_handle) = entity_data.prefab.as_ref().and_then(|p| p.get_handle()) {
+                let nested_root = self.entities.create();
+                self.parents
+                    .insert(
+                        nested_root,
+                        Parent {
+                            entity: created[index],
+                        },
+                    )
+                    .expect("Unable to insert `Parent` for nested prefab");
+                self.tags
+                    .insert(nested_root, PrefabTag::new(tag))
+                    .expect("Unable to insert `PrefabTag` for nested prefab");
+                let nested_entities =
+                    self.instantiate(nested_root, nested_handle, &[nested_root], nested);
+                nested.extend(nested_entities);
+            }
+        }
+        self.instantiating.pop();
+        created
+    }
+}
@@ -1,6 +1,11 @@
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc,
+use std::{
+    cmp::Reverse,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 use amethyst_error::Error;
@@ -41,12 +46,104 @@ impl Progress for () {
     fn create_tracker(self) {}
 }
 
+/// Measurements taken while loading a single asset, see `Tracker::record`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LoadStats {
+    /// Number of bytes read from the `Source`.
+    pub bytes: u64,
+    /// Time the load waited for a worker thread.
+    pub queued: Duration,
+    /// Time spent reading from the `Source`.
+    pub source: Duration,
+    /// Time spent in the `Format`, excluding reads from the `Source`.
+    pub import: Duration,
+    /// Time spent processing the data into the asset, e.g. in a `Processor`.
+    pub process: Duration,
+}
+
+impl LoadStats {
+    /// Total time spent working on the asset, i.e. without the time spent in the queue.
+    pub fn total(&self) -> Duration {
+        self.source + self.import + self.process
+    }
+
+    fn add(&mut self, other: &LoadStats) {
+        self.bytes += other.bytes;
+        self.queued += other.queued;
+        self.source += other.source;
+        self.import += other.import;
+        self.process += other.process;
+    }
+}
+
+/// The `LoadStats` of a single asset.
+#[derive(Clone, Debug)]
+pub struct AssetLoadStats {
+    /// The `Asset::NAME` of the asset's type.
+    pub asset_type_name: &'static str,
+    /// The name the asset was loaded with.
+    pub asset_name: String,
+    /// The measurements.
+    pub stats: LoadStats,
+}
+
+/// Summary of the assets tracked by a `ProgressCounter`, returned by `ProgressCounter::report`.
+///
+/// The `Display` implementation prints the totals and the slowest assets.
+#[derive(Clone, Debug, Default)]
+pub struct LoadReport {
+    /// Sum of the measurements of all assets.
+    pub total: LoadStats,
+    /// The measurements of each asset, slowest first.
+    pub assets: Vec<AssetLoadStats>,
+}
+
+impl LoadReport {
+    /// Number of assets listed by the `Display` implementation.
+    const SLOWEST: usize = 10;
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Loaded {} assets, {} bytes in {:?} (source: {:?}, import: {:?}, process: {:?}, queued: {:?})",
+            self.assets.len(),
+            self.total.bytes,
+            self.total.total(),
+            self.total.source,
+            self.total.import,
+            self.total.process,
+            self.total.queued,
+        )?;
+        for asset in self.assets.iter().take(Self::SLOWEST) {
+            writeln!(
+                f,
+                "  {:?} {:?}: {} bytes in {:?} (source: {:?}, import: {:?}, process: {:?})",
+                asset.asset_type_name,
+                asset.asset_name,
+                asset.stats.bytes,
+                asset.stats.total(),
+                asset.stats.source,
+                asset.stats.import,
+                asset.stats.process,
+            )?;
+        }
+        Ok(())
+    }
+}
+
 /// A progress tracker which is passed to the `Loader`
 /// in order to check how many assets are loaded.
+///
+/// Besides counting assets, it collects the `LoadStats` of every finished asset,
+/// see `bytes_loaded` and `report`.
 #[derive(Default, Debug)]
 pub struct ProgressCounter {
     errors: Arc<Mutex<Vec<AssetErrorMeta>>>,
+    stats: Arc<Mutex<Vec<AssetLoadStats>>>,
     num_assets: usize,
+    num_cancelled: Arc<AtomicUsize>,
     num_failed: Arc<AtomicUsize>,
     num_loading: Arc<AtomicUsize>,
 }
@@ -68,6 +165,14 @@ impl ProgressCounter {
         self.num_assets
     }
 
+    /// Returns the number of assets whose loading was cancelled, because all handles
+    /// to them were dropped before they finished loading.
+    ///
+    /// Cancelled assets count neither as failed nor as finished.
+    pub fn num_cancelled(&self) -> usize {
+        self.num_cancelled.load(Ordering::Relaxed)
+    }
+
     /// Returns the number of assets that have failed.
     pub fn num_failed(&self) -> usize {
         self.num_failed.load(Ordering::Relaxed)
@@ -80,7 +185,7 @@ impl ProgressCounter {
 
     /// Returns the number of assets that have successfully loaded.
     pub fn num_finished(&self) -> usize {
-        self.num_assets - self.num_loading() - self.num_failed()
+        self.num_assets - self.num_loading() - self.num_failed() - self.num_cancelled()
     }
 
     /// Returns `Completion::Complete` if all tracked assets are finished.
@@ -99,6 +204,27 @@ impl ProgressCounter {
     pub fn is_complete(&self) -> bool {
         self.complete() == Completion::Complete
     }
+
+    /// Returns the number of bytes read from sources for the assets finished so far.
+    pub fn bytes_loaded(&self) -> u64 {
+        self.stats
+            .lock()
+            .iter()
+            .map(|asset| asset.stats.bytes)
+            .sum()
+    }
+
+    /// Summarizes the `LoadStats` of all assets finished so far.
+    pub fn report(&self) -> LoadReport {
+        let mut assets = self.stats.lock().clone();
+        assets.sort_by_key(|asset| Reverse(asset.stats.total()));
+        let mut total = LoadStats::default();
+        for asset in &assets {
+            total.add(&asset.stats);
+        }
+
+        LoadReport { total, assets }
+    }
 }
 
 impl<'a> Progress for &'a mut ProgressCounter {
@@ -110,12 +236,16 @@ impl<'a> Progress for &'a mut ProgressCounter {
 
     fn create_tracker(self) -> Self::Tracker {
         let errors = self.errors.clone();
+        let stats = self.stats.clone();
+        let num_cancelled = self.num_cancelled.clone();
         let num_failed = self.num_failed.clone();
         let num_loading = self.num_loading.clone();
         num_loading.fetch_add(1, Ordering::Relaxed);
 
         ProgressCounterTracker {
             errors,
+            stats,
+            num_cancelled,
             num_failed,
             num_loading,
         }
@@ -126,11 +256,21 @@ impl<'a> Progress for &'a mut ProgressCounter {
 #[derive(Default, Debug)]
 pub struct ProgressCounterTracker {
     errors: Arc<Mutex<Vec<AssetErrorMeta>>>,
+    stats: Arc<Mutex<Vec<AssetLoadStats>>>,
+    num_cancelled: Arc<AtomicUsize>,
     num_failed: Arc<AtomicUsize>,
     num_loading: Arc<AtomicUsize>,
 }
 
 impl Tracker for ProgressCounterTracker {
+    fn record(&mut self, asset_type_name: &'static str, asset_name: &str, stats: &LoadStats) {
+        self.stats.lock().push(AssetLoadStats {
+            asset_type_name,
+            asset_name: asset_name.to_owned(),
+            stats: *stats,
+        });
+    }
+
     fn success(self: Box<Self>) {
         self.num_loading.fetch_sub(1, Ordering::Relaxed);
     }
@@ -155,6 +295,11 @@ impl Tracker for ProgressCounterTracker {
         // the assets that are still loading.
         self.num_loading.fetch_sub(1, Ordering::Relaxed);
     }
+
+    fn cancel(self: Box<Self>, _: u32, _: &'static str, _: String) {
+        self.num_cancelled.fetch_add(1, Ordering::Relaxed);
+        self.num_loading.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 #[derive(Debug)]
@@ -169,6 +314,11 @@ pub struct AssetErrorMeta {
 /// back to `Progress`.
 pub trait Tracker: Send + 'static {
     // TODO: maybe add handles as parameters?
+    /// Called with the measurements taken while loading the asset, right before
+    /// it is reported as finished. Does nothing by default.
+    fn record(&mut self, asset_type_name: &'static str, asset_name: &str, stats: &LoadStats) {
+        let _ = (asset_type_name, asset_name, stats);
+    }
     /// Called if the asset could be imported.
     fn success(self: Box<Self>);
     /// Called if the asset couldn't be imported to an error.
@@ -179,6 +329,12 @@ pub trait Tracker: Send + 'static {
         asset_name: String,
         error: Error,
     );
+    /// Called if the asset wasn't imported because all handles to it were dropped
+    /// before it finished loading. Treated like a success by default.
+    fn cancel(self: Box<Self>, handle_id: u32, asset_type_name: &'static str, asset_name: String) {
+        let _ = (handle_id, asset_type_name, asset_name);
+        self.success();
+    }
 }
 
 impl Tracker for () {
@@ -208,9 +364,11 @@ fn show_error(handle_id: u32, asset_type_name: &'static str, asset_name: &str, e
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use amethyst_error::Error;
 
-    use super::{Completion, Progress, ProgressCounter, Tracker};
+    use super::{Completion, LoadStats, Progress, ProgressCounter, Tracker};
 
     #[test]
     fn progress_counter_complete_returns_correct_completion_status_when_loading_or_complete() {
@@ -288,4 +446,65 @@ mod tests {
         tracker_2.success();
         assert_eq!(2, progress.num_finished());
     }
+
+    #[test]
+    fn progress_counter_reports_cancelled_assets_separately() {
+        let mut progress_counter = ProgressCounter::new();
+        let mut progress = &mut progress_counter;
+        progress.add_assets(2);
+        let tracker_0 = Box::new(progress.create_tracker());
+        let tracker_1 = Box::new(progress.create_tracker());
+
+        // 1 cancelled, 1 loading
+        tracker_0.cancel(1, "AssetType", String::from("test.asset"));
+        assert_eq!(1, progress.num_cancelled());
+        assert_eq!(0, progress.num_failed());
+        assert_eq!(Completion::Loading, progress.complete());
+
+        // 1 cancelled, 1 success
+        tracker_1.success();
+        assert_eq!(1, progress.num_finished());
+        assert_eq!(Completion::Complete, progress.complete());
+        assert!(progress.errors().is_empty());
+    }
+
+    #[test]
+    fn progress_counter_reports_load_stats_slowest_first() {
+        let mut progress_counter = ProgressCounter::new();
+        let mut progress = &mut progress_counter;
+        progress.add_assets(2);
+        let mut tracker_0 = Box::new(progress.create_tracker());
+        let mut tracker_1 = Box::new(progress.create_tracker());
+
+        tracker_0.record(
+            "Mesh",
+            "small.obj",
+            &LoadStats {
+                bytes: 10,
+                source: Duration::from_millis(1),
+                ..Default::default()
+            },
+        );
+        tracker_0.success();
+        tracker_1.record(
+            "Texture",
+            "big.png",
+            &LoadStats {
+                bytes: 1000,
+                import: Duration::from_millis(5),
+                process: Duration::from_millis(2),
+                ..Default::default()
+            },
+        );
+        tracker_1.success();
+
+        assert_eq!(1010, progress.bytes_loaded());
+        let report = progress.report();
+        assert_eq!(Duration::from_millis(8), report.total.total());
+        assert_eq!("big.png", report.assets[0].asset_name);
+        assert_eq!("small.obj", report.assets[1].asset_name);
+        assert!(report
+            .to_string()
+            .starts_with("Loaded 2 assets, 1010 bytes"));
+    }
 }
@@ -0,0 +1,183 @@
+//! Prioritized queue of loads waiting for a worker thread.
+
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use rayon::ThreadPool;
+
+use crate::AssetId;
+
+/// The priority of a load started through the `Loader`.
+///
+/// Pending loads with a higher priority are picked up by the worker threads first;
+/// loads with the same priority are started in the order they were requested.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct LoadPriority(pub i32);
+
+impl LoadPriority {
+    /// Priority for assets which aren't needed any time soon, e.g. prefetched levels.
+    pub const LOW: LoadPriority = LoadPriority(-100);
+    /// The priority used by `Loader::load` and `Loader::load_from`.
+    pub const NORMAL: LoadPriority = LoadPriority(0);
+    /// Priority for assets which are needed as soon as possible, e.g. UI textures.
+    pub const HIGH: LoadPriority = LoadPriority(100);
+}
+
+struct Job {
+    asset: AssetId,
+    priority: LoadPriority,
+    sequence: u64,
+    work: Box<dyn FnOnce() + Send>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_sequence: u64,
+    pending: Vec<Job>,
+}
+
+/// Loads which have been requested, but not yet started by a worker thread.
+///
+/// Every job spawns one task onto the thread pool, but that task runs whichever job
+/// has the highest priority at the time, so priorities can still change while a job
+/// is waiting.
+#[derive(Clone, Default)]
+pub(crate) struct LoadQueue {
+    jobs: Arc<Mutex<Jobs>>,
+}
+
+impl LoadQueue {
+    /// Queues `work` for `asset` and spawns a task running the next job onto `pool`.
+    pub(crate) fn spawn<F>(
+        &self,
+        pool: &ThreadPool,
+        asset: AssetId,
+        priority: LoadPriority,
+        work: F,
+    ) where
+        F: FnOnce() + Send + 'static,
+    {
+        self.push(asset, priority, work);
+        let queue = self.clone();
+        pool.spawn(move || queue.run_next());
+    }
+
+    fn push<F>(&self, asset: AssetId, priority: LoadPriority, work: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut jobs = self.jobs.lock();
+        let sequence = jobs.next_sequence;
+        jobs.next_sequence += 1;
+        jobs.pending.push(Job {
+            asset,
+            priority,
+            sequence,
+            work: Box::new(work),
+        });
+    }
+
+    /// Runs the pending job with the highest priority, if any.
+    fn run_next(&self) {
+        let job = {
+            let mut jobs = self.jobs.lock();
+            let next = jobs
+                .pending
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| {
+                    a.priority
+                        .cmp(&b.priority)
+                        .then(b.sequence.cmp(&a.sequence))
+                })
+                .map(|(index, _)| index);
+            next.map(|index| jobs.pending.swap_remove(index))
+        };
+
+        if let Some(job) = job {
+            (job.work)();
+        }
+    }
+
+    /// Changes the priority of the pending load of `asset`.
+    ///
+    /// Returns `false` if there is no such load, e.g. because it already started.
+    pub(crate) fn set_priority(&self, asset: AssetId, priority: LoadPriority) -> bool {
+        let mut jobs = self.jobs.lock();
+        match jobs.pending.iter_mut().find(|job| job.asset == asset) {
+            Some(job) => {
+                job.priority = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the priority of the pending load of `asset`.
+    pub(crate) fn priority(&self, asset: AssetId) -> Option<LoadPriority> {
+        self.jobs
+            .lock()
+            .pending
+            .iter()
+            .find(|job| job.asset == asset)
+            .map(|job| job.priority)
+    }
+
+    /// Returns the number of loads which haven't been started yet.
+    pub(crate) fn len(&self) -> usize {
+        self.jobs.lock().pending.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use amethyst_core::ecs::VecStorage;
+
+    use crate::{Asset, AssetId};
+
+    use super::{LoadPriority, LoadQueue};
+
+    struct Mesh;
+    impl Asset for Mesh {
+        const NAME: &'static str = "Mesh";
+        type Data = ();
+        type HandleStorage = VecStorage<crate::Handle<Self>>;
+    }
+
+    #[test]
+    fn runs_highest_priority_first_and_keeps_order_otherwise() {
+        let queue = LoadQueue::default();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for (id, priority) in [
+            (0, LoadPriority::NORMAL),
+            (1, LoadPriority::LOW),
+            (2, LoadPriority::NORMAL),
+            (3, LoadPriority::HIGH),
+        ]
+        .iter()
+        .cloned()
+        {
+            let order = order.clone();
+            queue.push(AssetId::from_id::<Mesh>(id), priority, move || {
+                order.lock().push(id)
+            });
+        }
+
+        assert!(queue.set_priority(AssetId::from_id::<Mesh>(1), LoadPriority(50)));
+        assert_eq!(
+            Some(LoadPriority(50)),
+            queue.priority(AssetId::from_id::<Mesh>(1))
+        );
+        while queue.len() > 0 {
+            queue.run_next();
+        }
+
+        assert_eq!(vec![3, 1, 0, 2], *order.lock());
+        assert!(!queue.set_priority(AssetId::from_id::<Mesh>(1), LoadPriority::HIGH));
+        assert_eq!(None, queue.priority(AssetId::from_id::<Mesh>(1)));
+    }
+}
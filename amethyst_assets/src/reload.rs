@@ -87,6 +87,20 @@ impl HotReloadStrategy {
         }
     }
 
+    /// Causes hot reloads as soon as a source pushes a change notification
+    /// (see `Source::changes`). Assets from sources which can't notify about
+    /// changes are never reloaded with this strategy.
+    pub fn on_change() -> Self {
+        use std::u64::MAX;
+
+        HotReloadStrategy {
+            inner: HotReloadStrategyInner::OnChange {
+                generation: 0,
+                frame_number: MAX,
+            },
+        }
+    }
+
     /// Never do any hot-reloading.
     pub fn never() -> Self {
         HotReloadStrategy {
@@ -112,6 +126,7 @@ impl HotReloadStrategy {
         match self.inner {
             HotReloadStrategyInner::Every { frame_number, .. } => frame_number == current_frame,
             HotReloadStrategyInner::Trigger { frame_number, .. } => frame_number == current_frame,
+            HotReloadStrategyInner::OnChange { frame_number, .. } => frame_number == current_frame,
             HotReloadStrategyInner::Never => false,
         }
     }
@@ -134,6 +149,10 @@ enum HotReloadStrategyInner {
         triggered: bool,
         frame_number: u64,
     },
+    OnChange {
+        generation: u64,
+        frame_number: u64,
+    },
     Never,
 }
 
@@ -160,9 +179,13 @@ impl<'a, 'b> SystemDesc<'a, 'b, HotReloadSystem> for HotReloadSystemDesc {
 pub struct HotReloadSystem;
 
 impl<'a> System<'a> for HotReloadSystem {
-    type SystemData = (Read<'a, Time>, Write<'a, HotReloadStrategy>);
+    type SystemData = (
+        Read<'a, Time>,
+        Write<'a, HotReloadStrategy>,
+        Option<Read<'a, Loader>>,
+    );
 
-    fn run(&mut self, (time, mut strategy): Self::SystemData) {
+    fn run(&mut self, (time, mut strategy, loader): Self::SystemData) {
         #[cfg(feature = "profiler")]
         profile_scope!("hot_reload_system");
 
@@ -186,6 +209,17 @@ impl<'a> System<'a> for HotReloadSystem {
                     *last = Instant::now();
                 }
             }
+            HotReloadStrategyInner::OnChange {
+                ref mut generation,
+                ref mut frame_number,
+            } => {
+                if let Some(current) = loader.map(|loader| loader.changes_generation()) {
+                    if current != *generation {
+                        *frame_number = time.frame_number() + 1;
+                        *generation = current;
+                    }
+                }
+            }
             HotReloadStrategyInner::Never => {}
         }
     }
@@ -224,8 +258,13 @@ impl<D: 'static> Clone for Box<dyn Reload<D>> {
 
 /// An implementation of `Reload` which just stores the modification time
 /// and the path of the file.
+///
+/// If the source pushes change notifications (see `Source::changes`), those are
+/// used instead of polling the modification time. Changes are noticed from the
+/// generation of the change log taken by `new`, or the one set with `with_generation`.
 pub struct SingleFile<D> {
     format: Box<dyn Format<D>>,
+    generation: Option<u64>,
     modified: u64,
     path: String,
     source: Arc<dyn Source>,
@@ -241,17 +280,29 @@ impl<D: 'static> SingleFile<D> {
     ) -> Self {
         SingleFile {
             format,
+            generation: source.changes().map(|changes| changes.generation()),
             modified,
             path,
             source,
         }
     }
+
+    /// Sets the generation of the source's change log (see `SourceChanges::generation`)
+    /// to notice changes from.
+    ///
+    /// Pass the generation from before loading the file, otherwise changes made while
+    /// it was loaded are missed.
+    pub fn with_generation(mut self, generation: Option<u64>) -> Self {
+        self.generation = generation;
+        self
+    }
 }
 
 impl<D: 'static> Clone for SingleFile<D> {
     fn clone(&self) -> Self {
         SingleFile {
             format: self.format.clone(),
+            generation: self.generation,
             modified: self.modified,
             path: self.path.clone(),
             source: self.source.clone(),
@@ -261,7 +312,13 @@ impl<D: 'static> Clone for SingleFile<D> {
 
 impl<D: 'static> Reload<D> for SingleFile<D> {
     fn needs_reload(&self) -> bool {
-        self.modified != 0 && (self.source.modified(&self.path).unwrap_or(0) > self.modified)
+        match (self.generation, self.source.changes()) {
+            (Some(generation), Some(changes)) => changes.changed_since(&self.path, generation),
+            _ => {
+                self.modified != 0
+                    && (self.source.modified(&self.path).unwrap_or(0) > self.modified)
+            }
+        }
     }
 
     fn name(&self) -> String {
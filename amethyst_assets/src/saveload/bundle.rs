@@ -0,0 +1,92 @@
+use std::fmt;
+
+use amethyst_config::migration::Migrations;
+use amethyst_core::{
+    ecs::{
+        prelude::{Component, DispatcherBuilder, World},
+        saveload::ConvertSaveload,
+    },
+    SystemBundle,
+};
+use amethyst_error::Error;
+
+use crate::source::WritableSource;
+
+use super::{
+    registry::{Registry, SaveMarker},
+    system::SaveLoadSystem,
+};
+
+/// Bundle adding the system which saves and loads save games requested through
+/// `SaveGames`.
+///
+/// Save games are RON files holding the version set with `with_version` and all entities
+/// marked with a `SaveMarker`, with the components registered with `with_component`.
+/// Loading a save game written with a newer version fails. Save games written with an
+/// older version are upgraded by the migrations set with `with_migrations`, without them
+/// they are loaded as they are.
+pub struct SaveLoadBundle {
+    system: SaveLoadSystem,
+}
+
+impl SaveLoadBundle {
+    /// Creates a bundle writing save games to the given source.
+    pub fn new<S>(source: S) -> Self
+    where
+        S: WritableSource,
+    {
+        SaveLoadBundle {
+            system: SaveLoadSystem {
+                source: Box::new(source),
+                registry: Registry::new(),
+            },
+        }
+    }
+
+    /// Sets the version written into save games, `0` by default.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.system.registry.set_version(version);
+        self
+    }
+
+    /// Upgrades save games written with older versions by the given migrations before
+    /// loading them, and writes save games with their version.
+    ///
+    /// The migrations edit the whole save game, the components of the saved entities are
+    /// found in the `components` map of each entry of `entities`.
+    pub fn with_migrations(mut self, migrations: Migrations) -> Self {
+        self.system.registry.set_migrations(migrations);
+        self
+    }
+
+    /// Persists components of type `C` in save games, under the given name.
+    ///
+    /// Components referring to other entities have to implement `ConvertSaveload`
+    /// themselves, all other components only need to be `Clone` and (de)serializable.
+    pub fn with_component<C>(mut self, name: &str) -> Self
+    where
+        C: Component + ConvertSaveload<SaveMarker> + Send + Sync,
+        C::Data: 'static,
+        C::Error: fmt::Display,
+    {
+        self.system.registry.register::<C>(name.to_string());
+        self
+    }
+}
+
+impl fmt::Debug for SaveLoadBundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SaveLoadBundle").finish()
+    }
+}
+
+impl<'a, 'b> SystemBundle<'a, 'b> for SaveLoadBundle {
+    fn build(
+        self,
+        _world: &mut World,
+        dispatcher: &mut DispatcherBuilder<'a, 'b>,
+    ) -> Result<(), Error> {
+        dispatcher.add_thread_local(self.system);
+        Ok(())
+    }
+}
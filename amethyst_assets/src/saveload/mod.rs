@@ -0,0 +1,65 @@
+//! Saving the world as save games and loading it back.
+//!
+//! With the `saveload` feature, `SaveLoadBundle` adds a system which writes all entities
+//! marked with a `SaveMarker` to a `WritableSource` when a save is requested through
+//! the `SaveGames` resource, and restores them when a load is requested. Only the
+//! components registered with the bundle are persisted. The outcome of every request is
+//! sent as a `SaveLoadEvent`, which states receive as `StateEvent::SaveLoad`.
+//!
+//! ```rust,ignore
+//! let game_data = GameDataBuilder::default().with_bundle(
+//!     SaveLoadBundle::new(Directory::new("saves"))
+//!         .with_version(2)
+//!         .with_component::<Transform>("transform")
+//!         .with_component::<Health>("health"),
+//! )?;
+//!
+//! // In a state:
+//! world.create_entity().with(Health(100)).marked::<SaveMarker>().build();
+//! world.write_resource::<SaveGames>().save("slot_1.ron");
+//! ```
+
+#[cfg(feature = "saveload")]
+pub use self::{
+    bundle::SaveLoadBundle,
+    registry::{Persistent, SaveMarker, SaveMarkerAllocator},
+    system::SaveGames,
+};
+
+#[cfg(feature = "saveload")]
+mod bundle;
+#[cfg(feature = "saveload")]
+mod registry;
+#[cfg(feature = "saveload")]
+mod system;
+
+/// Outcome of a save or load requested through `SaveGames`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SaveLoadEvent {
+    /// The save game with the given name has been written.
+    Saved {
+        /// Name of the save game.
+        name: String,
+    },
+    /// The save game with the given name has been loaded into the world.
+    Loaded {
+        /// Name of the save game.
+        name: String,
+        /// Version the save game has been written with.
+        version: u32,
+    },
+    /// Writing the save game with the given name failed.
+    SaveFailed {
+        /// Name of the save game.
+        name: String,
+        /// Description of the failure.
+        error: String,
+    },
+    /// Loading the save game with the given name failed.
+    LoadFailed {
+        /// Name of the save game.
+        name: String,
+        /// Description of the failure.
+        error: String,
+    },
+}
@@ -0,0 +1,537 @@
+use std::{any::Any, fmt, marker::PhantomData};
+
+use serde::{
+    de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use amethyst_config::migration::Migrations;
+use amethyst_core::ecs::{
+    prelude::{Component, Entity, Join, World, WorldExt},
+    saveload::{ConvertSaveload, Marker, MarkerAllocator, SimpleMarker, SimpleMarkerAllocator},
+    BitSet,
+};
+use amethyst_error::{format_err, Error, ResultExt};
+
+/// Tag of the markers of entities which are part of save games.
+#[derive(Debug)]
+pub struct Persistent;
+
+/// Marker of the entities which are part of save games.
+///
+/// Add it with `MarkedBuilder::marked::<SaveMarker>()` when creating an entity.
+pub type SaveMarker = SimpleMarker<Persistent>;
+
+/// Allocator of the `SaveMarker`s.
+pub type SaveMarkerAllocator = SimpleMarkerAllocator<Persistent>;
+
+/// Component type which is persisted in save games, with its type erased.
+trait PersistedComponent: Send + Sync {
+    /// Converts the components of `entities` into their saved form, together with the
+    /// index of their entity.
+    fn save(
+        &self,
+        world: &World,
+        entities: &[Entity],
+        ids: &mut dyn FnMut(Entity) -> Option<SaveMarker>,
+    ) -> Result<Vec<(usize, Box<dyn erased_serde::Serialize>)>, Error>;
+
+    /// Removes the component from `entity`.
+    fn remove(&self, world: &World, entity: Entity);
+
+    /// Reads the saved form of the component.
+    fn parse(
+        &self,
+        data: &mut dyn erased_serde::Deserializer<'_>,
+    ) -> Result<Box<dyn Any>, erased_serde::Error>;
+
+    /// Converts the saved form read by `parse` back into the component.
+    fn convert(
+        &self,
+        data: Box<dyn Any>,
+        ids: &mut dyn FnMut(SaveMarker) -> Option<Entity>,
+    ) -> Result<Box<dyn Any>, Error>;
+
+    /// Inserts the component created by `convert` for `entity`.
+    fn insert(&self, world: &World, entity: Entity, component: Box<dyn Any>);
+}
+
+struct Persisted<C>(PhantomData<C>);
+
+impl<C> PersistedComponent for Persisted<C>
+where
+    C: Component + ConvertSaveload<SaveMarker> + Send + Sync,
+    C::Data: 'static,
+    C::Error: fmt::Display,
+{
+    fn save(
+        &self,
+        world: &World,
+        entities: &[Entity],
+        ids: &mut dyn FnMut(Entity) -> Option<SaveMarker>,
+    ) -> Result<Vec<(usize, Box<dyn erased_serde::Serialize>)>, Error> {
+        let storage = world.read_storage::<C>();
+        let mut saved = Vec::new();
+        for (index, &entity) in entities.iter().enumerate() {
+            if let Some(component) = storage.get(entity) {
+                let data = component
+                    .convert_into(&mut *ids)
+                    .map_err(|e| format_err!("{}", e))?;
+                saved.push((index, Box::new(data) as Box<dyn erased_serde::Serialize>));
+            }
+        }
+        Ok(saved)
+    }
+
+    fn remove(&self, world: &World, entity: Entity) {
+        world.write_storage::<C>().remove(entity);
+    }
+
+    fn parse(
+        &self,
+        data: &mut dyn erased_serde::Deserializer<'_>,
+    ) -> Result<Box<dyn Any>, erased_serde::Error> {
+        Ok(Box::new(erased_serde::deserialize::<C::Data>(data)?))
+    }
+
+    fn convert(
+        &self,
+        data: Box<dyn Any>,
+        ids: &mut dyn FnMut(SaveMarker) -> Option<Entity>,
+    ) -> Result<Box<dyn Any>, Error> {
+        let data = data
+            .downcast::<C::Data>()
+            .expect("Unreachable: Saved data has been parsed by the same component");
+        let component = C::convert_from(*data, ids).map_err(|e| format_err!("{}", e))?;
+        Ok(Box::new(component))
+    }
+
+    fn insert(&self, world: &World, entity: Entity, component: Box<dyn Any>) {
+        let component = component
+            .downcast::<C>()
+            .expect("Unreachable: Component has been converted by the same component");
+        world
+            .write_storage::<C>()
+            .insert(entity, *component)
+            .expect("Unreachable: Loaded entities are alive");
+    }
+}
+
+/// The component types persisted in save games, by name, the version of the save games
+/// written and the migrations upgrading older ones.
+pub(crate) struct Registry {
+    version: u32,
+    migrations: Option<Migrations>,
+    components: Vec<(String, Box<dyn PersistedComponent>)>,
+}
+
+impl Registry {
+    pub(crate) fn new() -> Self {
+        Registry {
+            version: 0,
+            migrations: None,
+            components: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    pub(crate) fn set_migrations(&mut self, migrations: Migrations) {
+        self.version = migrations.version();
+        self.migrations = Some(migrations);
+    }
+
+    pub(crate) fn register<C>(&mut self, name: String)
+    where
+        C: Component + ConvertSaveload<SaveMarker> + Send + Sync,
+        C::Data: 'static,
+        C::Error: fmt::Display,
+    {
+        self.components.retain(|(n, _)| *n != name);
+        self.components
+            .push((name, Box::new(Persisted::<C>(PhantomData))));
+    }
+
+    /// Writes all marked entities with their registered components as RON.
+    pub(crate) fn save(&self, world: &World) -> Result<Vec<u8>, Error> {
+        let markers = world.read_storage::<SaveMarker>();
+        let mut marked = (&*world.entities(), &markers)
+            .join()
+            .map(|(entity, marker)| (entity, *marker))
+            .collect::<Vec<_>>();
+        marked.sort_by_key(|(_, marker)| marker.id());
+        let entities = marked.iter().map(|(entity, _)| *entity).collect::<Vec<_>>();
+
+        let mut saved = marked
+            .into_iter()
+            .map(|(_, marker)| SavedEntity {
+                marker,
+                components: SavedComponents(Vec::new()),
+            })
+            .collect::<Vec<_>>();
+        let mut ids = |entity| markers.get(entity).cloned();
+        for (name, component) in &self.components {
+            for (index, data) in component
+                .save(world, &entities, &mut ids)
+                .with_context(|_| format_err!("Failed to save component {}", name))?
+            {
+                saved[index].components.0.push((name, data));
+            }
+        }
+
+        let file = SaveFile {
+            version: self.version,
+            entities: saved,
+        };
+        ron::ser::to_string_pretty(&file, Default::default())
+            .map(String::into_bytes)
+            .with_context(|_| format_err!("Failed to serialize save game"))
+    }
+
+    /// Loads a save game written by `save`, returning its version.
+    ///
+    /// Save games of older versions are upgraded by the migrations first, if there are any.
+    /// The whole save game is read before the world is changed, so the world stays as it
+    /// is if that fails. Marked entities are matched with the saved ones by their marker,
+    /// their registered components are replaced by the saved ones. Missing entities are
+    /// created, marked entities which aren't part of the save game are deleted.
+    pub(crate) fn load(&self, world: &World, bytes: &[u8]) -> Result<u32, Error> {
+        let (save, from) = match self.migrations {
+            Some(ref migrations) => {
+                let migrated = migrations
+                    .migrate(bytes)
+                    .with_context(|_| format_err!("Failed migrating save game"))?;
+                (self.parse(&migrated.data)?, migrated.from)
+            }
+            None => (self.parse(bytes)?, None),
+        };
+        self.apply(world, save.entities)?;
+        Ok(from.unwrap_or(save.version))
+    }
+
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedSave, Error> {
+        let mut d = ron::de::Deserializer::from_bytes(bytes)
+            .with_context(|_| format_err!("Failed deserializing save game"))?;
+        let save = SaveFileSeed(self)
+            .deserialize(&mut d)
+            .with_context(|_| format_err!("Failed parsing save game"))?;
+        d.end()
+            .with_context(|_| format_err!("Failed parsing save game"))?;
+        Ok(save)
+    }
+
+    /// Replaces the marked entities by the parsed ones.
+    fn apply(&self, world: &World, saved: Vec<ParsedEntity>) -> Result<(), Error> {
+        let entities = world.entities();
+        let mut markers = world.write_storage::<SaveMarker>();
+        let mut allocator = world.write_resource::<SaveMarkerAllocator>();
+
+        // Find the entities of all markers, creating the missing ones without marking them
+        // yet, so they can simply be deleted again if converting the components fails.
+        let mut created = Vec::new();
+        let mut ids = |marker: SaveMarker| {
+            let existing = allocator
+                .retrieve_entity_internal(marker.id())
+                .filter(|entity| entities.is_alive(*entity) && markers.contains(*entity));
+            let entity = existing
+                .or_else(|| {
+                    created
+                        .iter()
+                        .find(|(_, id)| *id == marker.id())
+                        .map(|(entity, _)| *entity)
+                })
+                .unwrap_or_else(|| {
+                    let entity = entities.create();
+                    created.push((entity, marker.id()));
+                    entity
+                });
+            Some(entity)
+        };
+        let mut loaded = Vec::with_capacity(saved.len());
+        let converted = || -> Result<(), Error> {
+            for entity in saved {
+                let retrieved = ids(entity.marker).expect("Unreachable: Markers always resolve");
+                let mut components = Vec::with_capacity(entity.components.len());
+                for (index, data) in entity.components {
+                    let (name, component) = &self.components[index];
+                    let component = component
+                        .convert(data, &mut ids)
+                        .with_context(|_| format_err!("Failed to load component {}", name))?;
+                    components.push((index, component));
+                }
+                loaded.push((retrieved, components));
+            }
+            Ok(())
+        };
+        let result = converted();
+        if let Err(e) = result {
+            for (entity, _) in created {
+                entities
+                    .delete(entity)
+                    .expect("Unreachable: Entities have just been created");
+            }
+            return Err(e);
+        }
+
+        let mut alive = BitSet::new();
+        for (entity, id) in created {
+            let marker = allocator.allocate(entity, Some(id));
+            markers
+                .insert(entity, marker)
+                .expect("Unreachable: Entities have just been created");
+            alive.add(entity.id());
+        }
+        for (entity, components) in loaded {
+            alive.add(entity.id());
+            for (_, component) in &self.components {
+                component.remove(world, entity);
+            }
+            for (index, component) in components {
+                self.components[index].1.insert(world, entity, component);
+            }
+        }
+        for (entity, _, _) in (&*entities, &markers, !&alive).join() {
+            entities
+                .delete(entity)
+                .expect("Unreachable: Joined entities are alive");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct SaveFile<'r> {
+    version: u32,
+    entities: Vec<SavedEntity<'r>>,
+}
+
+#[derive(Serialize)]
+struct SavedEntity<'r> {
+    marker: SaveMarker,
+    components: SavedComponents<'r>,
+}
+
+struct SavedComponents<'r>(Vec<(&'r str, Box<dyn erased_serde::Serialize>)>);
+
+impl Serialize for SavedComponents<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, data) in &self.0 {
+            map.serialize_entry(name, data)?;
+        }
+        map.end()
+    }
+}
+
+/// Save game read by `Registry::parse`, which hasn't been applied to the world yet.
+struct ParsedSave {
+    version: u32,
+    entities: Vec<ParsedEntity>,
+}
+
+struct ParsedEntity {
+    marker: SaveMarker,
+    /// The saved forms of the components, with the index of their registration.
+    components: Vec<(usize, Box<dyn Any>)>,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum FileField {
+    Version,
+    Entities,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum EntityField {
+    Marker,
+    Components,
+}
+
+struct SaveFileSeed<'r>(&'r Registry);
+
+impl<'de> DeserializeSeed<'de> for SaveFileSeed<'_> {
+    type Value = ParsedSave;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<ParsedSave, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("SaveFile", &["version", "entities"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for SaveFileSeed<'_> {
+    type Value = ParsedSave;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a save game")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<ParsedSave, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut version = None;
+        let mut entities = None;
+        while let Some(key) = map.next_key()? {
+            match key {
+                FileField::Version => {
+                    let v = map.next_value::<u32>()?;
+                    if v > self.0.version {
+                        return Err(de::Error::custom(format!(
+                            "Save game version {} is newer than the supported version {}",
+                            v, self.0.version
+                        )));
+                    }
+                    version = Some(v);
+                }
+                FileField::Entities => {
+                    if version.is_none() {
+                        return Err(de::Error::missing_field("version"));
+                    }
+                    entities = Some(map.next_value_seed(EntitiesSeed(self.0))?);
+                }
+            }
+        }
+        Ok(ParsedSave {
+            version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+            entities: entities.ok_or_else(|| de::Error::missing_field("entities"))?,
+        })
+    }
+}
+
+struct EntitiesSeed<'r>(&'r Registry);
+
+impl<'de> DeserializeSeed<'de> for EntitiesSeed<'_> {
+    type Value = Vec<ParsedEntity>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntitiesSeed<'_> {
+    type Value = Vec<ParsedEntity>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a sequence of saved entities")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut entities = Vec::new();
+        while let Some(entity) = seq.next_element_seed(EntitySeed(self.0))? {
+            entities.push(entity);
+        }
+        Ok(entities)
+    }
+}
+
+struct EntitySeed<'r>(&'r Registry);
+
+impl<'de> DeserializeSeed<'de> for EntitySeed<'_> {
+    type Value = ParsedEntity;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<ParsedEntity, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("SavedEntity", &["marker", "components"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntitySeed<'_> {
+    type Value = ParsedEntity;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a saved entity")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<ParsedEntity, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut marker = None;
+        let mut components = None;
+        while let Some(key) = map.next_key()? {
+            match key {
+                EntityField::Marker => marker = Some(map.next_value::<SaveMarker>()?),
+                EntityField::Components => {
+                    components = Some(map.next_value_seed(ComponentsSeed(self.0))?);
+                }
+            }
+        }
+        Ok(ParsedEntity {
+            marker: marker.ok_or_else(|| de::Error::missing_field("marker"))?,
+            components: components.unwrap_or_default(),
+        })
+    }
+}
+
+struct ComponentsSeed<'r>(&'r Registry);
+
+impl<'de> DeserializeSeed<'de> for ComponentsSeed<'_> {
+    type Value = Vec<(usize, Box<dyn Any>)>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ComponentsSeed<'_> {
+    type Value = Vec<(usize, Box<dyn Any>)>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a map of components")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut components = Vec::new();
+        while let Some(name) = map.next_key::<String>()? {
+            let index = self
+                .0
+                .components
+                .iter()
+                .position(|(n, _)| *n == name)
+                .ok_or_else(|| {
+                    de::Error::custom(format!("Component {} is not registered", name))
+                })?;
+            let data = map.next_value_seed(ComponentSeed(&*self.0.components[index].1))?;
+            components.push((index, data));
+        }
+        Ok(components)
+    }
+}
+
+struct ComponentSeed<'r>(&'r dyn PersistedComponent);
+
+impl<'de> DeserializeSeed<'de> for ComponentSeed<'_> {
+    type Value = Box<dyn Any>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Box<dyn Any>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut erased = <dyn erased_serde::Deserializer<'_>>::erase(deserializer);
+        self.0.parse(&mut erased).map_err(de::Error::custom)
+    }
+}
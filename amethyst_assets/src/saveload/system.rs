@@ -0,0 +1,463 @@
+use std::mem;
+
+use log::error;
+
+use amethyst_core::{
+    ecs::{
+        prelude::{RunNow, World, WorldExt},
+        saveload::MarkerAllocator,
+    },
+    shrev::EventChannel,
+};
+use amethyst_error::{format_err, Error, ResultExt};
+
+use crate::source::WritableSource;
+
+use super::{
+    registry::{Registry, SaveMarker, SaveMarkerAllocator},
+    SaveLoadEvent,
+};
+
+/// Resource through which saves and loads are requested.
+///
+/// Requests are handled in order by the system added with `SaveLoadBundle`, the outcome
+/// of each one is sent as a `SaveLoadEvent`.
+#[derive(Debug, Default)]
+pub struct SaveGames {
+    requests: Vec<Request>,
+}
+
+#[derive(Debug)]
+enum Request {
+    Save(String),
+    Load(String),
+}
+
+impl SaveGames {
+    /// Requests saving all marked entities under the given name, which is the path
+    /// of the save game in the source of the `SaveLoadBundle`.
+    pub fn save<N>(&mut self, name: N)
+    where
+        N: Into<String>,
+    {
+        self.requests.push(Request::Save(name.into()));
+    }
+
+    /// Requests loading the save game with the given name.
+    pub fn load<N>(&mut self, name: N)
+    where
+        N: Into<String>,
+    {
+        self.requests.push(Request::Load(name.into()));
+    }
+}
+
+/// Thread local system handling the requests of `SaveGames`, as it needs access to
+/// the storages of all registered components.
+pub(crate) struct SaveLoadSystem {
+    pub(crate) source: Box<dyn WritableSource>,
+    pub(crate) registry: Registry,
+}
+
+impl SaveLoadSystem {
+    fn save(&self, world: &World, name: &str) -> Result<(), Error> {
+        world
+            .write_resource::<SaveMarkerAllocator>()
+            .maintain(&world.entities(), &world.read_storage::<SaveMarker>());
+        let bytes = self.registry.save(world)?;
+        self.source
+            .store(name, &bytes)
+            .with_context(|_| format_err!("Failed to store save game {:?}", name))
+    }
+
+    fn load(&self, world: &World, name: &str) -> Result<u32, Error> {
+        let bytes = self
+            .source
+            .load(name)
+            .with_context(|_| format_err!("Failed to load save game {:?}", name))?;
+        world
+            .write_resource::<SaveMarkerAllocator>()
+            .maintain(&world.entities(), &world.read_storage::<SaveMarker>());
+        self.registry.load(world, &bytes)
+    }
+}
+
+/// Describes the error together with its causes.
+fn describe(error: &Error) -> String {
+    error
+        .causes()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+impl<'a> RunNow<'a> for SaveLoadSystem {
+    fn run_now(&mut self, world: &'a World) {
+        let requests = mem::take(&mut world.write_resource::<SaveGames>().requests);
+        for request in requests {
+            let event = match request {
+                Request::Save(name) => match self.save(world, &name) {
+                    Ok(()) => SaveLoadEvent::Saved { name },
+                    Err(e) => {
+                        let error = describe(&e);
+                        error!("Failed to save {:?}: {}", name, error);
+                        SaveLoadEvent::SaveFailed { name, error }
+                    }
+                },
+                Request::Load(name) => match self.load(world, &name) {
+                    Ok(version) => SaveLoadEvent::Loaded { name, version },
+                    Err(e) => {
+                        let error = describe(&e);
+                        error!("Failed to load {:?}: {}", name, error);
+                        SaveLoadEvent::LoadFailed { name, error }
+                    }
+                },
+            };
+            world
+                .write_resource::<EventChannel<SaveLoadEvent>>()
+                .single_write(event);
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        world.register::<SaveMarker>();
+        world
+            .entry::<SaveMarkerAllocator>()
+            .or_insert_with(SaveMarkerAllocator::default);
+        world
+            .entry::<SaveGames>()
+            .or_insert_with(SaveGames::default);
+        world
+            .entry::<EventChannel<SaveLoadEvent>>()
+            .or_insert_with(EventChannel::default);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use parking_lot::Mutex;
+    use serde::{Deserialize, Serialize};
+
+    use amethyst_config::migration::{Migrations, Value};
+    use amethyst_core::{
+        ecs::{
+            prelude::{Builder, Component, Join, RunNow, VecStorage, World, WorldExt},
+            saveload::MarkedBuilder,
+        },
+        shrev::EventChannel,
+        Named,
+    };
+    use amethyst_error::{format_err, Error};
+
+    use crate::source::{Source, WritableSource};
+
+    use super::{
+        super::{registry::Registry, SaveLoadEvent},
+        SaveGames, SaveLoadSystem, SaveMarker,
+    };
+
+    #[derive(Clone, Default)]
+    struct Memory(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+    impl Source for Memory {
+        fn modified(&self, _: &str) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            self.0
+                .lock()
+                .get(path)
+                .cloned()
+                .ok_or_else(|| format_err!("{} not found", path))
+        }
+    }
+
+    impl WritableSource for Memory {
+        fn store(&self, path: &str, bytes: &[u8]) -> Result<(), Error> {
+            self.0.lock().insert(path.to_string(), bytes.to_vec());
+            Ok(())
+        }
+    }
+
+    fn system(source: &Memory, version: u32) -> SaveLoadSystem {
+        let mut registry = Registry::new();
+        registry.set_version(version);
+        registry.register::<Named>("named".to_string());
+        SaveLoadSystem {
+            source: Box::new(source.clone()),
+            registry,
+        }
+    }
+
+    fn run(system: &mut SaveLoadSystem, world: &mut World) -> Vec<SaveLoadEvent> {
+        let mut reader = world
+            .write_resource::<EventChannel<SaveLoadEvent>>()
+            .register_reader();
+        system.run_now(world);
+        world.maintain();
+        world
+            .read_resource::<EventChannel<SaveLoadEvent>>()
+            .read(&mut reader)
+            .cloned()
+            .collect()
+    }
+
+    fn names(world: &World) -> Vec<(String, bool)> {
+        let markers = world.read_storage::<SaveMarker>();
+        let mut names = (&world.entities(), &world.read_storage::<Named>())
+            .join()
+            .map(|(entity, named)| (named.name.to_string(), markers.contains(entity)))
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn saves_and_restores_marked_entities() {
+        let source = Memory::default();
+        let mut world = World::new();
+        let mut system = system(&source, 1);
+        system.setup(&mut world);
+        world.register::<Named>();
+
+        let knight = world
+            .create_entity()
+            .with(Named::new("knight"))
+            .marked::<SaveMarker>()
+            .build();
+        let dragon = world
+            .create_entity()
+            .with(Named::new("dragon"))
+            .marked::<SaveMarker>()
+            .build();
+        world.create_entity().with(Named::new("camera")).build();
+
+        world.write_resource::<SaveGames>().save("slot.ron");
+        assert_eq!(
+            vec![SaveLoadEvent::Saved {
+                name: "slot.ron".to_string()
+            }],
+            run(&mut system, &mut world)
+        );
+
+        world
+            .write_storage::<Named>()
+            .insert(knight, Named::new("ghost"))
+            .unwrap();
+        world.delete_entity(dragon).unwrap();
+        world
+            .create_entity()
+            .with(Named::new("princess"))
+            .marked::<SaveMarker>()
+            .build();
+        world.maintain();
+
+        world.write_resource::<SaveGames>().load("slot.ron");
+        assert_eq!(
+            vec![SaveLoadEvent::Loaded {
+                name: "slot.ron".to_string(),
+                version: 1,
+            }],
+            run(&mut system, &mut world)
+        );
+        assert_eq!(
+            vec![
+                ("camera".to_string(), false),
+                ("dragon".to_string(), true),
+                ("knight".to_string(), true),
+            ],
+            names(&world)
+        );
+        assert_eq!(
+            "knight",
+            world.read_storage::<Named>().get(knight).unwrap().name
+        );
+    }
+
+    #[test]
+    fn refuses_newer_save_games() {
+        let source = Memory::default();
+        let mut world = World::new();
+        let mut newer = system(&source, 2);
+        newer.setup(&mut world);
+        world.register::<Named>();
+        world
+            .create_entity()
+            .with(Named::new("knight"))
+            .marked::<SaveMarker>()
+            .build();
+        world.write_resource::<SaveGames>().save("slot.ron");
+        run(&mut newer, &mut world);
+
+        let mut older = system(&source, 1);
+        world.write_resource::<SaveGames>().load("slot.ron");
+        match run(&mut older, &mut world).as_slice() {
+            [SaveLoadEvent::LoadFailed { error, .. }] => assert!(
+                error.contains("Save game version 2 is newer than the supported version 1"),
+                "{}",
+                error
+            ),
+            events => panic!("Unexpected events: {:?}", events),
+        }
+        assert_eq!(vec![("knight".to_string(), true)], names(&world));
+    }
+
+    fn failed(events: &[SaveLoadEvent]) -> &str {
+        match events {
+            [SaveLoadEvent::LoadFailed { error, .. }] => error,
+            events => panic!("Unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn failed_loads_leave_the_world_unchanged() {
+        let source = Memory::default();
+        let mut world = World::new();
+        let mut system = system(&source, 1);
+        system.setup(&mut world);
+        world.register::<Named>();
+        world
+            .create_entity()
+            .with(Named::new("knight"))
+            .marked::<SaveMarker>()
+            .build();
+
+        source
+            .store(
+                "broken.ron",
+                br#"(
+                version: 1,
+                entities: [
+                    (marker: (7), components: {"named": (name: "dragon")}),
+                    (marker: (8), components: {"health": 100}),
+                ],
+            )"#,
+            )
+            .unwrap();
+        world.write_resource::<SaveGames>().load("broken.ron");
+        let events = run(&mut system, &mut world);
+        let error = failed(&events);
+        assert!(
+            error.contains("Component health is not registered"),
+            "{}",
+            error
+        );
+        assert_eq!(vec![("knight".to_string(), true)], names(&world));
+        assert_eq!(1, world.entities().join().count());
+    }
+
+    #[test]
+    fn migrates_older_save_games() {
+        let source = Memory::default();
+        let mut world = World::new();
+        let mut system = system(&source, 0);
+        system
+            .registry
+            .set_migrations(Migrations::new(1).with(0, |save| {
+                if let Some(Value::Seq(entities)) = save.field_mut("entities") {
+                    for entity in entities {
+                        if let Some(Value::Map(components)) = entity.field_mut("components") {
+                            for (name, _) in components {
+                                if *name == Value::String("name".to_string()) {
+                                    *name = Value::String("named".to_string());
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }));
+        system.setup(&mut world);
+        world.register::<Named>();
+
+        source
+            .store(
+                "old.ron",
+                br#"(
+                version: 0,
+                entities: [(marker: (3), components: {"name": (name: "knight")})],
+            )"#,
+            )
+            .unwrap();
+        world.write_resource::<SaveGames>().load("old.ron");
+        assert_eq!(
+            vec![SaveLoadEvent::Loaded {
+                name: "old.ron".to_string(),
+                version: 0,
+            }],
+            run(&mut system, &mut world)
+        );
+        assert_eq!(vec![("knight".to_string(), true)], names(&world));
+    }
+
+    #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+    struct Seed(u64);
+
+    impl Component for Seed {
+        type Storage = VecStorage<Self>;
+    }
+
+    #[test]
+    fn loads_large_integers_with_migrations() {
+        let source = Memory::default();
+        let mut world = World::new();
+        let mut system = system(&source, 0);
+        system.registry.register::<Seed>("seed".to_string());
+        system
+            .registry
+            .set_migrations(Migrations::new(1).with(0, |_| Ok(())));
+        system.setup(&mut world);
+        world.register::<Named>();
+        world.register::<Seed>();
+        let entity = world
+            .create_entity()
+            .with(Seed(u64::MAX))
+            .marked::<SaveMarker>()
+            .build();
+
+        world.write_resource::<SaveGames>().save("slot.ron");
+        run(&mut system, &mut world);
+        world
+            .write_storage::<Seed>()
+            .insert(entity, Seed(0))
+            .unwrap();
+        world.write_resource::<SaveGames>().load("slot.ron");
+        assert_eq!(
+            vec![SaveLoadEvent::Loaded {
+                name: "slot.ron".to_string(),
+                version: 1,
+            }],
+            run(&mut system, &mut world)
+        );
+        assert_eq!(
+            Some(&Seed(u64::MAX)),
+            world.read_storage::<Seed>().get(entity)
+        );
+
+        source
+            .store(
+                "old.ron",
+                br#"(entities: [(marker: (7), components: {"seed": (18446744073709551615)})])"#,
+            )
+            .unwrap();
+        world.write_resource::<SaveGames>().load("old.ron");
+        assert_eq!(
+            vec![SaveLoadEvent::Loaded {
+                name: "old.ron".to_string(),
+                version: 0,
+            }],
+            run(&mut system, &mut world)
+        );
+        let seeds = world.read_storage::<Seed>();
+        assert_eq!(
+            vec![&Seed(u64::MAX)],
+            (&world.entities(), &seeds)
+                .join()
+                .map(|(_, seed)| seed)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,534 @@
+//! Archive-backed sources.
+//!
+//! Besides zip files (behind the `zip` feature), this module supports a
+//! minimal "pak" container which doesn't need any additional dependency.
+//! A pak file has the following little-endian layout:
+//!
+//! ```text
+//! magic    b"APAK"
+//! version  u32        (currently 1)
+//! count    u32
+//! count times:
+//!     path_len  u16
+//!     path      [u8; path_len]  (UTF-8, `/` separated)
+//!     modified  u64             (seconds since `UNIX_EPOCH`)
+//!     offset    u64             (absolute offset of the data)
+//!     len       u64
+//! data
+//! ```
+//!
+//! Pak files can be written with `PakBuilder`.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use fnv::FnvHashMap;
+use parking_lot::Mutex;
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+use amethyst_error::{format_err, Error, ResultExt};
+
+use crate::{
+    error,
+    source::{not_found, Source},
+};
+
+const PAK_MAGIC: &[u8; 4] = b"APAK";
+const PAK_VERSION: u32 = 1;
+#[cfg(feature = "zip")]
+const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+/// A reader the archive can seek around in.
+pub trait ArchiveReader: Read + Seek + Send + 'static {}
+
+impl<T> ArchiveReader for T where T: Read + Seek + Send + 'static {}
+
+/// Source reading assets out of a single archive file.
+///
+/// The archive kind is detected from its header; pak files are always supported,
+/// zip files require the `zip` feature. The table of contents is read once when the
+/// archive is opened, so `modified` is answered from the entry timestamps without
+/// touching the underlying file.
+///
+/// ```rust,ignore
+/// let archive = Archive::open("assets.pak")?;
+/// loader.add_source("base", archive);
+/// ```
+pub struct Archive {
+    name: String,
+    entries: FnvHashMap<String, Entry>,
+    reader: Mutex<Reader>,
+}
+
+struct Entry {
+    modified: u64,
+    location: Location,
+}
+
+enum Location {
+    #[cfg(feature = "zip")]
+    Zip(usize),
+    Pak {
+        offset: u64,
+        len: u64,
+    },
+}
+
+enum Reader {
+    #[cfg(feature = "zip")]
+    Zip(zip::ZipArchive<Box<dyn ArchiveReader>>),
+    Pak(Box<dyn ArchiveReader>),
+}
+
+impl Archive {
+    /// Opens the archive file at `path`.
+    pub fn open<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|_| format_err!("Failed to open archive {:?}", path))
+            .with_context(|_| error::Error::Source)?;
+
+        Self::from_reader(path.display().to_string(), BufReader::new(file))
+    }
+
+    /// Reads an archive from an arbitrary reader. `name` is only used for diagnostics.
+    pub fn from_reader<N, R>(name: N, mut reader: R) -> Result<Self, Error>
+    where
+        N: Into<String>,
+        R: ArchiveReader,
+    {
+        let name = name.into();
+        let mut magic = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .and_then(|_| reader.seek(SeekFrom::Start(0)))
+            .with_context(|_| format_err!("Failed to read header of archive {:?}", name))
+            .with_context(|_| error::Error::Source)?;
+
+        match &magic {
+            PAK_MAGIC => Self::read_pak(name, Box::new(reader)),
+            #[cfg(feature = "zip")]
+            ZIP_MAGIC => Self::read_zip(name, Box::new(reader)),
+            _ => Err(format_err!("Unsupported archive format for {:?}", name))
+                .with_context(|_| error::Error::Source),
+        }
+    }
+
+    /// Returns the name this archive was opened with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `true` if the archive has an entry for `path`.
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(normalize(path))
+    }
+
+    /// Iterates over the paths of all entries in this archive.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    fn read_pak(name: String, mut reader: Box<dyn ArchiveReader>) -> Result<Self, Error> {
+        let entries = read_pak_entries(&mut reader)
+            .with_context(|_| format_err!("Failed to read table of contents of {:?}", name))
+            .with_context(|_| error::Error::Source)?;
+
+        Ok(Archive {
+            name,
+            entries,
+            reader: Mutex::new(Reader::Pak(reader)),
+        })
+    }
+
+    #[cfg(feature = "zip")]
+    fn read_zip(name: String, reader: Box<dyn ArchiveReader>) -> Result<Self, Error> {
+        let mut zip = zip::ZipArchive::new(reader)
+            .with_context(|_| format_err!("Failed to read zip archive {:?}", name))
+            .with_context(|_| error::Error::Source)?;
+
+        let mut entries = FnvHashMap::default();
+        for index in 0..zip.len() {
+            let file = zip
+                .by_index_raw(index)
+                .with_context(|_| format_err!("Failed to read entry {} of {:?}", index, name))
+                .with_context(|_| error::Error::Source)?;
+            if file.is_dir() {
+                continue;
+            }
+            entries.insert(
+                normalize(file.name()).to_owned(),
+                Entry {
+                    modified: zip_timestamp(file.last_modified()),
+                    location: Location::Zip(index),
+                },
+            );
+        }
+
+        Ok(Archive {
+            name,
+            entries,
+            reader: Mutex::new(Reader::Zip(zip)),
+        })
+    }
+
+    fn entry(&self, path: &str) -> Result<&Entry, Error> {
+        self.entries
+            .get(normalize(path))
+            .ok_or_else(|| not_found(format!("No entry {:?} in archive {:?}", path, self.name)))
+            .with_context(|_| error::Error::Source)
+    }
+
+    fn read(&self, path: &str, entry: &Entry) -> Result<Vec<u8>, Error> {
+        let mut reader = self.reader.lock();
+        let mut v = Vec::new();
+        let result = match (&mut *reader, &entry.location) {
+            #[cfg(feature = "zip")]
+            (Reader::Zip(zip), Location::Zip(index)) => zip
+                .by_index(*index)
+                .map_err(std::io::Error::from)
+                .and_then(|mut file| file.read_to_end(&mut v)),
+            (Reader::Pak(reader), Location::Pak { offset, len }) => reader
+                .seek(SeekFrom::Start(*offset))
+                .and_then(|_| reader.take(*len).read_to_end(&mut v)),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!("Archive entry does not match its reader"),
+        };
+
+        result
+            .with_context(|_| format_err!("Failed to read {:?} from archive {:?}", path, self.name))
+            .with_context(|_| error::Error::Source)?;
+
+        Ok(v)
+    }
+}
+
+impl fmt::Debug for Archive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Archive")
+            .field("name", &self.name)
+            .field("entries", &self.entries.len())
+            .finish()
+    }
+}
+
+impl Source for Archive {
+    fn modified(&self, path: &str) -> Result<u64, Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("archive_modified_asset");
+
+        self.entry(path).map(|entry| entry.modified)
+    }
+
+    fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("archive_load_asset");
+
+        let entry = self.entry(path)?;
+        self.read(path, entry)
+    }
+
+    fn load_with_metadata(&self, path: &str) -> Result<(Vec<u8>, u64), Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("archive_load_asset_with_metadata");
+
+        let entry = self.entry(path)?;
+        Ok((self.read(path, entry)?, entry.modified))
+    }
+}
+
+/// A set of mounted archives acting as a single source.
+///
+/// When a path exists in more than one archive, the archive mounted with the highest
+/// priority wins. For equal priorities the archive mounted last wins, so patch or DLC
+/// archives can simply be mounted after the base content.
+///
+/// ```rust,ignore
+/// let mut archives = Archives::new();
+/// archives
+///     .mount(Archive::open("base.pak")?, 0)
+///     .mount(Archive::open("patch_1.zip")?, 10);
+/// loader.set_default_source(archives);
+/// ```
+#[derive(Debug, Default)]
+pub struct Archives {
+    mounted: Vec<(i32, Archive)>,
+}
+
+impl Archives {
+    /// Creates an empty set of archives.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Mounts `archive` with the given priority.
+    pub fn mount(&mut self, archive: Archive, priority: i32) -> &mut Self {
+        let index = self
+            .mounted
+            .iter()
+            .position(|&(p, _)| p <= priority)
+            .unwrap_or(self.mounted.len());
+        self.mounted.insert(index, (priority, archive));
+        self
+    }
+
+    /// Unmounts the archive with the given name, returning it.
+    pub fn unmount(&mut self, name: &str) -> Option<Archive> {
+        let index = self.mounted.iter().position(|(_, a)| a.name() == name)?;
+        Some(self.mounted.remove(index).1)
+    }
+
+    /// Returns the archive which serves `path`, if any.
+    pub fn resolve(&self, path: &str) -> Option<&Archive> {
+        self.mounted
+            .iter()
+            .map(|(_, archive)| archive)
+            .find(|archive| archive.contains(path))
+    }
+
+    fn resolve_or_err(&self, path: &str) -> Result<&Archive, Error> {
+        self.resolve(path)
+            .ok_or_else(|| not_found(format!("No mounted archive contains {:?}", path)))
+            .with_context(|_| error::Error::Source)
+    }
+}
+
+impl Source for Archives {
+    fn modified(&self, path: &str) -> Result<u64, Error> {
+        self.resolve_or_err(path)?.modified(path)
+    }
+
+    fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+        self.resolve_or_err(path)?.load(path)
+    }
+
+    fn load_with_metadata(&self, path: &str) -> Result<(Vec<u8>, u64), Error> {
+        self.resolve_or_err(path)?.load_with_metadata(path)
+    }
+}
+
+/// Writes pak archives readable by `Archive`.
+#[derive(Debug, Default)]
+pub struct PakBuilder {
+    entries: Vec<(String, u64, Vec<u8>)>,
+}
+
+impl PakBuilder {
+    /// Creates an empty builder.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds an entry with its modification time in seconds since `UNIX_EPOCH`.
+    ///
+    /// Fails if the path is too long to be stored in a pak file.
+    pub fn add<P>(&mut self, path: P, modified: u64, bytes: Vec<u8>) -> Result<&mut Self, Error>
+    where
+        P: AsRef<str>,
+    {
+        let path = normalize(path.as_ref());
+        if path.len() > u16::MAX as usize {
+            return Err(format_err!("Path {:?} is too long for a pak entry", path));
+        }
+        self.entries.push((path.to_string(), modified, bytes));
+        Ok(self)
+    }
+
+    /// Writes the pak file.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let header_len = self
+            .entries
+            .iter()
+            .fold(12, |len, (path, _, _)| len + 2 + path.len() as u64 + 3 * 8);
+
+        writer.write_all(PAK_MAGIC)?;
+        writer.write_all(&PAK_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+
+        let mut offset = header_len;
+        for (path, modified, bytes) in &self.entries {
+            writer.write_all(&(path.len() as u16).to_le_bytes())?;
+            writer.write_all(path.as_bytes())?;
+            writer.write_all(&modified.to_le_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+            offset += bytes.len() as u64;
+        }
+
+        for (_, _, bytes) in &self.entries {
+            writer.write_all(bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+fn read_pak_entries(reader: &mut dyn ArchiveReader) -> std::io::Result<FnvHashMap<String, Entry>> {
+    use std::io::{Error as IoError, ErrorKind};
+
+    fn read_u16(r: &mut dyn ArchiveReader) -> std::io::Result<u16> {
+        let mut b = [0; 2];
+        r.read_exact(&mut b).map(|_| u16::from_le_bytes(b))
+    }
+    fn read_u32(r: &mut dyn ArchiveReader) -> std::io::Result<u32> {
+        let mut b = [0; 4];
+        r.read_exact(&mut b).map(|_| u32::from_le_bytes(b))
+    }
+    fn read_u64(r: &mut dyn ArchiveReader) -> std::io::Result<u64> {
+        let mut b = [0; 8];
+        r.read_exact(&mut b).map(|_| u64::from_le_bytes(b))
+    }
+
+    reader.seek(SeekFrom::Start(PAK_MAGIC.len() as u64))?;
+    let version = read_u32(reader)?;
+    if version != PAK_VERSION {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("unsupported pak version {}", version),
+        ));
+    }
+
+    let count = read_u32(reader)?;
+    let mut entries = FnvHashMap::default();
+    for _ in 0..count {
+        let mut path = vec![0; read_u16(reader)? as usize];
+        reader.read_exact(&mut path)?;
+        let path = String::from_utf8(path)
+            .map_err(|_| IoError::new(ErrorKind::InvalidData, "pak entry path is not UTF-8"))?;
+        let modified = read_u64(reader)?;
+        let offset = read_u64(reader)?;
+        let len = read_u64(reader)?;
+        entries.insert(
+            path,
+            Entry {
+                modified,
+                location: Location::Pak { offset, len },
+            },
+        );
+    }
+
+    Ok(entries)
+}
+
+/// Converts the MS-DOS timestamp of a zip entry into seconds since `UNIX_EPOCH`.
+/// Zip timestamps carry no time zone, so they are interpreted as UTC.
+#[cfg(feature = "zip")]
+fn zip_timestamp(time: zip::DateTime) -> u64 {
+    // Days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let (y, m, d) = (
+        i64::from(time.year()),
+        i64::from(time.month()),
+        i64::from(time.day()),
+    );
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400
+        + i64::from(time.hour()) * 3_600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+
+    secs.max(0) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::source::Source;
+
+    use super::{Archive, Archives, PakBuilder};
+
+    fn pak(name: &str, entries: &[(&str, u64, &[u8])]) -> Archive {
+        let mut builder = PakBuilder::new();
+        for &(path, modified, bytes) in entries {
+            builder
+                .add(path, modified, bytes.to_vec())
+                .expect("Failed to add pak entry");
+        }
+        let mut bytes = Vec::new();
+        builder.write(&mut bytes).expect("Failed to write pak");
+
+        Archive::from_reader(name, Cursor::new(bytes)).expect("Failed to read pak")
+    }
+
+    #[test]
+    fn loads_asset_from_pak() {
+        let archive = pak("base", &[("a/b.ron", 5, b"data"), ("c", 7, b"other")]);
+
+        assert_eq!(b"data".to_vec(), archive.load("a/b.ron").unwrap());
+        assert_eq!(
+            (b"other".to_vec(), 7),
+            archive.load_with_metadata("c").unwrap()
+        );
+        assert_eq!(5, archive.modified("a/b.ron").unwrap());
+        assert!(archive.load("missing").is_err());
+    }
+
+    #[test]
+    fn stores_paths_without_leading_slash() {
+        let archive = pak("base", &[("/a", 1, b"first"), ("/b/c", 2, b"second")]);
+
+        assert_eq!(b"first".to_vec(), archive.load("a").unwrap());
+        assert_eq!(b"second".to_vec(), archive.load("b/c").unwrap());
+    }
+
+    #[test]
+    fn rejects_too_long_paths() {
+        let path = "a".repeat(u16::MAX as usize + 1);
+        assert!(PakBuilder::new().add(path, 0, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn higher_priority_archive_shadows_base_content() {
+        let mut archives = Archives::new();
+        archives
+            .mount(pak("patch", &[("a", 2, b"patched")]), 10)
+            .mount(pak("base", &[("a", 1, b"base"), ("b", 1, b"base")]), 0)
+            .mount(pak("dlc", &[("b", 3, b"dlc")]), 0);
+
+        assert_eq!(b"patched".to_vec(), archives.load("a").unwrap());
+        assert_eq!(2, archives.modified("a").unwrap());
+        assert_eq!(b"dlc".to_vec(), archives.load("b").unwrap());
+        assert_eq!("dlc", archives.resolve("b").unwrap().name());
+
+        archives.unmount("patch");
+        assert_eq!(b"base".to_vec(), archives.load("a").unwrap());
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn loads_asset_from_zip_with_entry_timestamp() {
+        use std::io::Write;
+        use zip::{write::FileOptions, DateTime, ZipWriter};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let time = DateTime::from_date_and_time(2020, 2, 3, 4, 5, 6).unwrap();
+        writer
+            .start_file("dir/asset", FileOptions::default().last_modified_time(time))
+            .unwrap();
+        writer.write_all(b"zipped").unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let archive = Archive::from_reader("zip", Cursor::new(bytes)).unwrap();
+        assert_eq!(b"zipped".to_vec(), archive.load("dir/asset").unwrap());
+        assert_eq!(1_580_702_706, archive.modified("dir/asset").unwrap());
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use fnv::FnvHashMap;
+use parking_lot::Mutex;
+
+/// A log of changed paths, pushed by a `Source` which is able to notice changes itself
+/// (e.g. a `Directory` with a file system watcher).
+///
+/// Every change bumps a generation counter. Consumers remember the generation they last
+/// saw and ask whether a path changed since then, so several consumers can share one log
+/// without draining it.
+#[derive(Clone, Debug, Default)]
+pub struct SourceChanges {
+    inner: Arc<Mutex<ChangesInner>>,
+}
+
+#[derive(Debug, Default)]
+struct ChangesInner {
+    generation: u64,
+    changed: FnvHashMap<String, u64>,
+    forwarded: Vec<SourceChanges>,
+}
+
+impl SourceChanges {
+    /// Creates an empty change log.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records that the asset at `path` changed.
+    ///
+    /// The path uses `/` as separator, just like the paths passed to `Source::load`.
+    pub fn notify<P>(&self, path: P)
+    where
+        P: Into<String>,
+    {
+        let path = path.into();
+        let forwarded = {
+            let mut inner = self.inner.lock();
+            inner.generation += 1;
+            let generation = inner.generation;
+            inner.changed.insert(path.clone(), generation);
+            inner.forwarded.clone()
+        };
+        for changes in forwarded {
+            changes.notify(path.as_str());
+        }
+    }
+
+    /// Records all changes recorded in this log from now on in `other` too, e.g. for a
+    /// source combining the files of other sources.
+    pub fn forward_to(&self, other: &SourceChanges) {
+        self.inner.lock().forwarded.push(other.clone());
+    }
+
+    /// Returns the current generation, which increases with every change.
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().generation
+    }
+
+    /// Returns `true` if `path` changed after `generation`.
+    pub fn changed_since(&self, path: &str, generation: u64) -> bool {
+        self.inner
+            .lock()
+            .changed
+            .get(path)
+            .map(|&changed| changed > generation)
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use amethyst_error::Error;
+
+    use crate::{Format, RonFormat, Source};
+
+    use super::SourceChanges;
+
+    /// Source with a change log, which notices a change of every file while loading it
+    /// if the flag is set.
+    struct Pushing(SourceChanges, bool);
+
+    impl Source for Pushing {
+        fn modified(&self, _: &str) -> Result<u64, Error> {
+            panic!("Sources with a change log must not be polled");
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            if self.1 {
+                self.0.notify(path);
+            }
+            Ok(b"()".to_vec())
+        }
+
+        fn load_with_metadata(&self, path: &str) -> Result<(Vec<u8>, u64), Error> {
+            self.load(path).map(|b| (b, 1))
+        }
+
+        fn changes(&self) -> Option<SourceChanges> {
+            Some(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn changes_are_visible_to_all_consumers() {
+        let changes = SourceChanges::new();
+        let before = changes.generation();
+
+        changes.notify("a");
+        let after_a = changes.generation();
+        changes.notify("b");
+
+        assert!(changes.changed_since("a", before));
+        assert!(!changes.changed_since("a", after_a));
+        assert!(changes.changed_since("b", after_a));
+        assert!(!changes.changed_since("c", before));
+    }
+
+    #[test]
+    fn single_file_reloads_on_notification_without_polling() {
+        let changes = SourceChanges::new();
+        let source: Arc<dyn Source> = Arc::new(Pushing(changes.clone(), false));
+        let reload = <RonFormat as Format<()>>::import(
+            &RonFormat,
+            "a".into(),
+            source,
+            Some(Box::new(RonFormat)),
+        )
+        .unwrap()
+        .reload
+        .unwrap();
+
+        assert!(!reload.needs_reload());
+        changes.notify("b");
+        assert!(!reload.needs_reload());
+        changes.notify("a");
+        assert!(reload.needs_reload());
+    }
+
+    #[test]
+    fn single_file_reloads_changes_made_while_loading() {
+        let changes = SourceChanges::new();
+        let source: Arc<dyn Source> = Arc::new(Pushing(changes.clone(), true));
+        let reload = <RonFormat as Format<()>>::import(
+            &RonFormat,
+            "a".into(),
+            source,
+            Some(Box::new(RonFormat)),
+        )
+        .unwrap()
+        .reload
+        .unwrap();
+
+        assert!(reload.needs_reload());
+    }
+}
@@ -9,7 +9,12 @@ use thread_profiler::profile_scope;
 
 use amethyst_error::{format_err, Error, ResultExt};
 
-use crate::{error, source::Source};
+#[cfg(feature = "notify")]
+use crate::source::SourceChanges;
+use crate::{
+    error,
+    source::{Source, WritableSource},
+};
 
 /// Directory source.
 ///
@@ -17,9 +22,14 @@ use crate::{error, source::Source};
 /// inside the `Loader`, which is automatically used when you call
 /// `load`. In case you want another, second, directory for assets,
 /// you can instantiate one yourself, too. Please use `Loader::load_from` then.
+///
+/// With the `notify` feature, `Directory::watched` creates a directory source which
+/// watches its files for changes, so hot reloading doesn't need to poll them.
 #[derive(Debug)]
 pub struct Directory {
     loc: PathBuf,
+    #[cfg(feature = "notify")]
+    watcher: Option<watch::DirectoryWatcher>,
 }
 
 impl Directory {
@@ -28,7 +38,27 @@ impl Directory {
     where
         P: Into<PathBuf>,
     {
-        Directory { loc: loc.into() }
+        Directory {
+            loc: loc.into(),
+            #[cfg(feature = "notify")]
+            watcher: None,
+        }
+    }
+
+    /// Creates a new directory storage which pushes changes of its files
+    /// to the hot reloading machinery using a file system watcher.
+    #[cfg(feature = "notify")]
+    pub fn watched<P>(loc: P) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let loc = loc.into();
+        let watcher = watch::DirectoryWatcher::new(&loc)?;
+
+        Ok(Directory {
+            loc,
+            watcher: Some(watcher),
+        })
     }
 
     fn path(&self, s_path: &str) -> PathBuf {
@@ -75,6 +105,122 @@ impl Source for Directory {
 
         Ok(v)
     }
+
+    #[cfg(feature = "notify")]
+    fn changes(&self) -> Option<SourceChanges> {
+        self.watcher.as_ref().map(|w| w.changes.clone())
+    }
+}
+
+impl WritableSource for Directory {
+    fn store(&self, path: &str, bytes: &[u8]) -> Result<(), Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("dir_store_asset");
+        use std::fs::{create_dir_all, write};
+
+        let path = self.path(path);
+
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .with_context(|_| format_err!("Failed to create directory {:?}", parent))
+                .with_context(|_| error::Error::Source)?;
+        }
+        write(&path, bytes)
+            .with_context(|_| format_err!("Failed to write file {:?}", path))
+            .with_context(|_| error::Error::Source)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "notify")]
+mod watch {
+    use std::{
+        fmt,
+        path::{Path, PathBuf},
+        sync::mpsc::channel,
+        thread,
+        time::Duration,
+    };
+
+    use log::{debug, warn};
+    use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+    use parking_lot::Mutex;
+
+    use amethyst_error::{format_err, Error, ResultExt};
+
+    use crate::{error, source::SourceChanges};
+
+    pub struct DirectoryWatcher {
+        pub changes: SourceChanges,
+        // Dropping the watcher ends the event thread.
+        _watcher: Mutex<RecommendedWatcher>,
+    }
+
+    impl DirectoryWatcher {
+        pub fn new(loc: &Path) -> Result<Self, Error> {
+            let (tx, rx) = channel();
+            let mut watcher = notify::watcher(tx, Duration::from_millis(50))
+                .with_context(|_| format_err!("Failed to create file system watcher"))
+                .with_context(|_| error::Error::Source)?;
+            watcher
+                .watch(loc, RecursiveMode::Recursive)
+                .with_context(|_| format_err!("Failed to watch directory {:?}", loc))
+                .with_context(|_| error::Error::Source)?;
+
+            let changes = SourceChanges::new();
+            let roots = vec![loc.to_owned(), loc.canonicalize().unwrap_or_default()];
+            thread::Builder::new()
+                .name("amethyst_assets_watcher".into())
+                .spawn({
+                    let changes = changes.clone();
+                    move || {
+                        for event in rx {
+                            match event {
+                                DebouncedEvent::Create(path)
+                                | DebouncedEvent::Write(path)
+                                | DebouncedEvent::Remove(path) => notify(&changes, &roots, &path),
+                                DebouncedEvent::Rename(from, to) => {
+                                    notify(&changes, &roots, &from);
+                                    notify(&changes, &roots, &to);
+                                }
+                                DebouncedEvent::Error(e, path) => {
+                                    warn!("File system watcher error for {:?}: {}", path, e)
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                })
+                .with_context(|_| format_err!("Failed to spawn file system watcher thread"))?;
+
+            Ok(DirectoryWatcher {
+                changes,
+                _watcher: Mutex::new(watcher),
+            })
+        }
+    }
+
+    impl fmt::Debug for DirectoryWatcher {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("DirectoryWatcher")
+                .field("changes", &self.changes)
+                .finish()
+        }
+    }
+
+    fn notify(changes: &SourceChanges, roots: &[PathBuf], path: &Path) {
+        let relative = roots.iter().find_map(|root| path.strip_prefix(root).ok());
+        if let Some(relative) = relative {
+            let relative = relative
+                .iter()
+                .map(|c| c.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            debug!("Asset {:?} changed on disk", relative);
+            changes.notify(relative);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -98,6 +244,36 @@ mod test {
         );
     }
 
+    #[cfg(feature = "notify")]
+    #[test]
+    fn watched_directory_reports_changed_files() {
+        use std::{fs, thread, time::Duration};
+
+        let dir =
+            std::env::temp_dir().join(format!("amethyst_assets_watch_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/asset"), b"old").unwrap();
+
+        let directory = Directory::watched(&dir).expect("Failed to watch directory");
+        let changes = directory
+            .changes()
+            .expect("Watched directory has no change log");
+        let generation = changes.generation();
+
+        fs::write(dir.join("sub/asset"), b"new").unwrap();
+        let mut changed = false;
+        for _ in 0..100 {
+            thread::sleep(Duration::from_millis(50));
+            changed = changes.changed_since("sub/asset", generation);
+            if changed {
+                break;
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(changed);
+    }
+
     #[cfg(windows)]
     #[test]
     fn tolerates_backslashed_location_with_forward_slashed_asset_paths() {
@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+
+use fnv::FnvHashMap;
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+use amethyst_error::{Error, ResultExt};
+
+use crate::{
+    error,
+    source::{not_found, Source},
+};
+
+/// Table of files embedded into the executable.
+///
+/// Derive it with `#[derive(EmbeddedFiles)]` on a unit struct, naming the directory to
+/// embed relative to the crate's `Cargo.toml`:
+///
+/// ```rust,ignore
+/// use amethyst::assets::{Embedded, EmbeddedFiles};
+///
+/// #[derive(EmbeddedFiles)]
+/// #[embed(dir = "assets")]
+/// struct Assets;
+///
+/// let loader = Loader::with_default_source(Embedded::new::<Assets>(), pool);
+/// ```
+///
+/// The files are included with `include_bytes!`, so changing them rebuilds the crate,
+/// but adding or removing files does not.
+pub trait EmbeddedFiles {
+    /// Paths of the files relative to the embedded directory, using `/` as separator,
+    /// together with their contents.
+    const FILES: &'static [(&'static str, &'static [u8])];
+
+    /// Time of the build embedding the files, as seconds since `UNIX_EPOCH`.
+    const BUILD_TIME: u64;
+}
+
+/// Source serving files embedded into the executable, so small tools, tests and demos
+/// can ship as a single binary.
+///
+/// Paths are resolved like they are by `Directory`: `.` and `..` components are
+/// resolved and leading or duplicate separators are ignored. `modified` returns the
+/// build time for all files, so embedded assets are never hot reloaded.
+#[derive(Clone, Debug)]
+pub struct Embedded {
+    files: FnvHashMap<&'static str, &'static [u8]>,
+    built: u64,
+}
+
+impl Embedded {
+    /// Creates a source serving the files of an `EmbeddedFiles` table.
+    pub fn new<E>() -> Self
+    where
+        E: EmbeddedFiles,
+    {
+        Embedded::from_files(E::FILES, E::BUILD_TIME)
+    }
+
+    /// Creates a source serving the given files, which report `built` as their
+    /// modification time.
+    pub fn from_files(files: &'static [(&'static str, &'static [u8])], built: u64) -> Self {
+        Embedded {
+            files: files.iter().cloned().collect(),
+            built,
+        }
+    }
+
+    /// Returns the paths of all embedded files.
+    pub fn paths(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.files.keys().cloned()
+    }
+
+    fn file(&self, path: &str) -> Result<&'static [u8], Error> {
+        self.files
+            .get(normalize(path).as_ref())
+            .cloned()
+            .ok_or_else(|| not_found(format!("File {:?} is not embedded", path)))
+            .with_context(|_| error::Error::Source)
+    }
+}
+
+impl Source for Embedded {
+    fn modified(&self, path: &str) -> Result<u64, Error> {
+        self.file(path).map(|_| self.built)
+    }
+
+    fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("embedded_load_asset");
+
+        self.file(path).map(<[u8]>::to_vec)
+    }
+}
+
+/// Resolves `.` and `..` components and removes empty ones.
+fn normalize(path: &str) -> Cow<'_, str> {
+    let is_normal = |c: &str| !c.is_empty() && c != "." && c != "..";
+    if path.split('/').all(is_normal) {
+        return Cow::Borrowed(path);
+    }
+
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            c => components.push(c),
+        }
+    }
+    Cow::Owned(components.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::Source;
+
+    use super::Embedded;
+
+    static FILES: &[(&str, &[u8])] = &[("config.ron", b"()"), ("texture/logo.png", b"png")];
+
+    #[test]
+    fn resolves_paths_like_directory() {
+        let source = Embedded::from_files(FILES, 42);
+        assert_eq!(b"png".to_vec(), source.load("texture/logo.png").unwrap());
+        assert_eq!(b"png".to_vec(), source.load("/texture//logo.png").unwrap());
+        assert_eq!(
+            b"()".to_vec(),
+            source.load("texture/../config.ron").unwrap()
+        );
+        assert_eq!(b"()".to_vec(), source.load("./config.ron").unwrap());
+        assert_eq!(42, source.modified("config.ron").unwrap());
+        assert!(source.load("texture").is_err());
+        assert!(source.modified("missing.ron").is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use fnv::FnvHashMap;
+use log::warn;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+use amethyst_error::{format_err, Error, ResultExt};
+
+use crate::{
+    error,
+    source::{not_found, Source},
+};
+
+/// Source fetching assets over HTTP from a content server, caching them on disk.
+///
+/// Assets are requested from `url` followed by their path. Cached assets are revalidated
+/// with their `ETag` and `Last-Modified` headers, so unchanged assets aren't downloaded
+/// again. If the server can't be reached or fails with a server error, cached assets are
+/// used instead.
+///
+/// `modified` asks the server for the `Last-Modified` header of the asset, so hot reloading
+/// picks up changes on the server. For servers only sending an `ETag`, it returns the time
+/// a changed `ETag` has been noticed. To not send a request for every asset on every hot
+/// reload check, the server is asked at most once per check interval (see
+/// `with_check_interval`) for each asset, in between the last answer is returned.
+///
+/// Requires the `http` feature.
+///
+/// ### Example
+///
+/// ```rust,ignore
+/// let mut loader = Loader::new("assets", pool);
+/// loader.add_source("cdn", Http::new("https://cdn.example.com/assets", "cache/cdn"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Http {
+    url: String,
+    cache: PathBuf,
+    timeout: Duration,
+    check_interval: Duration,
+    /// Last known modification time of each asset, with the time it has been fetched.
+    checked: Arc<Mutex<FnvHashMap<String, (Instant, u64)>>>,
+}
+
+/// Validators of a cached asset.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct CacheMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    modified: u64,
+}
+
+impl Http {
+    /// Creates a source fetching assets from below `url`, caching them in the directory
+    /// `cache`.
+    pub fn new<U, P>(url: U, cache: P) -> Self
+    where
+        U: Into<String>,
+        P: Into<PathBuf>,
+    {
+        Http {
+            url: url.into(),
+            cache: cache.into(),
+            timeout: Duration::from_secs(30),
+            check_interval: Duration::from_secs(5),
+            checked: Default::default(),
+        }
+    }
+
+    /// Sets the timeout of requests, 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how long `modified` returns the last known modification time of an asset
+    /// before asking the server again, 5 seconds by default.
+    pub fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    fn checked(&self, path: &str, modified: u64) {
+        self.checked
+            .lock()
+            .insert(path.to_string(), (Instant::now(), modified));
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// Returns the path of the cached data (`meta == false`) or validators (`meta == true`)
+    /// of an asset.
+    fn cache_path(&self, path: &str, meta: bool) -> Result<PathBuf, Error> {
+        let mut cache = self.cache.join(if meta { "meta" } else { "data" });
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(c) => cache.push(c),
+                Component::CurDir | Component::RootDir => {}
+                _ => return Err(format_err!("Path {:?} leaves the cache directory", path)),
+            }
+        }
+        if meta {
+            cache.set_extension(match cache.extension() {
+                Some(ext) => format!("{}.ron", ext.to_string_lossy()),
+                None => "ron".to_string(),
+            });
+        }
+        Ok(cache)
+    }
+
+    fn cached_meta(&self, path: &str) -> Option<CacheMeta> {
+        let meta = fs::read(self.cache_path(path, true).ok()?).ok()?;
+        ron::de::from_bytes(&meta).ok()
+    }
+
+    fn cached(&self, path: &str, meta: &CacheMeta) -> Result<(Vec<u8>, u64), Error> {
+        let cache = self.cache_path(path, false)?;
+        let bytes = fs::read(&cache)
+            .with_context(|_| format_err!("Failed to read cached file {:?}", cache))?;
+        Ok((bytes, meta.modified))
+    }
+
+    fn store(&self, path: &str, bytes: &[u8], meta: &CacheMeta) -> Result<(), Error> {
+        let data = self.cache_path(path, false)?;
+        let meta_path = self.cache_path(path, true)?;
+        for cache in &[&data, &meta_path] {
+            if let Some(parent) = cache.parent() {
+                fs::create_dir_all(parent).with_context(|_| {
+                    format_err!("Failed to create cache directory {:?}", parent)
+                })?;
+            }
+        }
+        fs::write(&data, bytes)
+            .with_context(|_| format_err!("Failed to write cached file {:?}", data))?;
+        let meta = ron::ser::to_string(meta)
+            .with_context(|_| format_err!("Failed to serialize cache metadata"))?;
+        fs::write(&meta_path, meta)
+            .with_context(|_| format_err!("Failed to write cached file {:?}", meta_path))?;
+        Ok(())
+    }
+
+    fn request(&self, method: &str, path: &str, meta: Option<&CacheMeta>) -> ureq::Response {
+        let mut request = ureq::request(method, &self.url(path));
+        request.timeout(self.timeout);
+        if let Some(meta) = meta {
+            if let Some(ref etag) = meta.etag {
+                request.set("If-None-Match", etag);
+            }
+            if let Some(ref last_modified) = meta.last_modified {
+                request.set("If-Modified-Since", last_modified);
+            }
+        }
+        request.call()
+    }
+
+    fn fetch(&self, path: &str) -> Result<(Vec<u8>, u64), Error> {
+        let meta = self.cached_meta(path);
+        let response = self.request("GET", path, meta.as_ref());
+        match (response.status(), meta) {
+            (304, Some(meta)) => self.cached(path, &meta),
+            (200..=299, meta) => {
+                let new_meta = CacheMeta {
+                    etag: response.header("ETag").map(str::to_string),
+                    last_modified: response.header("Last-Modified").map(str::to_string),
+                    modified: modified(&response, meta.as_ref()),
+                };
+                let mut bytes = Vec::new();
+                response
+                    .into_reader()
+                    .read_to_end(&mut bytes)
+                    .with_context(|_| format_err!("Failed to read response for {:?}", path))?;
+                if let Err(e) = self.store(path, &bytes, &new_meta) {
+                    warn!("Failed to cache {:?}: {}", path, e);
+                }
+                Ok((bytes, new_meta.modified))
+            }
+            (_, Some(meta)) if unavailable(&response) => {
+                warn!(
+                    "Failed to fetch {:?}, using cached file: {}",
+                    path,
+                    describe(response)
+                );
+                self.cached(path, &meta)
+            }
+            (404, _) | (410, _) => Err(not_found(format!(
+                "Failed to fetch {:?}: {}",
+                path,
+                describe(response)
+            ))),
+            (_, _) => Err(format_err!(
+                "Failed to fetch {:?}: {}",
+                path,
+                describe(response)
+            )),
+        }
+    }
+}
+
+impl Source for Http {
+    fn modified(&self, path: &str) -> Result<u64, Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("http_modified_asset");
+
+        if let Some((at, modified)) = self.checked.lock().get(path) {
+            if at.elapsed() < self.check_interval {
+                return Ok(*modified);
+            }
+        }
+
+        let meta = self.cached_meta(path);
+        let response = self.request("HEAD", path, meta.as_ref());
+        let modified = match (response.status(), meta) {
+            (304, Some(meta)) => Ok(meta.modified),
+            (200..=299, meta) => Ok(modified(&response, meta.as_ref())),
+            (_, Some(meta)) if unavailable(&response) => Ok(meta.modified),
+            (404, _) | (410, _) => Err(not_found(format!(
+                "Failed to fetch metadata of {:?}: {}",
+                path,
+                describe(response)
+            ))),
+            (_, _) => Err(format_err!(
+                "Failed to fetch metadata of {:?}: {}",
+                path,
+                describe(response)
+            )),
+        }
+        .with_context(|_| error::Error::Source)?;
+        self.checked(path, modified);
+        Ok(modified)
+    }
+
+    fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+        self.load_with_metadata(path).map(|(bytes, _)| bytes)
+    }
+
+    fn load_with_metadata(&self, path: &str) -> Result<(Vec<u8>, u64), Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("http_load_asset");
+
+        let (bytes, modified) = self.fetch(path).with_context(|_| error::Error::Source)?;
+        self.checked(path, modified);
+        Ok((bytes, modified))
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+}
+
+/// Returns whether the server couldn't be reached or failed with a server error.
+fn unavailable(response: &ureq::Response) -> bool {
+    response.synthetic() || response.server_error()
+}
+
+fn describe(response: ureq::Response) -> String {
+    let status = response.status_line().to_string();
+    match response.into_synthetic_error() {
+        Some(e) => e.to_string(),
+        None => format!("Server responded with {:?}", status),
+    }
+}
+
+/// Returns the modification time of the asset of a successful response, which was cached
+/// with `meta` before.
+fn modified(response: &ureq::Response, meta: Option<&CacheMeta>) -> u64 {
+    if let Some(modified) = response.header("Last-Modified").and_then(parse_http_date) {
+        return modified;
+    }
+    match meta {
+        Some(meta) if meta.etag.is_some() && meta.etag.as_deref() == response.header("ETag") => {
+            meta.modified
+        }
+        _ => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    }
+}
+
+/// Parses an HTTP date like `Sun, 06 Nov 1994 08:49:37 GMT` as seconds since
+/// `UNIX_EPOCH`.
+fn parse_http_date(date: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut parts = date.split_whitespace().skip(1);
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|t| t.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if parts.next() != Some("GMT") || year < 1970 || day == 0 || day > 31 {
+        return None;
+    }
+
+    // Days since 1970-01-01 in the proleptic Gregorian calendar, counting years from
+    // March so the leap day is the last day of the year.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let days = 365 * y + y / 4 - y / 100 + y / 400 + (153 * m + 2) / 5 + day - 1 - 719_468;
+    Some(((days * 24 + hour) * 60 + minute) * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use crate::source::Source;
+
+    use super::{parse_http_date, Http};
+
+    /// Version of `/assets/level.ron` served by the stand-in server, with the responses it sent.
+    #[derive(Default)]
+    struct Served {
+        version: u32,
+        statuses: Vec<u16>,
+    }
+
+    fn respond(mut stream: TcpStream, served: &Mutex<Served>) {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut buf).unwrap();
+            if n == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        let request = String::from_utf8(request).unwrap();
+        let head = request.starts_with("HEAD");
+
+        let mut served = served.lock().unwrap();
+        let etag = format!("\"{}\"", served.version);
+        let (status, body) = if !request.contains(" /assets/level.ron ") {
+            (404, String::new())
+        } else if request.contains(&format!("If-None-Match: {}", etag)) {
+            (304, String::new())
+        } else {
+            (200, format!("v{}", served.version))
+        };
+        served.statuses.push(status);
+        let response = format!(
+            "HTTP/1.1 {} Status\r\nETag: {}\r\nLast-Modified: Sun, 0{} Nov 1994 08:49:37 GMT\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            etag,
+            served.version,
+            body.len(),
+            if head { "" } else { &body },
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    }
+
+    fn serve(served: Arc<Mutex<Served>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/assets", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                respond(stream.unwrap(), &served);
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn parses_http_dates() {
+        assert_eq!(
+            Some(784_111_777),
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+        assert_eq!(
+            Some(951_782_400),
+            parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT")
+        );
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+    }
+
+    #[test]
+    fn revalidates_cache_and_falls_back_to_it() {
+        let cache = env::temp_dir().join(format!("amethyst_http_cache_{}", std::process::id()));
+        let served = Arc::new(Mutex::new(Served {
+            version: 1,
+            statuses: Vec::new(),
+        }));
+        let source = Http::new(serve(served.clone()), &cache);
+
+        let v1 = parse_http_date("Sun, 01 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(
+            (b"v1".to_vec(), v1),
+            source.load_with_metadata("level.ron").unwrap()
+        );
+        assert_eq!(b"v1".to_vec(), source.load("level.ron").unwrap());
+        assert_eq!(v1, source.modified("level.ron").unwrap());
+        assert!(source.load("missing.ron").is_err());
+        // The modification time is known from loading, so `modified` didn't ask.
+        assert_eq!(vec![200, 304, 404], served.lock().unwrap().statuses);
+
+        served.lock().unwrap().version = 2;
+        assert_eq!(v1, source.modified("level.ron").unwrap());
+        let source = source.with_check_interval(Duration::from_secs(0));
+        let v2 = source.modified("level.ron").unwrap();
+        assert!(v2 > v1);
+        assert_eq!(
+            (b"v2".to_vec(), v2),
+            source.load_with_metadata("level.ron").unwrap()
+        );
+
+        let offline = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/assets", listener.local_addr().unwrap())
+        };
+        let source = Http::new(offline, &cache);
+        assert_eq!(
+            (b"v2".to_vec(), v2),
+            source.load_with_metadata("level.ron").unwrap()
+        );
+        assert_eq!(v2, source.modified("level.ron").unwrap());
+        assert!(source.load("missing.ron").is_err());
+        assert!(source.load("../escape.ron").is_err());
+
+        fs::remove_dir_all(&cache).unwrap();
+    }
+}
@@ -1,11 +1,28 @@
+use std::io;
+
 use amethyst_error::Error;
 
-pub use self::dir::Directory;
+pub use self::{
+    archive::{Archive, ArchiveReader, Archives, PakBuilder},
+    changes::SourceChanges,
+    dir::Directory,
+    embedded::{Embedded, EmbeddedFiles},
+    overlay::Overlay,
+};
+
+#[cfg(feature = "http")]
+pub use self::http::Http;
 
 #[cfg(feature = "profiler")]
 use thread_profiler::profile_scope;
 
+mod archive;
+mod changes;
 mod dir;
+mod embedded;
+#[cfg(feature = "http")]
+mod http;
+mod overlay;
 
 /// A trait for asset sources, which provides
 /// methods for loading bytes.
@@ -18,6 +35,10 @@ pub trait Source: Send + Sync + 'static {
     /// Loads the bytes given a path.
     ///
     /// The id should always use `/` as separator in paths.
+    ///
+    /// Missing files should be reported with an `io::Error` of kind `NotFound` in the error
+    /// chain, like `Directory` does. `Overlay` only tries lower layers for those, other
+    /// errors are failures of the source itself.
     fn load(&self, path: &str) -> Result<Vec<u8>, Error>;
 
     /// Returns both the result of `load` and `modified` as a tuple.
@@ -32,4 +53,48 @@ pub trait Source: Send + Sync + 'static {
 
         Ok((b, m))
     }
+
+    /// Returns the change log of this source, if it is able to notice changes itself.
+    ///
+    /// Assets of sources returning `None` (the default) are hot reloaded by polling
+    /// `modified`. Assets of sources returning a log are only reloaded once the log
+    /// reports a change to their path.
+    fn changes(&self) -> Option<SourceChanges> {
+        None
+    }
+
+    /// Returns whether loading from this source goes over the network.
+    ///
+    /// `Deduplication::Content` doesn't read files of remote sources on the thread
+    /// requesting them, it deduplicates their loads by path instead.
+    fn is_remote(&self) -> bool {
+        false
+    }
+}
+
+/// Creates the error reporting that a source doesn't have the requested file.
+pub(crate) fn not_found(message: String) -> Error {
+    Error::new(io::Error::new(io::ErrorKind::NotFound, message))
+}
+
+/// Returns whether `error` reports that a source doesn't have the requested file.
+pub(crate) fn is_not_found(error: &Error) -> bool {
+    // `Error` keeps the errors it wraps boxed.
+    error.causes().any(|cause| {
+        let cause = cause.as_error();
+        cause
+            .downcast_ref::<Box<io::Error>>()
+            .map(|e| &**e)
+            .or_else(|| cause.downcast_ref::<io::Error>())
+            .map(|e| e.kind() == io::ErrorKind::NotFound)
+            .unwrap_or(false)
+    })
+}
+
+/// A `Source` which can also store data, e.g. for save games or cooked assets.
+pub trait WritableSource: Source {
+    /// Stores `bytes` at the given path, replacing any data already stored there.
+    ///
+    /// The path should always use `/` as separator.
+    fn store(&self, path: &str, bytes: &[u8]) -> Result<(), Error>;
 }
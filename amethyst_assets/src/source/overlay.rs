@@ -0,0 +1,321 @@
+use std::{fmt, sync::Arc};
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+use amethyst_error::{format_err, Error, ResultExt};
+use log::trace;
+
+use crate::{
+    error,
+    source::{is_not_found, not_found, Source, SourceChanges},
+};
+
+/// A source layering other sources on top of each other.
+///
+/// Every request is tried against the layers in the order they were added, and the
+/// first layer having the path wins. Lower layers are only tried if a layer doesn't
+/// have the path at all (see `Source::load`), other errors are returned right away. This allows e.g. a mods directory to
+/// shadow a user directory, which in turn shadows the base assets:
+///
+/// ```rust,ignore
+/// let overlay = Overlay::new()
+///     .with_layer("mods", Directory::new("mods"))
+///     .with_layer("user", Directory::new(user_dir))
+///     .with_layer("base", Directory::new("assets"));
+/// loader.set_default_source(overlay);
+/// ```
+///
+/// `modified` reports the modification time of the layer currently serving a path,
+/// so hot reloading (see `SingleFile`) picks up files that are added to a higher
+/// layer while the game is running, as long as they are newer than the file they shadow.
+/// If all layers push change notifications (see `Source::changes`), the overlay pushes
+/// the changes of all of them instead.
+#[derive(Default)]
+pub struct Overlay {
+    layers: Vec<(String, Arc<dyn Source>)>,
+    changes: SourceChanges,
+    /// Whether a layer without change notifications has been added.
+    polled: bool,
+}
+
+impl Overlay {
+    /// Creates an overlay without any layers.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a layer below all existing layers.
+    pub fn with_layer<N, S>(mut self, name: N, source: S) -> Self
+    where
+        N: Into<String>,
+        S: Source,
+    {
+        self.push_layer(name, source);
+        self
+    }
+
+    /// Adds a layer below all existing layers.
+    pub fn push_layer<N, S>(&mut self, name: N, source: S)
+    where
+        N: Into<String>,
+        S: Source,
+    {
+        self.push_shared_layer(name, Arc::new(source));
+    }
+
+    /// Adds a layer below all existing layers, sharing a source which may be in use elsewhere.
+    pub fn push_shared_layer<N>(&mut self, name: N, source: Arc<dyn Source>)
+    where
+        N: Into<String>,
+    {
+        match source.changes() {
+            Some(changes) => changes.forward_to(&self.changes),
+            None => self.polled = true,
+        }
+        self.layers.push((name.into(), source));
+    }
+
+    /// Returns the names of all layers, from highest to lowest priority.
+    pub fn layers(&self) -> impl Iterator<Item = &str> {
+        self.layers.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the name of the layer which serves `path`, if any.
+    pub fn layer_of(&self, path: &str) -> Option<&str> {
+        self.layers
+            .iter()
+            .find(|(_, source)| match source.modified(path) {
+                Ok(_) => true,
+                Err(e) => !is_not_found(&e),
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn first<T, F>(&self, path: &str, mut f: F) -> Result<T, Error>
+    where
+        F: FnMut(&dyn Source) -> Result<T, Error>,
+    {
+        let mut errors = Vec::new();
+        for (name, source) in &self.layers {
+            match f(&**source) {
+                Ok(value) => {
+                    trace!("Overlay layer {:?} serves {:?}", name, path);
+                    return Ok(value);
+                }
+                Err(e) if is_not_found(&e) => errors.push(format!("{}: {}", name, e)),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|_| format_err!("Overlay layer {:?} failed", name))
+                        .with_context(|_| error::Error::Source);
+                }
+            }
+        }
+
+        Err(not_found(format!(
+            "No overlay layer could serve {:?} ({})",
+            path,
+            errors.join(", ")
+        )))
+        .with_context(|_| error::Error::Source)
+    }
+}
+
+impl fmt::Debug for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Overlay")
+            .field("layers", &self.layers().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Source for Overlay {
+    fn modified(&self, path: &str) -> Result<u64, Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("overlay_modified_asset");
+
+        self.first(path, |source| source.modified(path))
+    }
+
+    fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("overlay_load_asset");
+
+        self.first(path, |source| source.load(path))
+    }
+
+    fn load_with_metadata(&self, path: &str) -> Result<(Vec<u8>, u64), Error> {
+        #[cfg(feature = "profiler")]
+        profile_scope!("overlay_load_asset_with_metadata");
+
+        self.first(path, |source| source.load_with_metadata(path))
+    }
+
+    fn changes(&self) -> Option<SourceChanges> {
+        if self.polled || self.layers.is_empty() {
+            None
+        } else {
+            Some(self.changes.clone())
+        }
+    }
+
+    fn is_remote(&self) -> bool {
+        self.layers.iter().any(|(_, source)| source.is_remote())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Arc,
+        },
+    };
+
+    use amethyst_error::{format_err, Error};
+
+    use crate::{
+        source::{not_found, Directory, Source, SourceChanges},
+        Format, RonFormat,
+    };
+
+    use super::Overlay;
+
+    #[derive(Default)]
+    struct Single {
+        path: &'static str,
+        bytes: &'static str,
+        modified: AtomicU64,
+        present: AtomicBool,
+        broken: AtomicBool,
+    }
+
+    impl Single {
+        fn new(path: &'static str, bytes: &'static str, modified: u64) -> Arc<Self> {
+            let s = Single {
+                path,
+                bytes,
+                ..Default::default()
+            };
+            s.modified.store(modified, Ordering::Relaxed);
+            s.present.store(true, Ordering::Relaxed);
+            Arc::new(s)
+        }
+
+        fn check(&self, path: &str) -> Result<(), Error> {
+            if self.broken.load(Ordering::Relaxed) {
+                Err(format_err!("{} is broken", path))
+            } else if self.present.load(Ordering::Relaxed) && path == self.path {
+                Ok(())
+            } else {
+                Err(not_found(format!("{} not found", path)))
+            }
+        }
+    }
+
+    impl Source for Single {
+        fn modified(&self, path: &str) -> Result<u64, Error> {
+            self.check(path)
+                .map(|_| self.modified.load(Ordering::Relaxed))
+        }
+
+        fn load(&self, path: &str) -> Result<Vec<u8>, Error> {
+            self.check(path).map(|_| self.bytes.as_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn falls_back_to_lower_layers() {
+        let mut overlay = Overlay::new();
+        overlay.push_layer(
+            "user",
+            Directory::new(env::temp_dir().join("amethyst_overlay_missing")),
+        );
+        overlay.push_shared_layer("mods", Single::new("a", "\"mod\"", 2));
+        overlay.push_shared_layer("base", Single::new("b", "\"base\"", 1));
+
+        assert_eq!(b"\"mod\"".to_vec(), overlay.load("a").unwrap());
+        assert_eq!(b"\"base\"".to_vec(), overlay.load("b").unwrap());
+        assert_eq!(Some("mods"), overlay.layer_of("a"));
+        assert_eq!(Some("base"), overlay.layer_of("b"));
+        assert_eq!(None, overlay.layer_of("c"));
+        assert!(overlay.load("c").is_err());
+    }
+
+    struct Watched(SourceChanges);
+
+    impl Source for Watched {
+        fn modified(&self, _: &str) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        fn load(&self, _: &str) -> Result<Vec<u8>, Error> {
+            Ok(b"()".to_vec())
+        }
+
+        fn changes(&self) -> Option<SourceChanges> {
+            Some(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn fails_on_errors_other_than_missing_files() {
+        let mods = Single::new("a", "\"mod\"", 2);
+        mods.broken.store(true, Ordering::Relaxed);
+        let mut overlay = Overlay::new();
+        overlay.push_shared_layer("mods", mods);
+        overlay.push_shared_layer("base", Single::new("a", "\"base\"", 1));
+
+        let error = overlay.load("a").unwrap_err();
+        assert!(error.causes().any(|e| e.to_string() == "a is broken"));
+        assert_eq!(Some("mods"), overlay.layer_of("a"));
+    }
+
+    #[test]
+    fn forwards_changes_of_all_layers() {
+        let mods = SourceChanges::new();
+        let base = SourceChanges::new();
+        let mut overlay = Overlay::new();
+        overlay.push_layer("mods", Watched(mods.clone()));
+        overlay.push_layer("base", Watched(base.clone()));
+
+        let changes = overlay.changes().unwrap();
+        let generation = changes.generation();
+        base.notify("a");
+        assert!(changes.changed_since("a", generation));
+        assert!(!changes.changed_since("b", generation));
+        mods.notify("b");
+        assert!(changes.changed_since("b", generation));
+
+        overlay.push_shared_layer("polled", Single::new("c", "()", 1));
+        assert!(overlay.changes().is_none());
+    }
+
+    #[test]
+    fn hot_reload_notices_shadowing_layer() {
+        let mods = Single::new("a", "\"mod\"", 5);
+        mods.present.store(false, Ordering::Relaxed);
+
+        let mut overlay = Overlay::new();
+        overlay.push_shared_layer("mods", mods.clone());
+        overlay.push_shared_layer("base", Single::new("a", "\"base\"", 1));
+        let overlay: Arc<dyn Source> = Arc::new(overlay);
+
+        let value = <RonFormat as Format<String>>::import(
+            &RonFormat,
+            "a".into(),
+            overlay,
+            Some(Box::new(RonFormat)),
+        )
+        .unwrap();
+        assert_eq!("base", value.data);
+        let reload = value.reload.unwrap();
+        assert!(!reload.needs_reload());
+
+        mods.present.store(true, Ordering::Relaxed);
+        assert!(reload.needs_reload());
+        assert_eq!("mod", reload.reload().unwrap().data);
+    }
+}
@@ -1,14 +1,17 @@
 use std::{
     marker::PhantomData,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc, Weak,
     },
+    time::Instant,
 };
 
 use crossbeam_queue::SegQueue;
 use derivative::Derivative;
+use fnv::FnvHashMap;
 use log::{debug, error, trace, warn};
+use parking_lot::Mutex;
 use rayon::ThreadPool;
 
 use amethyst_core::{
@@ -19,15 +22,17 @@ use amethyst_core::{
     },
     SystemDesc, Time,
 };
-use amethyst_error::{Error, ResultExt};
+use amethyst_error::{format_err, Error, ResultExt};
 
 #[cfg(feature = "profiler")]
 use thread_profiler::profile_scope;
 
 use crate::{
     asset::{Asset, FormatValue, ProcessableAsset},
+    dependency::{AssetId, Dependencies},
     error,
-    progress::Tracker,
+    loader::Deduplicated,
+    progress::{LoadStats, Tracker},
     reload::{HotReloadStrategy, Reload},
 };
 
@@ -46,14 +51,79 @@ impl Allocator {
 
 /// An asset storage, storing the actual assets and allocating
 /// handles to them.
+///
+/// By default, an asset is freed as soon as the last `Handle` to it is dropped.
+/// With a `MemoryBudget` (see `set_budget`) unused assets are kept around, so
+/// e.g. a `Cache` can still hand them out, until they exceed the budget. Then the
+/// least recently used ones are evicted.
 pub struct AssetStorage<A: Asset> {
     assets: VecStorage<(A, u32)>,
     bitset: BitSet,
+    budget: Option<MemoryBudget<A>>,
+    deduplicated: Mutex<Option<Deduplicated>>,
+    dependencies: Mutex<Option<Dependencies>>,
+    evicted: u64,
+    failed: FnvHashMap<u32, (Handle<A>, String)>,
+    frame_number: u64,
     handles: Vec<Handle<A>>,
     handle_alloc: Allocator,
+    meta: FnvHashMap<u32, AssetMeta>,
     pub(crate) processed: Arc<SegQueue<Processed<A>>>,
     reloads: Vec<(WeakHandle<A>, Box<dyn Reload<A::Data>>)>,
     unused_handles: SegQueue<Handle<A>>,
+    waiting: Mutex<FnvHashMap<u32, Vec<Box<dyn Tracker>>>>,
+}
+
+/// Limits the memory an `AssetStorage` may use for assets which are not in use anymore.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Copy(bound = ""))]
+pub struct MemoryBudget<A> {
+    bytes: usize,
+    size_of: fn(&A) -> usize,
+}
+
+impl<A> MemoryBudget<A> {
+    /// Creates a budget of `bytes`, using `size_of` to determine the size of an asset.
+    pub fn new(bytes: usize, size_of: fn(&A) -> usize) -> Self {
+        MemoryBudget { bytes, size_of }
+    }
+
+    /// Returns the number of bytes in this budget.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+/// Statistics about the assets in an `AssetStorage`, returned by `AssetStorage::stats`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StorageStats {
+    /// Number of loaded assets.
+    pub count: usize,
+    /// Number of loaded assets no `Handle` points to anymore,
+    /// which are only kept because of the `MemoryBudget`.
+    pub unused: usize,
+    /// Total size of all loaded assets in bytes. Only known if there's a `MemoryBudget`.
+    pub bytes: usize,
+    /// Number of assets evicted to stay within the `MemoryBudget`.
+    pub evicted: u64,
+}
+
+/// Statistics about a single asset, returned by `AssetStorage::asset_stats`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AssetStats {
+    /// Size of the asset in bytes. Only known if there's a `MemoryBudget`.
+    pub bytes: usize,
+    /// The last frame the asset was loaded or replaced. With a `MemoryBudget`, this also
+    /// accounts for frames in which a `Handle` to the asset was alive or the asset was
+    /// accessed.
+    pub last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct AssetMeta {
+    bytes: usize,
+    /// Atomic, so `get` can record accesses.
+    last_used: AtomicU64,
 }
 
 /// Returned by processor systems, describes the loading state of the asset.
@@ -88,11 +158,82 @@ impl<A: Asset> AssetStorage<A> {
         }
     }
 
+    /// Makes this storage report loads, failures and reloads to `dependencies`.
+    pub(crate) fn attach_dependencies(&self, dependencies: &Dependencies) {
+        let mut attached = self.dependencies.lock();
+        if attached.is_none() {
+            *attached = Some(dependencies.clone());
+        }
+    }
+
+    /// Makes this storage remove the deduplication entries of assets whose handle ids
+    /// are freed from `deduplicated`.
+    pub(crate) fn attach_deduplicated(&self, deduplicated: &Deduplicated) {
+        let mut attached = self.deduplicated.lock();
+        if attached.is_none() {
+            *attached = Some(deduplicated.clone());
+        }
+    }
+
     /// Remove all data from asset storages, invalidating all associated handles.
     /// Trying to retreive any data using old handle will return `None`.
     pub fn unload_all(&mut self) {
         unsafe { self.assets.clean(&self.bitset) }
         self.bitset.clear();
+        self.meta.clear();
+    }
+
+    /// Sets the memory budget for this storage. Passing `None` frees all unused
+    /// assets during the next `process` call, just like without a budget.
+    pub fn set_budget(&mut self, budget: Option<MemoryBudget<A>>) {
+        self.budget = budget;
+        let ids: Vec<u32> = self.meta.keys().cloned().collect();
+        for id in ids {
+            self.track(id);
+        }
+    }
+
+    /// Returns the memory budget of this storage.
+    pub fn budget(&self) -> Option<&MemoryBudget<A>> {
+        self.budget.as_ref()
+    }
+
+    /// Returns statistics about the loaded assets.
+    pub fn stats(&self) -> StorageStats {
+        StorageStats {
+            count: self.meta.len(),
+            unused: self.handles.iter().filter(|h| h.is_unique()).count(),
+            bytes: self.meta.values().map(|m| m.bytes).sum(),
+            evicted: self.evicted,
+        }
+    }
+
+    /// Returns statistics about the asset `handle` points to.
+    pub fn asset_stats(&self, handle: &Handle<A>) -> Option<AssetStats> {
+        self.meta.get(&handle.id()).map(|meta| AssetStats {
+            bytes: meta.bytes,
+            last_used: meta.last_used.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Updates size and last use of the asset with the given id.
+    fn track(&mut self, id: u32) {
+        let bytes = match (self.budget, self.get_by_id(id)) {
+            (Some(budget), Some(asset)) => (budget.size_of)(asset),
+            _ => 0,
+        };
+        let meta = self.meta.entry(id).or_default();
+        meta.bytes = bytes;
+        *meta.last_used.get_mut() = self.frame_number;
+    }
+
+    /// Records an access to the asset with the given id, if there's a `MemoryBudget`.
+    fn touch(&self, id: u32) {
+        if self.budget.is_some() {
+            if let Some(meta) = self.meta.get(&id) {
+                meta.last_used.store(self.frame_number, Ordering::Relaxed);
+            }
+        }
     }
 
     /// When cloning an asset handle, you'll get another handle,
@@ -114,6 +255,7 @@ impl<A: Asset> AssetStorage<A> {
             unsafe {
                 self.assets.insert(id, (asset, 0));
             }
+            self.track(id);
 
             Some(h)
         } else {
@@ -124,6 +266,7 @@ impl<A: Asset> AssetStorage<A> {
     /// Get an asset from a given asset handle.
     pub fn get(&self, handle: &Handle<A>) -> Option<&A> {
         if self.bitset.contains(handle.id()) {
+            self.touch(handle.id());
             Some(unsafe { &self.assets.get(handle.id()).0 })
         } else {
             None
@@ -163,7 +306,9 @@ impl<A: Asset> AssetStorage<A> {
         if self.bitset.contains(handle.id()) {
             let data = unsafe { self.assets.get_mut(handle.id()) };
             data.1 += 1;
-            std::mem::replace(&mut data.0, asset)
+            let old = std::mem::replace(&mut data.0, asset);
+            self.track(handle.id());
+            old
         } else {
             panic!("Trying to replace not loaded asset");
         }
@@ -183,6 +328,7 @@ impl<A: Asset> AssetStorage<A> {
         unsafe {
             self.assets.insert(id, (asset, 0));
         }
+        self.track(id);
         handle
     }
 
@@ -196,6 +342,11 @@ impl<A: Asset> AssetStorage<A> {
         self.bitset.contains(id)
     }
 
+    /// Returns `true` if loading the asset with the given id failed.
+    pub(crate) fn failed_id(&self, id: u32) -> bool {
+        self.failed.contains_key(&id)
+    }
+
     /// Get an asset by it's handle id without checking the internal bitset.
     /// Use `contains_id` to manually check it's status before access.
     ///
@@ -210,6 +361,7 @@ impl<A: Asset> AssetStorage<A> {
     /// Get an asset mutably from a given asset handle.
     pub fn get_mut(&mut self, handle: &Handle<A>) -> Option<&mut A> {
         if self.bitset.contains(handle.id()) {
+            self.touch(handle.id());
             Some(unsafe { &mut self.assets.get_mut(handle.id()).0 })
         } else {
             None
@@ -242,6 +394,10 @@ impl<A: Asset> AssetStorage<A> {
         D: FnMut(A),
         F: FnMut(A::Data) -> Result<ProcessingState<A>, Error>,
     {
+        self.frame_number = frame_number;
+        let dependencies = self.dependencies.lock().clone();
+        let deduplicated = self.deduplicated.lock().clone();
+        let mut changed = Vec::new();
         {
             let mut requeue = Vec::new();
             while let Ok(processed) = self.processed.pop() {
@@ -249,6 +405,7 @@ impl<A: Asset> AssetStorage<A> {
                 let bitset = &mut self.bitset;
                 let handles = &mut self.handles;
                 let reloads = &mut self.reloads;
+                let waiting = self.waiting.get_mut();
 
                 let f = &mut f;
                 let (reload_obj, handle) = match processed {
@@ -256,20 +413,58 @@ impl<A: Asset> AssetStorage<A> {
                         data,
                         handle,
                         name,
-                        tracker,
+                        mut stats,
+                        mut tracker,
                     } => {
-                        let (asset, reload_obj) = match data
+                        // Nobody is interested in the asset anymore, so don't even process it.
+                        if handle.is_unique() {
+                            let id = handle.id();
+                            tracker.record(A::NAME, &name, &stats);
+                            Self::cancel(
+                                &self.unused_handles,
+                                dependencies.as_ref(),
+                                deduplicated.as_ref(),
+                                id,
+                                name.clone(),
+                                tracker,
+                            );
+                            Self::notify_waiting(waiting, id, &name, LoadOutcome::Cancelled);
+                            continue;
+                        }
+
+                        let asset_id = AssetId::of(&handle);
+                        let started = Instant::now();
+                        let result = data
                             .map(|FormatValue { data, reload }| (data, reload))
-                            .and_then(|(d, rel)| f(d).map(|a| (a, rel)))
-                            .with_context(|_| error::Error::Asset(name.clone()))
-                        {
+                            .and_then(|(d, rel)| {
+                                Dependencies::scope(asset_id, || f(d)).map(|a| (a, rel))
+                            })
+                            .with_context(|_| error::Error::Asset(name.clone()));
+                        stats.process += started.elapsed();
+
+                        let (asset, reload_obj) = match result {
                             Ok((ProcessingState::Loaded(x), r)) => {
+                                tracker.record(A::NAME, &name, &stats);
                                 debug!(
                                         "{:?}: Asset {:?} (handle id: {:?}) has been loaded successfully",
                                         A::NAME,
                                         name,
                                         handle,
                                     );
+                                if let Some(ref dependencies) = dependencies {
+                                    dependencies.loaded(asset_id);
+                                    let failed = dependencies.failed_dependencies_of(asset_id);
+                                    if !failed.is_empty() {
+                                        warn!(
+                                            "{:?}: Asset {:?} (handle id: {:?}) loaded, but some of its dependencies failed: {:?}",
+                                            A::NAME,
+                                            name,
+                                            handle,
+                                            failed,
+                                        );
+                                    }
+                                }
+
                                 // Add a warning if a handle is unique (i.e. asset does not
                                 // need to be loaded as it is not used by anything)
                                 // https://github.com/amethyst/amethyst/issues/628
@@ -281,12 +476,18 @@ impl<A: Asset> AssetStorage<A> {
                                     tracker.fail(
                                         handle.id(),
                                         A::NAME,
-                                        name,
+                                        name.clone(),
                                         Error::from(error::Error::UnusedHandle),
                                     );
                                 } else {
                                     tracker.success();
                                 }
+                                Self::notify_waiting(
+                                    waiting,
+                                    handle.id(),
+                                    &name,
+                                    LoadOutcome::Loaded,
+                                );
 
                                 (x, r)
                             }
@@ -301,6 +502,7 @@ impl<A: Asset> AssetStorage<A> {
                                     data: Ok(FormatValue { data: x, reload: r }),
                                     handle,
                                     name,
+                                    stats,
                                     tracker,
                                 });
                                 continue;
@@ -313,7 +515,28 @@ impl<A: Asset> AssetStorage<A> {
                                     handle,
                                     e,
                                 );
-                                tracker.fail(handle.id(), A::NAME, name, e);
+                                if let Some(ref dependencies) = dependencies {
+                                    let dependents = dependencies.failed(asset_id, e.to_string());
+                                    if !dependents.is_empty() {
+                                        error!(
+                                            "{:?}: Assets depending on {:?} (handle id: {:?}) will be incomplete: {:?}",
+                                            A::NAME,
+                                            name,
+                                            handle,
+                                            dependents,
+                                        );
+                                    }
+                                }
+                                let message = e.to_string();
+                                tracker.record(A::NAME, &name, &stats);
+                                tracker.fail(handle.id(), A::NAME, name.clone(), e);
+                                Self::notify_waiting(
+                                    waiting,
+                                    handle.id(),
+                                    &name,
+                                    LoadOutcome::Failed(&message),
+                                );
+                                self.failed.insert(handle.id(), (handle, message));
 
                                 continue;
                             }
@@ -328,6 +551,7 @@ impl<A: Asset> AssetStorage<A> {
                         unsafe {
                             assets.insert(id, (asset, 0));
                         }
+                        changed.push(id);
 
                         (reload_obj, handle)
                     }
@@ -337,9 +561,12 @@ impl<A: Asset> AssetStorage<A> {
                         name,
                         old_reload,
                     } => {
+                        let asset_id = AssetId::of(&handle);
                         let (asset, reload_obj) = match data
                             .map(|FormatValue { data, reload }| (data, reload))
-                            .and_then(|(d, rel)| f(d).map(|a| (a, rel)))
+                            .and_then(|(d, rel)| {
+                                Dependencies::scope(asset_id, || f(d)).map(|a| (a, rel))
+                            })
                             .with_context(|_| error::Error::Asset(name.clone()))
                         {
                             Ok((ProcessingState::Loaded(x), r)) => (x, r),
@@ -383,9 +610,36 @@ impl<A: Asset> AssetStorage<A> {
                         let data = unsafe { self.assets.get_mut(id) };
                         data.1 += 1;
                         drop_fn(std::mem::replace(&mut data.0, asset));
+                        changed.push(id);
+
+                        if let Some(ref dependencies) = dependencies {
+                            dependencies.reloaded(asset_id);
+                        }
 
                         (reload_obj, handle)
                     }
+                    Processed::Cancelled { id, name, tracker } => {
+                        Self::cancel(
+                            &self.unused_handles,
+                            dependencies.as_ref(),
+                            deduplicated.as_ref(),
+                            id,
+                            name.clone(),
+                            tracker,
+                        );
+                        Self::notify_waiting(waiting, id, &name, LoadOutcome::Cancelled);
+                        continue;
+                    }
+                    Processed::Waiting { id, name, tracker } => {
+                        if bitset.contains(id) {
+                            tracker.success();
+                        } else if let Some((_, message)) = self.failed.get(&id) {
+                            tracker.fail(id, A::NAME, name, format_err!("{}", message));
+                        } else {
+                            waiting.entry(id).or_default().push(tracker);
+                        }
+                        continue;
+                    }
                 };
 
                 // Add the reload obj if it is `Some`.
@@ -399,28 +653,40 @@ impl<A: Asset> AssetStorage<A> {
             }
         }
 
-        let mut count = 0;
-        let mut skip = 0;
-        while let Some(i) = self.handles.iter().skip(skip).position(Handle::is_unique) {
-            count += 1;
-            // Re-normalize index
-            let i = skip + i;
-            skip = i;
-            let handle = self.handles.swap_remove(i);
-            let id = handle.id();
-            unsafe {
-                let (asset, _) = self.assets.remove(id);
-                drop_fn(asset);
+        // Failures are remembered for deduplicated loads, until the handle is dropped.
+        let unused_handles = &self.unused_handles;
+        self.failed.retain(|_, (handle, _)| {
+            if handle.is_unique() {
+                if let Some(ref deduplicated) = deduplicated {
+                    deduplicated.forget(AssetId::of(handle));
+                }
+                unused_handles.push(Handle {
+                    id: Arc::new(handle.id()),
+                    marker: PhantomData,
+                });
             }
-            self.bitset.remove(id);
+            !handle.is_unique()
+        });
 
-            // Can't reuse old handle here, because otherwise weak handles would still be valid.
-            // TODO: maybe just store u32?
-            self.unused_handles.push(Handle {
-                id: Arc::new(id),
-                marker: PhantomData,
-            });
+        for id in changed {
+            self.track(id);
         }
+
+        let count = match self.budget {
+            Some(budget) => self.evict(budget.bytes, &mut drop_fn, dependencies.as_ref()),
+            None => {
+                let mut count = 0;
+                let mut skip = 0;
+                while let Some(i) = self.handles.iter().skip(skip).position(Handle::is_unique) {
+                    count += 1;
+                    // Re-normalize index
+                    let i = skip + i;
+                    skip = i;
+                    self.free(i, &mut drop_fn, dependencies.as_ref());
+                }
+                count
+            }
+        };
         if count != 0 {
             debug!("{:?}: Freed {} handle ids", A::NAME, count,);
         }
@@ -432,6 +698,170 @@ impl<A: Asset> AssetStorage<A> {
             trace!("{:?}: Testing for asset reloads..", A::NAME);
             self.hot_reload(pool);
         }
+
+        if let Some(ref dependencies) = dependencies {
+            for id in dependencies.take_stale::<A>() {
+                self.reload_dependent(id, pool);
+            }
+        }
+    }
+
+    /// Frees the least recently used assets without handles, until the storage fits into
+    /// `budget` bytes. Returns the number of freed assets.
+    fn evict<D>(
+        &mut self,
+        budget: usize,
+        drop_fn: &mut D,
+        dependencies: Option<&Dependencies>,
+    ) -> usize
+    where
+        D: FnMut(A),
+    {
+        // Only assets without handles count against the budget.
+        let mut unused = Vec::new();
+        let mut bytes = 0;
+        for handle in &self.handles {
+            let id = handle.id();
+            if let Some(meta) = self.meta.get_mut(&id) {
+                if handle.is_unique() {
+                    unused.push((*meta.last_used.get_mut(), id));
+                    bytes += meta.bytes;
+                } else {
+                    *meta.last_used.get_mut() = self.frame_number;
+                }
+            }
+        }
+
+        if bytes <= budget {
+            return 0;
+        }
+
+        unused.sort_unstable();
+        let mut count = 0;
+        for (_, id) in unused {
+            if bytes <= budget {
+                break;
+            }
+            if let Some(i) = self.handles.iter().position(|h| h.id() == id) {
+                bytes -= self.meta.get(&id).map(|m| m.bytes).unwrap_or(0);
+                self.free(i, drop_fn, dependencies);
+                count += 1;
+            }
+        }
+        self.evicted += count as u64;
+        debug!(
+            "{:?}: Evicted {} unused assets to stay within the memory budget of {} bytes",
+            A::NAME,
+            count,
+            budget,
+        );
+
+        count
+    }
+
+    /// Frees the asset of the handle at `index` in `self.handles`.
+    fn free<D>(&mut self, index: usize, drop_fn: &mut D, dependencies: Option<&Dependencies>)
+    where
+        D: FnMut(A),
+    {
+        let handle = self.handles.swap_remove(index);
+        let id = handle.id();
+        unsafe {
+            let (asset, _) = self.assets.remove(id);
+            drop_fn(asset);
+        }
+        self.bitset.remove(id);
+        self.meta.remove(&id);
+        if let Some(dependencies) = dependencies {
+            dependencies.remove(AssetId::from_id::<A>(id));
+        }
+        if let Some(deduplicated) = self.deduplicated.get_mut() {
+            deduplicated.forget(AssetId::from_id::<A>(id));
+        }
+
+        // Can't reuse old handle here, because otherwise weak handles would still be valid.
+        // TODO: maybe just store u32?
+        self.unused_handles.push(Handle {
+            id: Arc::new(id),
+            marker: PhantomData,
+        });
+    }
+
+    /// Gives the id of a load whose handles were all dropped before it completed back to
+    /// the pool of unused ids.
+    fn cancel(
+        unused_handles: &SegQueue<Handle<A>>,
+        dependencies: Option<&Dependencies>,
+        deduplicated: Option<&Deduplicated>,
+        id: u32,
+        name: String,
+        tracker: Box<dyn Tracker>,
+    ) {
+        debug!(
+            "{:?}: Cancelled loading asset {:?} (handle id: {:?}), all handles have been dropped",
+            A::NAME,
+            name,
+            id,
+        );
+        if let Some(dependencies) = dependencies {
+            dependencies.remove(AssetId::from_id::<A>(id));
+        }
+        if let Some(deduplicated) = deduplicated {
+            deduplicated.forget(AssetId::from_id::<A>(id));
+        }
+        tracker.cancel(id, A::NAME, name);
+
+        // Weak handles to the old id must not be upgradable anymore.
+        unused_handles.push(Handle {
+            id: Arc::new(id),
+            marker: PhantomData,
+        });
+    }
+
+    /// Notifies the trackers of deduplicated loads waiting for the asset with the given id.
+    fn notify_waiting(
+        waiting: &mut FnvHashMap<u32, Vec<Box<dyn Tracker>>>,
+        id: u32,
+        name: &str,
+        outcome: LoadOutcome<'_>,
+    ) {
+        for tracker in waiting.remove(&id).unwrap_or_default() {
+            match outcome {
+                LoadOutcome::Loaded => tracker.success(),
+                LoadOutcome::Failed(message) => {
+                    tracker.fail(id, A::NAME, name.to_owned(), format_err!("{}", message))
+                }
+                LoadOutcome::Cancelled => tracker.cancel(id, A::NAME, name.to_owned()),
+            }
+        }
+    }
+
+    /// Reloads the asset with the given id because one of its dependencies was reloaded.
+    fn reload_dependent(&mut self, id: u32, pool: &ThreadPool) {
+        let position = self
+            .reloads
+            .iter()
+            .position(|(handle, _)| handle.upgrade().map(|h| h.id()) == Some(id));
+
+        match position {
+            Some(p) => {
+                let (handle, rel) = self.reloads.swap_remove(p);
+                if let Some(handle) = handle.upgrade() {
+                    debug!(
+                        "{:?}: Asset {:?} (handle id: {:?}) needs a reload because a dependency changed",
+                        A::NAME,
+                        rel.name(),
+                        handle,
+                    );
+                    self.spawn_reload(handle, rel, pool);
+                }
+            }
+            None => debug!(
+                "{:?}: Dependency of asset with handle id {} changed, but it can't be reloaded",
+                A::NAME,
+                id,
+            ),
+        }
     }
 
     fn hot_reload(&mut self, pool: &ThreadPool) {
@@ -456,22 +886,28 @@ impl<A: Asset> AssetStorage<A> {
             );
 
             if let Some(handle) = handle {
-                let processed = self.processed.clone();
-                pool.spawn(move || {
-                    let old_reload = rel.clone();
-                    let data = rel.reload().with_context(|_| error::Error::Format(format));
-
-                    let p = Processed::HotReload {
-                        data,
-                        name,
-                        handle,
-                        old_reload,
-                    };
-                    processed.push(p);
-                });
+                self.spawn_reload(handle, rel, pool);
             }
         }
     }
+
+    fn spawn_reload(&self, handle: Handle<A>, rel: Box<dyn Reload<A::Data>>, pool: &ThreadPool) {
+        let processed = self.processed.clone();
+        pool.spawn(move || {
+            let name = rel.name();
+            let format = rel.format();
+            let old_reload = rel.clone();
+            let data = rel.reload().with_context(|_| error::Error::Format(format));
+
+            let p = Processed::HotReload {
+                data,
+                name,
+                handle,
+                old_reload,
+            };
+            processed.push(p);
+        });
+    }
 }
 
 impl<A: Asset> Default for AssetStorage<A> {
@@ -479,11 +915,19 @@ impl<A: Asset> Default for AssetStorage<A> {
         AssetStorage {
             assets: Default::default(),
             bitset: Default::default(),
+            budget: None,
+            deduplicated: Default::default(),
+            dependencies: Default::default(),
+            evicted: 0,
+            failed: Default::default(),
+            frame_number: 0,
             handles: Default::default(),
             handle_alloc: Default::default(),
+            meta: Default::default(),
             processed: Arc::new(SegQueue::new()),
             reloads: Default::default(),
             unused_handles: SegQueue::new(),
+            waiting: Default::default(),
         }
     }
 }
@@ -601,6 +1045,7 @@ pub(crate) enum Processed<A: Asset> {
         data: Result<FormatValue<A::Data>, Error>,
         handle: Handle<A>,
         name: String,
+        stats: LoadStats,
         tracker: Box<dyn Tracker>,
     },
     HotReload {
@@ -609,6 +1054,24 @@ pub(crate) enum Processed<A: Asset> {
         name: String,
         old_reload: Box<dyn Reload<A::Data>>,
     },
+    Cancelled {
+        id: u32,
+        name: String,
+        tracker: Box<dyn Tracker>,
+    },
+    /// A deduplicated load, which completes together with the load of the asset.
+    Waiting {
+        id: u32,
+        name: String,
+        tracker: Box<dyn Tracker>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum LoadOutcome<'a> {
+    Loaded,
+    Failed(&'a str),
+    Cancelled,
 }
 
 /// A weak handle, which is useful if you don't directly need the asset
@@ -635,4 +1098,162 @@ impl<A> WeakHandle<A> {
     pub fn is_dead(&self) -> bool {
         self.upgrade().is_none()
     }
+
+    pub(crate) fn from_raw(id: Weak<u32>) -> Self {
+        WeakHandle {
+            id,
+            marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn into_raw(self) -> Weak<u32> {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::ThreadPoolBuilder;
+
+    use amethyst_core::ecs::VecStorage;
+    use amethyst_error::format_err;
+
+    use crate::{Asset, Cache, Loader, ProgressCounter};
+
+    use super::{AssetStorage, MemoryBudget, ProcessingState};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Blob(usize);
+
+    impl Asset for Blob {
+        const NAME: &'static str = "Blob";
+        type Data = Self;
+        type HandleStorage = VecStorage<super::Handle<Self>>;
+    }
+
+    fn process(storage: &mut AssetStorage<Blob>, frame: u64) {
+        let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+        storage.process(|b| Ok(ProcessingState::Loaded(b)), frame, &pool, None);
+    }
+
+    #[test]
+    fn unused_assets_are_freed_without_budget() {
+        let mut storage = AssetStorage::<Blob>::new();
+        let handle = storage.insert(Blob(10));
+        let weak = handle.downgrade();
+        drop(handle);
+
+        process(&mut storage, 1);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(0, storage.stats().count);
+    }
+
+    #[test]
+    fn budget_keeps_cached_assets_and_evicts_least_recently_used() {
+        let mut storage = AssetStorage::<Blob>::new();
+        storage.set_budget(Some(MemoryBudget::new(100, |b: &Blob| b.0)));
+        let mut cache = Cache::new();
+
+        process(&mut storage, 1);
+        let old = storage.insert(Blob(40));
+        cache.insert("old", &old);
+        process(&mut storage, 2);
+        let new = storage.insert(Blob(40));
+        cache.insert("new", &new);
+        drop(old);
+        drop(new);
+
+        // Within budget, so both stay available through the cache.
+        process(&mut storage, 3);
+        let stats = storage.stats();
+        assert_eq!((2, 2, 80), (stats.count, stats.unused, stats.bytes));
+        let new = cache.get("new").expect("Asset should be retained");
+        assert_eq!(Some(&Blob(40)), storage.get(&new));
+        process(&mut storage, 4);
+        assert_eq!(4, storage.asset_stats(&new).unwrap().last_used);
+        drop(new);
+
+        // Exceeding the budget evicts the least recently used asset only.
+        let big = storage.insert(Blob(30));
+        cache.insert("big", &big);
+        drop(big);
+        process(&mut storage, 5);
+        assert!(cache.get("old").is_none());
+        assert!(cache.get("new").is_some());
+        let stats = storage.stats();
+        assert_eq!((2, 70, 1), (stats.count, stats.bytes, stats.evicted));
+    }
+
+    #[test]
+    fn budget_counts_unused_assets_and_accesses() {
+        let mut storage = AssetStorage::<Blob>::new();
+        storage.set_budget(Some(MemoryBudget::new(50, |b: &Blob| b.0)));
+        let mut cache = Cache::new();
+
+        process(&mut storage, 1);
+        let used = storage.insert(Blob(100));
+        let a = storage.insert(Blob(20));
+        cache.insert("a", &a);
+        drop(a);
+        process(&mut storage, 2);
+        let b = storage.insert(Blob(20));
+        cache.insert("b", &b);
+        drop(b);
+
+        // Assets in use don't count against the budget.
+        process(&mut storage, 3);
+        assert_eq!(3, storage.stats().count);
+
+        let a = cache.get("a").unwrap();
+        assert_eq!(Some(&Blob(20)), storage.get(&a));
+        drop(a);
+        let c = storage.insert(Blob(20));
+        cache.insert("c", &c);
+        drop(c);
+
+        // Accessing `a` made `b` the least recently used asset.
+        process(&mut storage, 4);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        assert_eq!(Some(&Blob(100)), storage.get(&used));
+    }
+
+    #[test]
+    fn loads_of_dropped_handles_are_cancelled() {
+        let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+        let loader = Loader::new(".", pool.into());
+        let mut storage = AssetStorage::<Blob>::new();
+        let mut progress = ProgressCounter::new();
+
+        let kept = loader.load_from_data(Blob(1), &mut progress, &storage);
+        drop(loader.load_from_data(Blob(2), &mut progress, &storage));
+        process(&mut storage, 1);
+
+        assert_eq!(Some(&Blob(1)), storage.get(&kept));
+        assert_eq!(1, progress.num_cancelled());
+        assert_eq!(0, progress.num_failed());
+        assert!(progress.is_complete());
+        assert_eq!(1, storage.stats().count);
+    }
+
+    #[test]
+    fn failures_are_forgotten_with_their_handles() {
+        let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+        let loader = Loader::new(".", pool.into());
+        let mut storage = AssetStorage::<Blob>::new();
+        let mut progress = ProgressCounter::new();
+
+        let handle = loader.load_from_data(Blob(1), &mut progress, &storage);
+        let id = handle.id();
+        let pool = ThreadPoolBuilder::default().num_threads(1).build().unwrap();
+        storage.process(|_| Err(format_err!("Broken blob")), 1, &pool, None);
+        assert_eq!(1, progress.num_failed());
+        assert!(storage.failed_id(id));
+
+        drop(handle);
+        process(&mut storage, 2);
+        assert!(!storage.failed_id(id));
+        assert_eq!(id, storage.allocate().id());
+    }
 }
@@ -0,0 +1,588 @@
+//! Validation of whole asset directories ahead of time.
+//!
+//! A `Validator` imports every file it has a format for and checks that the sub-assets
+//! the file refers to exist and can be imported, so broken references are found before
+//! the game loads them.
+//!
+//! Sub-assets are found while deserializing: paths deserialized with
+//! `deserialize_asset_path`, like the path of `AssetPrefab::File`, are recorded as
+//! references. If the path is followed by a format deserialized with
+//! `deserialize_asset_format`, the referenced file is imported with it, too, and the
+//! sub-assets it refers to are checked in turn. Prefabs referenced with `PrefabRef` are
+//! recorded and imported the same way. Outside of a `Validator`, both functions just
+//! deserialize.
+
+use std::{
+    cell::RefCell,
+    fmt, fs, mem,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Deserializer};
+
+use amethyst_error::{format_err, Error, ResultExt};
+
+use crate::{Format, FormatRegisteredData, SerializableFormat};
+
+type ValidateFn = Box<dyn Fn(Vec<u8>) -> Result<(), Error> + Send + Sync>;
+
+/// Sub-asset referenced by a file being validated.
+struct Reference {
+    path: String,
+    import: Option<ValidateFn>,
+}
+
+#[derive(Default)]
+struct References {
+    references: Vec<Reference>,
+    /// Index of the last reference, until its format is deserialized.
+    pending: Option<usize>,
+}
+
+thread_local! {
+    static REFERENCES: RefCell<Option<References>> = RefCell::new(None);
+}
+
+/// Runs `f`, returning the references recorded while it ran.
+fn recording<F, R>(f: F) -> (R, Vec<Reference>)
+where
+    F: FnOnce() -> R,
+{
+    let outer = REFERENCES.with(|r| r.replace(Some(References::default())));
+    let result = f();
+    let references = REFERENCES.with(|r| mem::replace(&mut *r.borrow_mut(), outer));
+    (result, references.map(|r| r.references).unwrap_or_default())
+}
+
+/// Deserializes the path of a sub-asset, recording it as a reference of the file being
+/// validated by a `Validator`.
+///
+/// Use it with `#[serde(deserialize_with = "...")]` on the path of custom `PrefabData`
+/// loading sub-assets, so `Validator` checks the path exists. Deserialize the format
+/// following the path with `deserialize_asset_format`, naming the data it imports, so the
+/// file is imported with it:
+///
+/// ```rust,ignore
+/// #[derive(Deserialize)]
+/// pub enum MyTexturePrefab {
+///     File(
+///         #[serde(deserialize_with = "amethyst_assets::deserialize_asset_path")] String,
+///         #[serde(
+///             deserialize_with = "amethyst_assets::deserialize_asset_format::<TextureData, _, _>"
+///         )]
+///         Box<dyn SerializableFormat<TextureData>>,
+///     ),
+/// }
+/// ```
+pub fn deserialize_asset_path<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let path = String::deserialize(deserializer)?;
+    REFERENCES.with(|r| {
+        if let Some(ref mut r) = *r.borrow_mut() {
+            r.pending = Some(r.references.len());
+            r.references.push(Reference {
+                path: path.clone(),
+                import: None,
+            });
+        }
+    });
+    Ok(path)
+}
+
+/// Deserializes the format importing `T` of a sub-asset, whose path has been deserialized
+/// with `deserialize_asset_path` right before. `Validator` imports the referenced file
+/// with it.
+pub fn deserialize_asset_format<'de, T, D, F>(deserializer: D) -> Result<F, D::Error>
+where
+    T: 'static,
+    D: Deserializer<'de>,
+    F: Format<T> + Deserialize<'de>,
+{
+    let pending = REFERENCES.with(|r| match *r.borrow_mut() {
+        Some(ref mut r) => r.pending.take(),
+        None => None,
+    });
+    let format = F::deserialize(deserializer)?;
+    if let Some(index) = pending {
+        let import = objekt::clone(&format);
+        REFERENCES.with(|r| {
+            if let Some(ref mut r) = *r.borrow_mut() {
+                r.references[index].import = Some(Box::new(move |bytes| {
+                    import.import_simple(bytes).map(|_| ())
+                }));
+            }
+        });
+    }
+    Ok(format)
+}
+
+/// Records `import` as the import of the reference whose path has just been
+/// deserialized by `deserialize_asset_path`, if any.
+pub(crate) fn record_import(import: fn(Vec<u8>) -> Result<(), Error>) {
+    REFERENCES.with(|r| {
+        if let Some(ref mut r) = *r.borrow_mut() {
+            if let Some(index) = r.pending.take() {
+                r.references[index].import = Some(Box::new(import));
+            }
+        }
+    });
+}
+
+/// What `Validator::validate_file` found out about a file.
+#[derive(Debug)]
+pub enum ValidationStatus {
+    /// The file and all sub-assets it refers to could be imported.
+    Valid,
+    /// No format is registered for the file.
+    Skipped,
+    /// All problems found in the file.
+    Invalid(Vec<Error>),
+}
+
+/// Summary of a `Validator::validate_dir` run.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// Number of valid files.
+    pub valid: usize,
+    /// Number of files without a registered format.
+    pub skipped: usize,
+    /// All problems found, with the file they were found in.
+    pub problems: Vec<(PathBuf, Error)>,
+}
+
+impl ValidationReport {
+    /// Returns whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} valid, {} skipped, {} problems",
+            self.valid,
+            self.skipped,
+            self.problems.len(),
+        )
+    }
+}
+
+/// Validates whole asset directories, reporting all problems at once.
+///
+/// Formats are registered per path pattern, matched against the path of files relative to
+/// the validated directory. A `*` in a pattern matches any characters including `/`, so
+/// `*.png` matches all PNG files and `ui/*.ron` all RON files below `ui`. If several
+/// patterns match a file, the longest one wins. Patterns are matched case insensitively.
+///
+/// Paths of sub-assets are resolved relative to the validated directory, like the default
+/// source of the `Loader` does. Sub-assets are checked recursively, each file once per
+/// validated file, so cyclic references are fine.
+///
+/// ```rust,ignore
+/// let report = Validator::new()
+///     .with_format::<UiPrefab, _>("ui/*.ron", UiFormat::<NoCustomUi>::default())
+///     .with_format::<Prefab<MyPrefabData>, _>("prefab/*.ron", RonFormat)
+///     .with_registered_format::<TextureData>("*.png", r#"("IMAGE", ())"#)?
+///     .validate_dir("assets")?;
+/// for (path, problem) in &report.problems {
+///     eprintln!("{:?}: {}", path, problem);
+/// }
+/// ```
+#[derive(Default)]
+pub struct Validator {
+    formats: Vec<(String, ValidateFn)>,
+}
+
+impl Validator {
+    /// Creates a validator without any formats.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers `format` for files matching `pattern`.
+    pub fn with_format<D, F>(mut self, pattern: &str, format: F) -> Self
+    where
+        D: 'static,
+        F: Format<D>,
+    {
+        self.add_format::<D, F>(pattern, format);
+        self
+    }
+
+    /// Registers `format` for files matching `pattern`, replacing any format registered
+    /// for it before.
+    pub fn add_format<D, F>(&mut self, pattern: &str, format: F)
+    where
+        D: 'static,
+        F: Format<D>,
+    {
+        let pattern = pattern.to_lowercase();
+        self.formats.retain(|(p, _)| *p != pattern);
+        self.formats.push((
+            pattern,
+            Box::new(move |bytes| format.import_simple(bytes).map(|_| ())),
+        ));
+    }
+
+    /// Registers a format of the registry filled by `register_format!` for files matching
+    /// `pattern`.
+    ///
+    /// The format is given the way prefabs refer to it, e.g. `("IMAGE", ())`. Fails if it
+    /// is not registered for `D` or its options can't be deserialized.
+    pub fn with_registered_format<D>(mut self, pattern: &str, format: &str) -> Result<Self, Error>
+    where
+        D: FormatRegisteredData,
+    {
+        let format: Box<dyn SerializableFormat<D>> = ron::de::from_str(format)
+            .with_context(|_| format_err!("Failed to deserialize format {}", format))?;
+        self.add_format::<D, _>(pattern, format);
+        Ok(self)
+    }
+
+    /// Validates the file at `path`, relative to the asset directory `root`.
+    pub fn validate_file<P, Q>(&self, root: P, path: Q) -> ValidationStatus
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let (root, path) = (root.as_ref(), path.as_ref());
+        let name = path
+            .iter()
+            .map(|c| c.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+            .to_lowercase();
+        let validate = match self
+            .formats
+            .iter()
+            .filter(|(pattern, _)| matches(pattern, &name))
+            .max_by_key(|(pattern, _)| pattern.len())
+        {
+            Some((_, validate)) => validate,
+            None => return ValidationStatus::Skipped,
+        };
+
+        let bytes = match fs::read(root.join(path))
+            .with_context(|_| format_err!("Failed to read asset {:?}", path))
+        {
+            Ok(bytes) => bytes,
+            Err(e) => return ValidationStatus::Invalid(vec![e]),
+        };
+        let (result, references) = recording(|| validate(bytes));
+
+        let mut problems = Vec::new();
+        if let Err(e) = result {
+            problems.push(e);
+        }
+        let mut checked = vec![name];
+        check_references(root, references, &mut checked, &mut problems);
+
+        if problems.is_empty() {
+            ValidationStatus::Valid
+        } else {
+            ValidationStatus::Invalid(problems)
+        }
+    }
+
+    /// Validates all files in `dir` and its subdirectories.
+    ///
+    /// Only failing to list a directory is an error, all problems found in files are
+    /// collected in the report.
+    pub fn validate_dir<P>(&self, dir: P) -> Result<ValidationReport, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let root = dir.as_ref();
+        let mut report = ValidationReport::default();
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            let entries = fs::read_dir(root.join(&dir))
+                .with_context(|_| format_err!("Failed to read directory {:?}", dir))?;
+            for entry in entries {
+                let entry =
+                    entry.with_context(|_| format_err!("Failed to read directory {:?}", dir))?;
+                let path = dir.join(entry.file_name());
+                if entry.path().is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                match self.validate_file(root, &path) {
+                    ValidationStatus::Valid => report.valid += 1,
+                    ValidationStatus::Skipped => report.skipped += 1,
+                    ValidationStatus::Invalid(problems) => report
+                        .problems
+                        .extend(problems.into_iter().map(|e| (path.clone(), e))),
+                }
+            }
+        }
+        report.problems.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(report)
+    }
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Validator")
+            .field(
+                "formats",
+                &self.formats.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Checks that the referenced sub-assets exist and can be imported, and checks the
+/// sub-assets they refer to in turn. Files in `checked` aren't checked again, so cyclic
+/// references end.
+fn check_references(
+    root: &Path,
+    references: Vec<Reference>,
+    checked: &mut Vec<String>,
+    problems: &mut Vec<Error>,
+) {
+    for reference in references {
+        let name = reference.path.to_lowercase();
+        if checked.contains(&name) {
+            continue;
+        }
+        checked.push(name);
+
+        let bytes = match fs::read(root.join(&reference.path)) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                problems.push(format_err!(
+                    "Referenced asset {:?} does not exist",
+                    reference.path
+                ));
+                continue;
+            }
+        };
+        if let Some(ref import) = reference.import {
+            let (result, nested) = recording(|| import(bytes));
+            match result.with_context(|_| {
+                format_err!("Referenced asset {:?} can't be imported", reference.path)
+            }) {
+                Ok(()) => check_references(root, nested, checked, problems),
+                Err(e) => problems.push(e),
+            }
+        }
+    }
+}
+
+/// Matches `name` against `pattern`, where a single `*` matches any characters.
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.find('*') {
+        Some(i) => {
+            let (prefix, suffix) = (&pattern[..i], &pattern[i + 1..]);
+            name.len() >= prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+        }
+        None => pattern == name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use amethyst_core::ecs::storage::VecStorage;
+    use amethyst_error::{format_err, Error};
+    use serde::{Deserialize, Serialize};
+
+    use crate as amethyst_assets;
+    use crate::{Asset, AssetPrefab, Format, Handle, Prefab, RonFormat, SerializableFormat};
+
+    use super::{matches, recording, ValidationStatus, Validator};
+
+    #[derive(Clone, Debug)]
+    struct Text;
+    amethyst_assets::register_format_type!(Text);
+
+    impl Asset for Text {
+        const NAME: &'static str = "TEXT";
+        type Data = Self;
+        type HandleStorage = VecStorage<Handle<Self>>;
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    struct TextFormat;
+    amethyst_assets::register_format!("TEXT", TextFormat as Text);
+
+    impl Format<Text> for TextFormat {
+        fn name(&self) -> &'static str {
+            "TEXT"
+        }
+
+        fn import_simple(&self, bytes: Vec<u8>) -> Result<Text, Error> {
+            String::from_utf8(bytes)
+                .map(|_| Text)
+                .map_err(|_| format_err!("Text is not UTF-8"))
+        }
+    }
+
+    type TextPrefab = Prefab<Option<AssetPrefab<Text>>>;
+
+    fn assets() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("amethyst_validate_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let files: &[(&str, &[u8])] = &[
+            ("text/hello.txt", b"hello"),
+            ("text/broken.txt", &[0xff, 0xfe]),
+            ("prefab/valid.ron", br#"#![enable(implicit_some)] Prefab(entities: [(data: File("text/hello.txt", ("TEXT", ())))])"#),
+            ("prefab/unknown_format.ron", br#"#![enable(implicit_some)] Prefab(entities: [(data: File("text/hello.txt", ("TXT", ())))])"#),
+            ("prefab/broken_references.ron", br#"#![enable(implicit_some)] Prefab(entities: [
+                (data: File("text/missing.txt", ("TEXT", ()))),
+                (data: File("text/broken.txt", ("TEXT", ()))),
+            ])"#),
+            ("readme.md", b"Not an asset"),
+        ];
+        for (path, bytes) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, bytes).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn matches_patterns() {
+        assert!(matches("*.png", "logo.png"));
+        assert!(matches("*.png", "texture/logo.png"));
+        assert!(matches("ui/*.ron", "ui/menu/main.ron"));
+        assert!(!matches("ui/*.ron", "prefab/ui.ron"));
+        assert!(!matches("a*a", "a"));
+        assert!(matches("config.ron", "config.ron"));
+    }
+
+    #[derive(Deserialize)]
+    struct Unpaired {
+        #[serde(deserialize_with = "crate::deserialize_asset_path")]
+        _path: String,
+        _format: Box<dyn SerializableFormat<Text>>,
+    }
+
+    /// Paths referenced by `ron` and whether they are imported.
+    fn references<T>(ron: &str) -> Vec<(String, bool)>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        let (value, references) = recording(|| ron::de::from_str::<T>(ron));
+        value.map_err(|e| e.to_string()).unwrap();
+        references
+            .into_iter()
+            .map(|r| (r.path, r.import.is_some()))
+            .collect()
+    }
+
+    #[test]
+    fn pairs_formats_with_paths_explicitly() {
+        assert_eq!(
+            vec![("a.txt".to_string(), false), ("b.txt".to_string(), true)],
+            references::<(Unpaired, AssetPrefab<Text>)>(
+                r#"((_path: "a.txt", _format: ("TEXT", ())), File("b.txt", ("TEXT", ())))"#
+            )
+        );
+    }
+
+    #[test]
+    fn records_prefab_references() {
+        assert_eq!(
+            vec![("prefab/lamp.ron".to_string(), true)],
+            references::<TextPrefab>(
+                r#"#![enable(implicit_some)] (entities: [(), (parent: 0, prefab: "prefab/lamp.ron")])"#
+            )
+        );
+    }
+
+    #[test]
+    fn checks_references_recursively() {
+        let dir = std::env::temp_dir().join(format!(
+            "amethyst_validate_recursive_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let files: &[(&str, &[u8])] = &[
+            (
+                "outer.ron",
+                br#"#![enable(implicit_some)] (entities: [(), (parent: 0, prefab: "inner.ron")])"#,
+            ),
+            (
+                "inner.ron",
+                br#"#![enable(implicit_some)] Prefab(entities: [
+                (data: File("missing.txt", ("TEXT", ()))),
+                (prefab: "outer.ron"),
+            ])"#,
+            ),
+        ];
+        for (path, bytes) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, bytes).unwrap();
+        }
+
+        let validator = Validator::new().with_format::<TextPrefab, _>("*.ron", RonFormat);
+        match validator.validate_file(&dir, "outer.ron") {
+            ValidationStatus::Invalid(problems) => {
+                assert_eq!(1, problems.len(), "{:?}", problems);
+                assert!(problems[0]
+                    .to_string()
+                    .contains("\"missing.txt\" does not exist"));
+            }
+            status => panic!("Expected an invalid prefab, got {:?}", status),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        let dir = assets();
+        let validator = Validator::new()
+            .with_format::<TextPrefab, _>("prefab/*.ron", RonFormat)
+            .with_registered_format::<Text>("*.txt", r#"("TEXT", ())"#)
+            .unwrap();
+        assert!(Validator::new()
+            .with_registered_format::<Text>("*.txt", r#"("TXT", ())"#)
+            .is_err());
+
+        match validator.validate_file(&dir, "prefab/valid.ron") {
+            ValidationStatus::Valid => {}
+            status => panic!("Expected a valid prefab, got {:?}", status),
+        }
+
+        let report = validator.validate_dir(&dir).unwrap();
+        assert_eq!(2, report.valid);
+        assert_eq!(1, report.skipped);
+        let problems = report
+            .problems
+            .iter()
+            .map(|(path, e)| {
+                let causes = e.causes().map(|e| e.to_string()).collect::<Vec<_>>();
+                (path.to_str().unwrap().replace('\\', "/"), causes.join(": "))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(4, problems.len(), "{:?}", problems);
+        assert_eq!("prefab/broken_references.ron", problems[0].0);
+        assert!(problems[0]
+            .1
+            .contains("\"text/missing.txt\" does not exist"));
+        assert_eq!("prefab/broken_references.ron", problems[1].0);
+        assert!(problems[1]
+            .1
+            .contains("\"text/broken.txt\" can't be imported"));
+        assert!(problems[1].1.contains("Text is not UTF-8"));
+        assert_eq!("prefab/unknown_format.ron", problems[2].0);
+        assert!(problems[2].1.contains("TXT"));
+        assert_eq!("text/broken.txt", problems[3].0);
+        assert!(!report.is_valid());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -19,6 +19,10 @@ use std::{
 use ron::{self, de::Error as DeError, ser::Error as SerError};
 use serde::{Deserialize, Serialize};
 
+use crate::migration::Migrations;
+
+pub mod migration;
+
 /// Error related to anything that manages/creates configurations as well as
 /// "workspace"-related things.
 #[derive(Debug)]
@@ -31,6 +35,8 @@ pub enum ConfigError {
     Serializer(SerError),
     /// Related to the path of the file.
     Extension(PathBuf),
+    /// Upgrading a file of an older version failed.
+    Migration(String),
 }
 
 impl fmt::Display for ConfigError {
@@ -39,6 +45,7 @@ impl fmt::Display for ConfigError {
             ConfigError::File(ref err) => write!(f, "{}", err),
             ConfigError::Parser(ref msg) => write!(f, "{}", msg),
             ConfigError::Serializer(ref msg) => write!(f, "{}", msg),
+            ConfigError::Migration(ref msg) => write!(f, "{}", msg),
             ConfigError::Extension(ref path) => {
                 let found = match path.extension() {
                     Some(extension) => format!("{:?}", extension),
@@ -81,6 +88,7 @@ impl Error for ConfigError {
             ConfigError::Parser(_) => "Project parser error",
             ConfigError::Serializer(_) => "Project serializer error",
             ConfigError::Extension(_) => "Invalid extension or directory for a file",
+            ConfigError::Migration(_) => "Project migration error",
         }
     }
 
@@ -109,6 +117,16 @@ where
     /// Loads configuration structure from raw bytes.
     fn load_bytes(bytes: &[u8]) -> Result<Self, ConfigError>;
 
+    /// Loads a configuration structure from a file, upgrading files of older versions
+    /// with the given migrations.
+    ///
+    /// If `rewrite` is set, migrated files are written back, so they are only migrated once.
+    fn load_migrated<P: AsRef<Path>>(
+        path: P,
+        migrations: &Migrations,
+        rewrite: bool,
+    ) -> Result<Self, ConfigError>;
+
     /// Writes a configuration structure to a file.
     fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError>;
 }
@@ -118,31 +136,25 @@ where
     T: for<'a> Deserialize<'a> + Serialize,
 {
     fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
-        use std::{fs::File, io::Read};
-
-        let path = path.as_ref();
-
-        let content = {
-            let mut file = File::open(path)?;
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer)?;
-
-            buffer
-        };
-
-        if path.extension().and_then(std::ffi::OsStr::to_str) == Some("ron") {
-            Self::load_bytes(&content)
-        } else {
-            Err(ConfigError::Extension(path.to_path_buf()))
-        }
+        Self::load_bytes(&read_ron(path.as_ref())?)
     }
 
     fn load_bytes(bytes: &[u8]) -> Result<Self, ConfigError> {
-        let mut de = ron::de::Deserializer::from_bytes(bytes)?;
-        let val = T::deserialize(&mut de)?;
-        de.end()?;
+        from_ron(bytes)
+    }
+
+    fn load_migrated<P: AsRef<Path>>(
+        path: P,
+        migrations: &Migrations,
+        rewrite: bool,
+    ) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let migrated = migrations.load(&read_ron(path)?)?;
+        if migrated.from.is_some() && rewrite {
+            std::fs::write(path, migrations.write(&migrated.data)?)?;
+        }
 
-        Ok(val)
+        Ok(migrated.data)
     }
 
     fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
@@ -155,3 +167,34 @@ where
         Ok(())
     }
 }
+
+/// Reads a file, which has to have the `ron` extension.
+fn read_ron(path: &Path) -> Result<Vec<u8>, ConfigError> {
+    use std::{fs::File, io::Read};
+
+    let content = {
+        let mut file = File::open(path)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+
+        buffer
+    };
+
+    if path.extension().and_then(std::ffi::OsStr::to_str) == Some("ron") {
+        Ok(content)
+    } else {
+        Err(ConfigError::Extension(path.to_path_buf()))
+    }
+}
+
+/// Deserializes RON bytes, which have to contain nothing but the value.
+fn from_ron<T>(bytes: &[u8]) -> Result<T, ConfigError>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    let mut de = ron::de::Deserializer::from_bytes(bytes)?;
+    let val = T::deserialize(&mut de)?;
+    de.end()?;
+
+    Ok(val)
+}
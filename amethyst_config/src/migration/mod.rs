@@ -0,0 +1,342 @@
+//! Upgrading RON files written for older versions of a type.
+//!
+//! Versioned RON files state their version in a top-level `version` field, files without
+//! one are version `0`:
+//!
+//! ```ron
+//! (
+//!     version: 2,
+//!     title: "Amethyst",
+//!     resolution: (1280, 720),
+//! )
+//! ```
+//!
+//! `Migrations` upgrades files of older versions step by step, by running the migration
+//! registered for each version on the file parsed as `Value`. Files of the current
+//! version are deserialized as they are.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{from_ron, ConfigError};
+
+pub use self::value::Value;
+
+use self::value::{read_version, Document};
+
+mod value;
+
+/// Function upgrading a RON file by a single version.
+pub type Migration = Box<dyn Fn(&mut Value) -> Result<(), ConfigError> + Send + Sync>;
+
+/// Migrations upgrading RON files to the current version of a type.
+///
+/// ### Example
+///
+/// ```
+/// use amethyst_config::{migration::Migrations, ConfigError};
+///
+/// // Version 1 renamed `name` to `title`.
+/// let migrations = Migrations::new(1).with(0, |value| {
+///     if let Some(name) = value.remove_field("name") {
+///         value.insert_field("title", name);
+///     }
+///     Ok(())
+/// });
+///
+/// #[derive(serde_derive::Deserialize)]
+/// struct Window {
+///     title: String,
+/// }
+///
+/// let migrated = migrations.load::<Window>(br#"(name: "Amethyst")"#)?;
+/// assert_eq!("Amethyst", migrated.data.title);
+/// assert_eq!(Some(0), migrated.from);
+/// # Ok::<(), ConfigError>(())
+/// ```
+pub struct Migrations {
+    version: u32,
+    migrations: BTreeMap<u32, Migration>,
+}
+
+/// Data loaded by `Migrations::load`.
+#[derive(Debug)]
+pub struct Migrated<T> {
+    /// The loaded data.
+    pub data: T,
+    /// The version the file has been migrated from, `None` if it already had the
+    /// current version.
+    pub from: Option<u32>,
+}
+
+impl Migrations {
+    /// Creates migrations for the given current version, without any migrations.
+    pub fn new(version: u32) -> Self {
+        Migrations {
+            version,
+            migrations: BTreeMap::new(),
+        }
+    }
+
+    /// Registers the migration upgrading files of version `from` to version `from + 1`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `from` isn't older than the current version.
+    pub fn with<F>(mut self, from: u32, migration: F) -> Self
+    where
+        F: Fn(&mut Value) -> Result<(), ConfigError> + Send + Sync + 'static,
+    {
+        assert!(
+            from < self.version,
+            "Migration from version {} isn't older than the current version {}",
+            from,
+            self.version
+        );
+        self.migrations.insert(from, Box::new(migration));
+        self
+    }
+
+    /// Returns the current version.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Loads a RON file, upgrading it to the current version first.
+    ///
+    /// Fails for files of newer versions and for versions without a registered
+    /// migration.
+    pub fn load<T>(&self, bytes: &[u8]) -> Result<Migrated<T>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let migrated = self.migrate(bytes)?;
+        Ok(Migrated {
+            data: from_ron(&migrated.data)?,
+            from: migrated.from,
+        })
+    }
+
+    /// Upgrades a RON file to the current version, returning the upgraded file.
+    ///
+    /// Files of the current version are returned as they are.
+    pub fn migrate(&self, bytes: &[u8]) -> Result<Migrated<Vec<u8>>, ConfigError> {
+        let from = version(read_version(bytes)?)?;
+        if from == self.version {
+            return Ok(Migrated {
+                data: bytes.to_vec(),
+                from: None,
+            });
+        }
+        if from > self.version {
+            return Err(ConfigError::Migration(format!(
+                "File version {} is newer than the supported version {}",
+                from, self.version
+            )));
+        }
+
+        let mut document = Document::parse(bytes)?;
+        for v in from..self.version {
+            let migration = self.migrations.get(&v).ok_or_else(|| {
+                ConfigError::Migration(format!("No migration from version {}", v))
+            })?;
+            migration(&mut document.value).map_err(|e| {
+                ConfigError::Migration(format!("Migration from version {} failed: {}", v, e))
+            })?;
+        }
+        // The version goes first, so readers know it before the rest of the file.
+        document.value.remove_field("version");
+        if let Value::Struct(_, ref mut fields) = document.value {
+            fields.insert(
+                0,
+                (
+                    "version".to_string(),
+                    Value::Integer(i128::from(self.version)),
+                ),
+            );
+        }
+
+        Ok(Migrated {
+            data: document.to_string().into_bytes(),
+            from: Some(from),
+        })
+    }
+
+    /// Serializes migrated data for writing it back.
+    ///
+    /// Fails if the data doesn't serialize the current version as `version` field, as
+    /// the written file would be migrated again.
+    pub fn write<T>(&self, data: &T) -> Result<String, ConfigError>
+    where
+        T: Serialize,
+    {
+        let s = ron::ser::to_string_pretty(data, Default::default())?;
+        if version(read_version(s.as_bytes())?)? != self.version {
+            return Err(ConfigError::Migration(format!(
+                "Can't write migrated file without a `version: {}` field",
+                self.version
+            )));
+        }
+        Ok(s)
+    }
+}
+
+impl fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrations")
+            .field("version", &self.version)
+            .field("migrations", &self.migrations.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Converts the `version` field of a RON file to a version.
+fn version(value: Option<Value>) -> Result<u32, ConfigError> {
+    match value {
+        None => Ok(0),
+        Some(Value::Integer(n)) if n >= 0 && n <= i128::from(u32::MAX) => Ok(n as u32),
+        Some(v) => Err(ConfigError::Migration(format!(
+            "Expected an unsigned integer as `version`, found {:?}",
+            v
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::{Migrations, Value};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Mode {
+        Windowed,
+        Fullscreen { monitor: u32 },
+        Scaled(f32),
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Window {
+        version: u32,
+        title: String,
+        size: (u32, u32),
+        modes: Vec<Mode>,
+        icon: Option<String>,
+    }
+
+    fn migrations() -> Migrations {
+        Migrations::new(2)
+            .with(0, |value| {
+                let name = value.remove_field("name").unwrap();
+                value.insert_field("title", name);
+                Ok(())
+            })
+            .with(1, |value| {
+                let width = value.remove_field("width").unwrap();
+                let height = value.remove_field("height").unwrap();
+                value.insert_field("size", Value::Tuple(None, vec![width, height]));
+                // Version 2 renamed `Full` to `Fullscreen`.
+                if let Some(Value::Seq(modes)) = value.field_mut("modes") {
+                    for mode in modes {
+                        if let Value::Struct(Some(name), _) = mode {
+                            if name == "Full" {
+                                *name = "Fullscreen".to_string();
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn migrates_old_files_step_by_step() {
+        let migrated = migrations()
+            .load::<Window>(
+                br##"(
+                    // Comments are fine, too.
+                    name: "Amethyst \u{1F48E}",
+                    width: 800,
+                    height: 600,
+                    modes: [Windowed, Full(monitor: 1), Scaled(0.5)],
+                    icon: Some(r#"icon.png"#),
+                )"##,
+            )
+            .unwrap();
+        assert_eq!(Some(0), migrated.from);
+        assert_eq!(
+            Window {
+                version: 2,
+                title: "Amethyst \u{1F48E}".to_string(),
+                size: (800, 600),
+                modes: vec![
+                    Mode::Windowed,
+                    Mode::Fullscreen { monitor: 1 },
+                    Mode::Scaled(0.5)
+                ],
+                icon: Some("icon.png".to_string()),
+            },
+            migrated.data
+        );
+
+        let written = migrations().write(&migrated.data).unwrap();
+        let reloaded = migrations().load::<Window>(written.as_bytes()).unwrap();
+        assert_eq!(None, reloaded.from);
+        assert_eq!(migrated.data, reloaded.data);
+    }
+
+    #[test]
+    fn keeps_extensions() {
+        let migrated = Migrations::new(1)
+            .with(0, |_| Ok(()))
+            .load::<Window>(
+                br#"#![enable(implicit_some)]
+                (
+                    title: "Amethyst",
+                    size: (1, 1),
+                    modes: [],
+                    icon: "icon.png",
+                )"#,
+            )
+            .unwrap();
+        assert_eq!(Some("icon.png".to_string()), migrated.data.icon);
+    }
+
+    #[test]
+    fn loads_large_integers() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Save {
+            version: u32,
+            seed: u64,
+        }
+
+        let migrations = Migrations::new(1).with(0, |_| Ok(()));
+        for ron in &[
+            &b"(version: 1, seed: 18446744073709551615)"[..],
+            &b"(seed: 18446744073709551615)"[..],
+        ] {
+            let save = migrations.load::<Save>(ron).unwrap().data;
+            assert_eq!(u64::MAX, save.seed);
+            assert_eq!(1, save.version);
+        }
+        assert_eq!(
+            &b"(version: 1, seed: 18446744073709551615)"[..],
+            &migrations
+                .migrate(b"(seed: 18446744073709551615, version: 0)")
+                .unwrap()
+                .data[..]
+        );
+    }
+
+    #[test]
+    fn refuses_newer_files() {
+        let err = migrations()
+            .load::<Window>(br#"(version: 3, title: "Amethyst", size: (1, 1))"#)
+            .unwrap_err();
+        assert_eq!(
+            "File version 3 is newer than the supported version 2",
+            err.to_string()
+        );
+    }
+}
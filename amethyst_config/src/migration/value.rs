@@ -0,0 +1,682 @@
+//! RON values as edited by migrations.
+
+use std::fmt::{self, Write};
+
+use crate::ConfigError;
+
+/// Value of a RON file, as passed to migrations.
+///
+/// Unlike `ron::Value`, it keeps the names of structs and enum variants, so migrated
+/// files still deserialize into types containing enums. `Option`s are enum variants,
+/// too: `Some(1)` is a `Tuple` named `Some`, `None` an `Ident`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// `true` or `false`.
+    Bool(bool),
+    /// A character literal.
+    Char(char),
+    /// A number without fractional part or exponent, or a byte literal like `b'a'`.
+    ///
+    /// It's wide enough for all `i64` and `u64` values.
+    Integer(i128),
+    /// A number with fractional part or exponent.
+    Float(f64),
+    /// A string literal.
+    String(String),
+    /// `()`.
+    Unit,
+    /// A lone identifier, i.e. a unit struct or a unit enum variant like `None`.
+    Ident(String),
+    /// `[a, b]`.
+    Seq(Vec<Value>),
+    /// `{a: b}`.
+    Map(Vec<(Value, Value)>),
+    /// `(a, b)`, optionally named like the tuple structs or variants `Some(a)`.
+    Tuple(Option<String>, Vec<Value>),
+    /// `(a: b)`, optionally named like the structs or variants `Rect(w: 1, h: 2)`.
+    Struct(Option<String>, Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the field with the given name, if this is a struct.
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(_, fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns the field with the given name mutably, if this is a struct.
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut Value> {
+        match self {
+            Value::Struct(_, fields) => fields.iter_mut().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Removes the field with the given name, if this is a struct.
+    pub fn remove_field(&mut self, name: &str) -> Option<Value> {
+        match self {
+            Value::Struct(_, fields) => {
+                let index = fields.iter().position(|(n, _)| n == name)?;
+                Some(fields.remove(index).1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets the field with the given name, returning its previous value.
+    ///
+    /// Values other than structs are left as they are.
+    pub fn insert_field<N>(&mut self, name: N, value: Value) -> Option<Value>
+    where
+        N: Into<String>,
+    {
+        let name = name.into();
+        match self.field_mut(&name) {
+            Some(field) => Some(std::mem::replace(field, value)),
+            None => {
+                if let Value::Struct(_, fields) = self {
+                    fields.push((name, value));
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Writes the value as RON.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T>(
+            f: &mut fmt::Formatter<'_>,
+            items: &[T],
+            mut item: impl FnMut(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+        ) -> fmt::Result {
+            for (index, value) in items.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                item(f, value)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Char(c) => f.write_str(&ron::ser::to_string(c).map_err(|_| fmt::Error)?),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::String(s) => f.write_str(&ron::ser::to_string(s).map_err(|_| fmt::Error)?),
+            Value::Unit => f.write_str("()"),
+            Value::Ident(name) => f.write_str(name),
+            Value::Seq(values) => {
+                f.write_char('[')?;
+                list(f, values, |f, value| write!(f, "{}", value))?;
+                f.write_char(']')
+            }
+            Value::Map(entries) => {
+                f.write_char('{')?;
+                list(f, entries, |f, (key, value)| {
+                    write!(f, "{}: {}", key, value)
+                })?;
+                f.write_char('}')
+            }
+            Value::Tuple(name, values) => {
+                f.write_str(name.as_ref().map_or("", String::as_str))?;
+                f.write_char('(')?;
+                list(f, values, |f, value| write!(f, "{}", value))?;
+                f.write_char(')')
+            }
+            Value::Struct(name, fields) => {
+                f.write_str(name.as_ref().map_or("", String::as_str))?;
+                f.write_char('(')?;
+                list(f, fields, |f, (name, value)| {
+                    write!(f, "{}: {}", name, value)
+                })?;
+                f.write_char(')')
+            }
+        }
+    }
+}
+
+/// A RON file, made of its extension attributes like `#![enable(implicit_some)]`
+/// followed by its value.
+pub(crate) struct Document {
+    attributes: Vec<String>,
+    pub(crate) value: Value,
+}
+
+impl Document {
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Self, ConfigError> {
+        let text = utf8(bytes)?;
+        let mut parser = Parser { text, pos: 0 };
+        let attributes = parser.attributes()?;
+        let value = parser.value()?;
+        parser.skip_ws()?;
+        if parser.pos != text.len() {
+            return Err(parser.error("Trailing characters"));
+        }
+        Ok(Document { attributes, value })
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for attribute in &self.attributes {
+            writeln!(f, "{}", attribute)?;
+        }
+        write!(f, "{}", self.value)
+    }
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str, ConfigError> {
+    std::str::from_utf8(bytes)
+        .map_err(|e| ConfigError::Migration(format!("File isn't valid UTF-8: {}", e)))
+}
+
+/// Reads the top-level `version` field of a RON file, `None` if it has none.
+///
+/// Other values are skipped without parsing them, so files of the current version are
+/// never rejected by the parser.
+pub(crate) fn read_version(bytes: &[u8]) -> Result<Option<Value>, ConfigError> {
+    let mut parser = Parser {
+        text: utf8(bytes)?,
+        pos: 0,
+    };
+    parser.attributes()?;
+    parser.skip_ws()?;
+    parser.ident();
+    parser.skip_ws()?;
+    if !parser.consume("(") {
+        return Ok(None);
+    }
+    loop {
+        parser.skip_ws()?;
+        let name = match parser.ident() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        parser.skip_ws()?;
+        if parser.rest().starts_with("::") || !parser.consume(":") {
+            return Ok(None);
+        }
+        if name == "version" {
+            return parser.value().map(Some);
+        }
+        parser.skip_value()?;
+        if !parser.consume(",") {
+            return Ok(None);
+        }
+    }
+}
+
+/// Parser of RON files, which keeps names where `ron` drops them.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn error(&self, msg: &str) -> ConfigError {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        ConfigError::Migration(format!("{} at {}:{}", msg, line, column))
+    }
+
+    /// Consumes `s` if the rest starts with it.
+    fn consume(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), ConfigError> {
+        self.skip_ws()?;
+        if self.consume(s) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected `{}`", s)))
+        }
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_ws(&mut self) -> Result<(), ConfigError> {
+        loop {
+            let trimmed = self.rest().trim_start();
+            self.pos = self.text.len() - trimmed.len();
+            if self.consume("//") {
+                self.pos += self.rest().find('\n').unwrap_or_else(|| self.rest().len());
+            } else if self.consume("/*") {
+                match self.rest().find("*/") {
+                    Some(end) => self.pos += end + 2,
+                    None => return Err(self.error("Unterminated comment")),
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn attributes(&mut self) -> Result<Vec<String>, ConfigError> {
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_ws()?;
+            if !self.rest().starts_with("#!") {
+                return Ok(attributes);
+            }
+            let end = self
+                .rest()
+                .find(']')
+                .ok_or_else(|| self.error("Unterminated attribute"))?;
+            attributes.push(self.rest()[..=end].to_string());
+            self.pos += end + 1;
+        }
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let rest = self.rest();
+        if !rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        Some(rest[..len].to_string())
+    }
+
+    fn value(&mut self) -> Result<Value, ConfigError> {
+        self.skip_ws()?;
+        let rest = self.rest();
+        match self.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some('r') if rest[1..].starts_with(&['"', '#'][..]) => {
+                self.raw_string().map(Value::String)
+            }
+            Some('\'') => self.char().map(Value::Char),
+            Some('b') if rest[1..].starts_with('\'') => {
+                self.pos += 1;
+                let c = self.char()?;
+                if (c as u32) > 0xff {
+                    return Err(self.error("Byte literal out of range"));
+                }
+                Ok(Value::Integer(i128::from(c as u32)))
+            }
+            Some('[') => {
+                self.pos += 1;
+                let values = self.list("]", Parser::value)?;
+                Ok(Value::Seq(values))
+            }
+            Some('{') => {
+                self.pos += 1;
+                let entries = self.list("}", |p| {
+                    let key = p.value()?;
+                    p.expect(":")?;
+                    Ok((key, p.value()?))
+                })?;
+                Ok(Value::Map(entries))
+            }
+            Some('(') => self.parenthesized(None),
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' || c == '.' => self.number(),
+            _ => {
+                let name = self.ident().ok_or_else(|| self.error("Expected a value"))?;
+                match name.as_str() {
+                    "true" => return Ok(Value::Bool(true)),
+                    "false" => return Ok(Value::Bool(false)),
+                    _ => {}
+                }
+                self.skip_ws()?;
+                if self.peek() == Some('(') {
+                    self.parenthesized(Some(name))
+                } else {
+                    Ok(Value::Ident(name))
+                }
+            }
+        }
+    }
+
+    /// Skips a value, only keeping track of nesting, strings and characters, and stops
+    /// in front of the `,` or closing bracket following it.
+    fn skip_value(&mut self) -> Result<(), ConfigError> {
+        let mut depth = 0usize;
+        loop {
+            self.skip_ws()?;
+            let rest = self.rest();
+            match self.peek() {
+                None => return Err(self.error("Unexpected end of file")),
+                Some(',') | Some(')') | Some(']') | Some('}') if depth == 0 => return Ok(()),
+                Some('(') | Some('[') | Some('{') => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                Some(')') | Some(']') | Some('}') => {
+                    depth -= 1;
+                    self.pos += 1;
+                }
+                Some('"') => {
+                    self.string()?;
+                }
+                Some('r') if rest[1..].starts_with(&['"', '#'][..]) => {
+                    self.raw_string()?;
+                }
+                Some('b') if rest[1..].starts_with('\'') => {
+                    self.pos += 1;
+                    self.char()?;
+                }
+                Some('\'') => {
+                    self.char()?;
+                }
+                Some(c) => {
+                    if self.ident().is_none() {
+                        self.pos += c.len_utf8();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parses comma separated items up to `end`, allowing a trailing comma.
+    fn list<T>(
+        &mut self,
+        end: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T, ConfigError>,
+    ) -> Result<Vec<T>, ConfigError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws()?;
+            if self.consume(end) {
+                return Ok(items);
+            }
+            items.push(item(self)?);
+            self.skip_ws()?;
+            if !self.consume(",") {
+                self.expect(end)?;
+                return Ok(items);
+            }
+        }
+    }
+
+    /// Parses a tuple or struct after its name, if any.
+    fn parenthesized(&mut self, name: Option<String>) -> Result<Value, ConfigError> {
+        self.expect("(")?;
+        self.skip_ws()?;
+        let start = self.pos;
+        let is_struct = self.ident().is_some() && {
+            self.skip_ws()?;
+            self.rest().starts_with(':') && !self.rest().starts_with("::")
+        };
+        self.pos = start;
+
+        if is_struct {
+            let fields = self.list(")", |p| {
+                p.skip_ws()?;
+                let name = p.ident().ok_or_else(|| p.error("Expected a field name"))?;
+                p.expect(":")?;
+                Ok((name, p.value()?))
+            })?;
+            Ok(Value::Struct(name, fields))
+        } else {
+            let values = self.list(")", Parser::value)?;
+            if name.is_none() && values.is_empty() {
+                Ok(Value::Unit)
+            } else {
+                Ok(Value::Tuple(name, values))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ConfigError> {
+        let rest = self.rest();
+        let mut len = 0;
+        for (index, c) in rest.char_indices() {
+            let sign_allowed = index == 0 || rest[..index].ends_with(&['e', 'E'][..]);
+            if c.is_ascii_alphanumeric()
+                || c == '.'
+                || c == '_'
+                || (sign_allowed && (c == '+' || c == '-'))
+            {
+                len = index + c.len_utf8();
+            } else {
+                break;
+            }
+        }
+        let literal = rest[..len].replace('_', "");
+        let (negative, digits) = match literal.chars().next() {
+            Some('-') => (true, &literal[1..]),
+            Some('+') => (false, &literal[1..]),
+            _ => (false, &literal[..]),
+        };
+        let radix = match digits.get(..2) {
+            Some("0x") => 16,
+            Some("0b") => 2,
+            Some("0o") => 8,
+            _ => 10,
+        };
+        let value = if radix != 10 {
+            i128::from_str_radix(&digits[2..], radix)
+                .ok()
+                .map(|i| Value::Integer(if negative { -i } else { i }))
+        } else if digits.contains(&['.', 'e', 'E'][..]) {
+            literal.parse().ok().map(Value::Float)
+        } else {
+            literal.parse().ok().map(Value::Integer)
+        };
+        let value = value.ok_or_else(|| self.error(&format!("Invalid number `{}`", literal)))?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    fn escape(&mut self) -> Result<char, ConfigError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("Unterminated escape"))?;
+        self.pos += c.len_utf8();
+        let hex = |p: &mut Self, len: usize| {
+            let digits = p.rest().get(..len).unwrap_or("");
+            let code = u32::from_str_radix(digits, 16)
+                .ok()
+                .and_then(std::char::from_u32)
+                .ok_or_else(|| p.error("Invalid escape"))?;
+            p.pos += len;
+            Ok(code)
+        };
+        match c {
+            '\'' | '"' | '\\' => Ok(c),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            'x' => hex(self, 2),
+            'u' if self.consume("{") => {
+                let len = self.rest().find('}').unwrap_or(0);
+                let c = hex(self, len)?;
+                self.pos += 1;
+                Ok(c)
+            }
+            _ => Err(self.error("Invalid escape")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ConfigError> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("Unterminated string"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => s.push(self.escape()?),
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn raw_string(&mut self) -> Result<String, ConfigError> {
+        self.pos += 1;
+        let hashes = self.rest().len() - self.rest().trim_start_matches('#').len();
+        self.pos += hashes;
+        if !self.consume("\"") {
+            return Err(self.error("Expected `\"`"));
+        }
+        let end = format!("\"{}", "#".repeat(hashes));
+        let len = self
+            .rest()
+            .find(&end)
+            .ok_or_else(|| self.error("Unterminated raw string"))?;
+        let s = self.rest()[..len].to_string();
+        self.pos += len + end.len();
+        Ok(s)
+    }
+
+    fn char(&mut self) -> Result<char, ConfigError> {
+        self.pos += 1;
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("Unterminated character"))?;
+        self.pos += c.len_utf8();
+        let c = if c == '\\' { self.escape()? } else { c };
+        if self.consume("'") {
+            Ok(c)
+        } else {
+            Err(self.error("Unterminated character"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_derive::Deserialize;
+
+    use super::{read_version, Document, Value};
+
+    fn parse(ron: &str) -> Value {
+        Document::parse(ron.as_bytes()).unwrap().value
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape {
+        Circle { radius: f32 },
+        Rect(u32, u32),
+        Empty,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Literals {
+        seed: u64,
+        min: i64,
+        hex: u32,
+        bin: u8,
+        oct: u16,
+        separated: u32,
+        byte: u8,
+        escaped_byte: u8,
+        float: f64,
+        exponent: f64,
+        fraction: f32,
+        c: char,
+        escaped: String,
+        raw: String,
+        unit: (),
+        some: Option<u8>,
+        none: Option<u8>,
+        shapes: Vec<Shape>,
+        map: std::collections::BTreeMap<String, (bool, i8)>,
+    }
+
+    const LITERALS: &str = r##"#![enable(unwrap_newtypes)]
+    Literals(
+        seed: 18446744073709551615,
+        min: -9223372036854775807,
+        hex: 0xFF_FF,
+        bin: 0b1010,
+        oct: 0o17,
+        separated: 1_000_000,
+        byte: b'a',
+        escaped_byte: b'\x7f',
+        float: -1.5,
+        exponent: 1e-3,
+        fraction: .25,
+        c: '\u{1F48E}',
+        escaped: "tab\t\"quote\"",
+        raw: r#"C:\"path""#,
+        unit: (),
+        some: Some(1),
+        none: None,
+        shapes: [Circle(radius: 1.0), Rect(2, 3), Empty],
+        map: {"a": (true, -1)},
+    )"##;
+
+    #[test]
+    fn parses_all_literals() {
+        let value = parse(LITERALS);
+        let field = |name| value.field(name).unwrap().clone();
+        assert_eq!(Value::Integer(i128::from(u64::MAX)), field("seed"));
+        assert_eq!(Value::Integer(-i128::from(i64::MAX)), field("min"));
+        assert_eq!(Value::Integer(0xffff), field("hex"));
+        assert_eq!(Value::Integer(10), field("bin"));
+        assert_eq!(Value::Integer(15), field("oct"));
+        assert_eq!(Value::Integer(1_000_000), field("separated"));
+        assert_eq!(Value::Integer(97), field("byte"));
+        assert_eq!(Value::Integer(127), field("escaped_byte"));
+        assert_eq!(Value::Float(-1.5), field("float"));
+        assert_eq!(Value::Float(1e-3), field("exponent"));
+        assert_eq!(Value::Float(0.25), field("fraction"));
+        assert_eq!(Value::Char('\u{1F48E}'), field("c"));
+        assert_eq!(
+            Value::String("tab\t\"quote\"".to_string()),
+            field("escaped")
+        );
+        assert_eq!(Value::String(r#"C:\"path""#.to_string()), field("raw"));
+        assert_eq!(Value::Unit, field("unit"));
+        assert_eq!(
+            Value::Tuple(Some("Some".to_string()), vec![Value::Integer(1)]),
+            field("some")
+        );
+        assert_eq!(Value::Ident("None".to_string()), field("none"));
+    }
+
+    #[test]
+    fn round_trips() {
+        let document = Document::parse(LITERALS.as_bytes()).unwrap();
+        let printed = document.to_string();
+        assert_eq!(document.value, parse(&printed));
+        assert_eq!(
+            ron::de::from_str::<Literals>(
+                &LITERALS.replace("b'a'", "97").replace("b'\\x7f'", "127")
+            )
+            .unwrap(),
+            ron::de::from_str::<Literals>(&printed).unwrap()
+        );
+    }
+
+    #[test]
+    fn reads_only_the_version() {
+        let version = |ron: &str| read_version(ron.as_bytes()).unwrap();
+        assert_eq!(
+            Some(Value::Integer(2)),
+            version("#![enable(implicit_some)] Window(a: (1, [2]), b: \"),\", version: 2)")
+        );
+        // Values before the version aren't parsed.
+        assert_eq!(
+            Some(Value::Integer(1)),
+            version("(seed: 99999999999999999999999, c: ')', version: 1)")
+        );
+        assert_eq!(None, version("(title: \"version\")"));
+        assert_eq!(None, version("(1, 2)"));
+        assert_eq!(None, version("[]"));
+    }
+}
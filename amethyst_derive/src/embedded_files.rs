@@ -0,0 +1,102 @@
+//! EmbeddedFiles Implementation
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Lit, Meta, NestedMeta};
+
+pub fn impl_embedded_files(ast: &DeriveInput) -> TokenStream {
+    let name = &ast.ident;
+
+    let dir = embedded_dir(ast).unwrap_or_else(|| {
+        panic!(
+            r#"
+#[derive(EmbeddedFiles)] requested for {}, but #[embed(dir = "...")] attribute is missing
+
+Example usage:
+#[derive(EmbeddedFiles)]
+#[embed(dir = "assets")]
+struct Assets;
+"#,
+            name
+        )
+    });
+    let root = PathBuf::from(
+        env::var("CARGO_MANIFEST_DIR").expect("`CARGO_MANIFEST_DIR` is not set by cargo"),
+    )
+    .join(dir);
+
+    let mut files = Vec::new();
+    collect_files(&root, "", &mut files);
+    files.sort();
+    let paths = files.iter().map(|(path, _)| path);
+    let full_paths = files
+        .iter()
+        .map(|(_, full_path)| full_path.to_str().expect("Non UTF-8 path"));
+
+    let build_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    quote! {
+        impl EmbeddedFiles for #name {
+            const FILES: &'static [(&'static str, &'static [u8])] = &[
+                #((#paths, include_bytes!(#full_paths)),)*
+            ];
+
+            const BUILD_TIME: u64 = #build_time;
+        }
+    }
+}
+
+/// Reads the directory of the `#[embed(dir = "...")]` attribute.
+fn embedded_dir(ast: &DeriveInput) -> Option<String> {
+    ast.attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("embed"))
+        .map(|attr| {
+            attr.parse_meta()
+                .expect("embed attribute incorrectly defined")
+        })
+        .filter_map(|meta| match meta {
+            Meta::List(list) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .map(|nested| match nested {
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("dir") => match nv.lit {
+                Lit::Str(ref dir) => dir.value(),
+                _ => panic!("embed dir has to be a string"),
+            },
+            _ => panic!("embed attribute only supports `dir = \"...\"`"),
+        })
+        .next()
+}
+
+/// Collects all files below `dir` recursively, with their paths relative to the
+/// embedded directory.
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) {
+    let entries = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("Failed to read embedded directory {:?}: {}", dir, e));
+    for entry in entries {
+        let entry =
+            entry.unwrap_or_else(|e| panic!("Failed to read embedded directory {:?}: {}", dir, e));
+        let name = entry
+            .file_name()
+            .into_string()
+            .unwrap_or_else(|name| panic!("Non UTF-8 file name {:?}", name));
+        let path = format!("{}{}", prefix, name);
+        let full_path = entry.path();
+        if full_path.is_dir() {
+            collect_files(&full_path, &format!("{}/", path), files);
+        } else {
+            files.push((path, full_path));
+        }
+    }
+}
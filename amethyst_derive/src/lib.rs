@@ -1,5 +1,5 @@
 //! This crate implements various derive macros for easing the use of various amethyst features.
-//! At the moment, this consists of event readers, prefab and UI widget derives.
+//! At the moment, this consists of event readers, prefab, embedded file and UI widget derives.
 
 #![recursion_limit = "256"]
 #![warn(
@@ -17,11 +17,24 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
 
+mod embedded_files;
 mod event_reader;
 mod prefab_data;
+mod prefab_merge;
 mod system_desc;
 mod widget_id;
 
+/// Deriving `EmbeddedFiles` embeds all files of the directory named by the
+/// `#[embed(dir = "...")]` attribute, relative to the crate's `Cargo.toml`, into the
+/// executable. It requires that `amethyst::assets::EmbeddedFiles` is imported and visible
+/// in the current scope.
+#[proc_macro_derive(EmbeddedFiles, attributes(embed))]
+pub fn embedded_files_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let gen = embedded_files::impl_embedded_files(&ast);
+    gen.into()
+}
+
 /// EventReader
 #[proc_macro_derive(EventReader, attributes(reader))]
 pub fn event_reader_derive(input: TokenStream) -> TokenStream {
@@ -34,6 +47,11 @@ pub fn event_reader_derive(input: TokenStream) -> TokenStream {
 /// `amethyst:assets::{PrefabData, ProgressCounter}` and
 /// `amethyst::error::Error` are imported and visible in the current scope. This
 /// is due to how Rust macros work.
+///
+/// With `#[prefab(Extract)]` on the type, `PrefabExtract` is derived as well, which also
+/// requires `amethyst::assets::PrefabExtract` and `amethyst::ecs::ReadStorage` to be
+/// imported. Extracting fails for an entity if any field can't be extracted, so use
+/// `Option` fields for components which may be missing.
 #[proc_macro_derive(PrefabData, attributes(prefab))]
 pub fn prefab_data_derive(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
@@ -41,6 +59,19 @@ pub fn prefab_data_derive(input: TokenStream) -> TokenStream {
     gen.into()
 }
 
+/// Deriving `PrefabMerge` requires that `amethyst::assets::PrefabMerge` is imported and
+/// visible in the current scope.
+///
+/// Fields are merged one by one, so e.g. `Option` fields of an overriding prefab only
+/// replace the base's data if they are `Some`. Types and fields marked with
+/// `#[prefab(Component)]`, as well as enums, are replaced as a whole.
+#[proc_macro_derive(PrefabMerge, attributes(prefab))]
+pub fn prefab_merge_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let gen = prefab_merge::impl_prefab_merge(&ast);
+    gen.into()
+}
+
 /// This allows the use of an enum as an ID for the `Widgets` resource. One
 /// variant has to be marked as the default variant with `#[widget_id_default]
 /// and will be used when a `Widget` is added to the resource without an
@@ -8,10 +8,120 @@ use syn::{
 };
 
 pub fn impl_prefab_data(ast: &DeriveInput) -> TokenStream {
-    if is_component_prefab(&ast.attrs[..]) {
+    let prefab_data = if is_component_prefab(&ast.attrs[..]) {
         impl_prefab_data_component(ast)
     } else {
         impl_prefab_data_aggregate(ast)
+    };
+    if is_extract_prefab(&ast.attrs[..]) {
+        let prefab_extract = impl_prefab_extract(ast);
+        quote! {
+            #prefab_data
+            #prefab_extract
+        }
+    } else {
+        prefab_data
+    }
+}
+
+fn impl_prefab_extract(ast: &DeriveInput) -> TokenStream {
+    let base = &ast.ident;
+    let (_, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let lf_tokens = gen_def_lt_tokens(&ast.generics);
+    let ty_tokens = gen_def_ty_params(&ast.generics);
+
+    let (system_data, extract) = if is_component_prefab(&ast.attrs[..]) {
+        (
+            quote! {
+                ReadStorage<'pfe, #base #ty_generics>
+            },
+            quote! {
+                Ok(system_data.get(entity).cloned())
+            },
+        )
+    } else {
+        let fields = match &ast.data {
+            Data::Struct(ref s) => &s.fields,
+            _ => panic!("PrefabData extract derive only supports structs"),
+        };
+        let mut data_types = Vec::new();
+        let extract_fields = fields.iter().enumerate().map(|(field_number, field)| {
+            let is_component = is_component_prefab(&field.attrs[..]);
+            let i = match data_types
+                .iter()
+                .position(|t: &(Type, bool)| t.0 == field.ty && t.1 == is_component)
+            {
+                Some(i) => i,
+                None => {
+                    data_types.push((field.ty.clone(), is_component));
+                    data_types.len() - 1
+                }
+            };
+            let tuple_index = Literal::usize_unsuffixed(i);
+            let name = field
+                .ident
+                .clone()
+                .unwrap_or_else(|| Ident::new(&format!("field_{}", field_number), Span::call_site()));
+            let ty = &field.ty;
+            let value = if is_component {
+                quote! {
+                    system_data.#tuple_index.get(entity).cloned()
+                }
+            } else {
+                quote! {
+                    <#ty as PrefabExtract<'pfe>>::extract(entity, &system_data.#tuple_index, entities)?
+                }
+            };
+            quote! {
+                let #name = match #value {
+                    Some(data) => data,
+                    None => return Ok(None),
+                };
+            }
+        }).collect::<Vec<_>>();
+        let field_names = fields.iter().enumerate().map(|(field_number, field)| {
+            field.ident.clone().unwrap_or_else(|| {
+                Ident::new(&format!("field_{}", field_number), Span::call_site())
+            })
+        });
+        let construct = match fields {
+            Fields::Named(_) => quote! { #base { #(#field_names,)* } },
+            Fields::Unnamed(_) => quote! { #base ( #(#field_names,)* ) },
+            Fields::Unit => quote! { #base },
+        };
+        let system_data = data_types.iter().map(|(ty, is_component)| {
+            if *is_component {
+                quote! {
+                    ReadStorage<'pfe, #ty>
+                }
+            } else {
+                quote! {
+                    <#ty as PrefabExtract<'pfe>>::SystemData
+                }
+            }
+        });
+        (
+            quote! {
+                (#(#system_data,)*)
+            },
+            quote! {
+                #(#extract_fields)*
+                Ok(Some(#construct))
+            },
+        )
+    };
+
+    quote! {
+        impl<'pfe, #lf_tokens #ty_tokens> PrefabExtract<'pfe> for #base #ty_generics #where_clause {
+            type SystemData = #system_data;
+
+            #[allow(unused_variables)]
+            fn extract(entity: Entity,
+                       system_data: &Self::SystemData,
+                       entities: &[Entity]) -> ::std::result::Result<Option<Self>, Error> {
+                #extract
+            }
+        }
     }
 }
 
@@ -322,7 +432,16 @@ fn gen_def_ty_params(generics: &Generics) -> TokenStream {
     quote! { #( #ty_params ),* }
 }
 
-fn is_component_prefab(attrs: &[Attribute]) -> bool {
+pub(crate) fn is_component_prefab(attrs: &[Attribute]) -> bool {
+    has_prefab_flag(attrs, "Component")
+}
+
+#[inline]
+fn is_extract_prefab(attrs: &[Attribute]) -> bool {
+    has_prefab_flag(attrs, "Extract")
+}
+
+fn has_prefab_flag(attrs: &[Attribute], flag: &str) -> bool {
     for meta in attrs
         .iter()
         .filter(|attr| attr.path.segments[0].ident == "prefab")
@@ -335,7 +454,7 @@ fn is_component_prefab(attrs: &[Attribute]) -> bool {
             for nested_meta in l.nested.iter() {
                 match nested_meta {
                     NestedMeta::Meta(Meta::Path(path)) => {
-                        if let Some(true) = path.get_ident().map(|word| word == "Component") {
+                        if let Some(true) = path.get_ident().map(|word| word == flag) {
                             return true;
                         }
                     }
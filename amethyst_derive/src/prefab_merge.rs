@@ -0,0 +1,50 @@
+//! PrefabMerge Implementation
+
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
+use syn::{Data, DeriveInput};
+
+use crate::prefab_data::is_component_prefab;
+
+pub fn impl_prefab_merge(ast: &DeriveInput) -> TokenStream {
+    let base = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let merge = match &ast.data {
+        Data::Struct(data) if !is_component_prefab(&ast.attrs[..]) => {
+            let fields = data.fields.iter().enumerate().map(|(field_number, field)| {
+                let member = match &field.ident {
+                    Some(name) => quote!(#name),
+                    None => {
+                        let number = Literal::usize_unsuffixed(field_number);
+                        quote!(#number)
+                    }
+                };
+                if is_component_prefab(&field.attrs[..]) {
+                    quote! {
+                        self.#member = patch.#member;
+                    }
+                } else {
+                    quote! {
+                        PrefabMerge::merge(&mut self.#member, patch.#member);
+                    }
+                }
+            });
+            quote! {
+                #(#fields)*
+            }
+        }
+        // Components and enums can only be replaced as a whole.
+        _ => quote! {
+            *self = patch;
+        },
+    };
+
+    quote! {
+        impl #impl_generics PrefabMerge for #base #ty_generics #where_clause {
+            fn merge(&mut self, patch: Self) {
+                #merge
+            }
+        }
+    }
+}
@@ -3,11 +3,11 @@
     clippy::block_in_if_condition_stmt,
     clippy::unneeded_field_pattern
 )]
-use amethyst_derive::{EventReader, PrefabData};
+use amethyst_derive::{EmbeddedFiles, EventReader, PrefabData, PrefabMerge};
 
-use amethyst_assets::{PrefabData, ProgressCounter};
+use amethyst_assets::{EmbeddedFiles, PrefabData, PrefabExtract, PrefabMerge, ProgressCounter};
 use amethyst_core::{
-    ecs::{Component, DenseVecStorage, Entity, Read, SystemData, World, WriteStorage},
+    ecs::{Component, DenseVecStorage, Entity, Read, ReadStorage, SystemData, World, WriteStorage},
     shrev::{EventChannel, ReaderId},
     EventReader,
 };
@@ -42,8 +42,8 @@ where
     Four(TestEvent3<T2>),
 }
 
-#[derive(Clone, PrefabData, Default)]
-#[prefab(Component)]
+#[derive(Clone, PrefabData, PrefabMerge, Default)]
+#[prefab(Component, Extract)]
 pub struct Stuff<T>
 where
     T: Default + Clone + Send + Sync + 'static,
@@ -84,6 +84,22 @@ pub struct Outer {
 #[derive(PrefabData, Clone)]
 pub struct OuterTuple(#[prefab(Component)] External);
 
+#[derive(PrefabData, Clone)]
+#[prefab(Extract)]
+pub struct ExtractedPrefab {
+    stuff: Option<Stuff<u32>>,
+    #[prefab(Component)]
+    external: External,
+}
+
+#[derive(PrefabData, PrefabMerge, Clone)]
+pub struct MergedPrefab {
+    stuff: Option<Stuff<u32>>,
+    named: Option<Stuff<String>>,
+    #[prefab(Component)]
+    external: External,
+}
+
 #[derive(PrefabData, Clone)]
 pub enum EnumPrefab {
     One {
@@ -98,10 +114,14 @@ pub enum EnumPrefab {
     Five(Stuff<String>, #[prefab(Component)] External),
 }
 
+#[derive(EmbeddedFiles)]
+#[embed(dir = "tests/embedded")]
+pub struct TestAssets;
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use amethyst_assets::{AssetStorage, Loader, Prefab, PrefabLoaderSystemDesc};
+    use amethyst_assets::{AssetStorage, Loader, Prefab, PrefabExtractor, PrefabLoaderSystemDesc};
     use amethyst_core::ecs::{world::EntitiesRes, Builder, Join, WorldExt};
     use amethyst_test::prelude::*;
 
@@ -129,6 +149,71 @@ mod tests {
         };
     }
 
+    #[test]
+    fn embed_directory() {
+        use amethyst_assets::{Embedded, Source};
+
+        let paths = TestAssets::FILES
+            .iter()
+            .map(|(path, _)| *path)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["config.ron", "texture/logo.txt"], paths);
+
+        let source = Embedded::new::<TestAssets>();
+        assert_eq!(b"logo".to_vec(), source.load("texture/logo.txt").unwrap());
+        assert_eq!(
+            TestAssets::BUILD_TIME,
+            source.modified("config.ron").unwrap()
+        );
+    }
+
+    #[test]
+    fn extract_struct_prefabs() {
+        let mut world = World::new();
+        world.register::<Stuff<u32>>();
+        world.register::<External>();
+        world.register::<amethyst_core::Parent>();
+        let root = world
+            .create_entity()
+            .with(Stuff { inner: 7u32 })
+            .with(External { inner: 3 })
+            .build();
+        world
+            .create_entity()
+            .with(amethyst_core::Parent { entity: root })
+            .build();
+
+        let prefab = world
+            .exec(|extractor: PrefabExtractor<'_, ExtractedPrefab>| extractor.extract(root))
+            .unwrap();
+        let mut entities = prefab.entities();
+        let main = entities.next().unwrap().data().unwrap();
+        assert_eq!(Some(7), main.stuff.as_ref().map(|stuff| stuff.inner));
+        assert_eq!(3, main.external.inner);
+        // The child has no `External`, so there's nothing to extract.
+        assert!(entities.next().unwrap().data().is_none());
+    }
+
+    #[test]
+    fn merge_struct_prefabs_field_by_field() {
+        let mut base = MergedPrefab {
+            stuff: Some(Stuff { inner: 1 }),
+            named: Some(Stuff {
+                inner: "goblin".into(),
+            }),
+            external: External { inner: 1 },
+        };
+        base.merge(MergedPrefab {
+            stuff: Some(Stuff { inner: 2 }),
+            named: None,
+            external: External { inner: 2 },
+        });
+
+        assert_eq!(Some(2), base.stuff.map(|stuff| stuff.inner));
+        assert_eq!(Some("goblin".into()), base.named.map(|named| named.inner));
+        assert_eq!(2, base.external.inner);
+    }
+
     #[test]
     fn instantiate_struct_prefabs() {
         assert_prefab!(
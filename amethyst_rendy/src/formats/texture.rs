@@ -112,7 +112,13 @@ pub enum TexturePrefab {
     /// Generate texture
     Generate(TextureGenerator),
     /// Load file with format
-    File(String, Box<dyn SerializableFormat<TextureData>>),
+    File(
+        #[serde(deserialize_with = "amethyst_assets::deserialize_asset_path")] String,
+        #[serde(
+            deserialize_with = "amethyst_assets::deserialize_asset_format::<TextureData, _, _>"
+        )]
+        Box<dyn SerializableFormat<TextureData>>,
+    ),
 
     /// Clone handle only
     #[serde(skip)]
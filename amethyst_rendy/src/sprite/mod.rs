@@ -3,7 +3,7 @@ use ron::de::from_bytes as from_ron_bytes;
 use serde::{Deserialize, Serialize};
 
 use crate::{error, types::Texture};
-use amethyst_assets::{Asset, Format, Handle};
+use amethyst_assets::{Asset, AssetId, Format, Handle};
 use amethyst_core::ecs::prelude::{Component, DenseVecStorage};
 use amethyst_error::Error;
 
@@ -478,6 +478,10 @@ impl Format<SpriteSheet> for SpriteSheetFormat {
         "SPRITE_SHEET"
     }
 
+    fn dependencies(&self) -> Vec<AssetId> {
+        vec![AssetId::of(&self.0)]
+    }
+
     fn import_simple(&self, bytes: Vec<u8>) -> Result<SpriteSheet, Error> {
         let sprites: Sprites =
             from_ron_bytes(&bytes).map_err(error::Error::LoadSpritesheetError)?;
@@ -7,6 +7,7 @@ use amethyst::{
     error::Error,
     input::{BindingTypes, InputBundle},
     prelude::*,
+    replay::EventReplay,
     shred::Resource,
     ui::UiBundle,
     utils::application_root_dir,
@@ -18,7 +19,7 @@ use lazy_static::lazy_static;
 
 use crate::{
     CustomDispatcherStateBuilder, FunctionState, GameUpdate, SequencerState,
-    SystemDescInjectionBundle, SystemInjectionBundle, ThreadLocalInjectionBundle,
+    SystemDescInjectionBundle, SystemInjectionBundle, ThreadLocalInjectionBundle, WaitForLoad,
 };
 
 type BundleAddFn = Box<
@@ -401,6 +402,24 @@ where
         self
     }
 
+    /// Replays a recording made with an `EventRecorder` before the states registered after it.
+    ///
+    /// The recorded events and frame times are fed to the application from the first frame on,
+    /// and the following states only run once all frames have been replayed. This allows using
+    /// recordings of bugs as regression tests.
+    ///
+    /// # Parameters
+    ///
+    /// * `replay`: Recording to replay.
+    pub fn with_replay(self, replay: EventReplay) -> Self {
+        self.with_resource(replay.with_stop_at_end(false))
+            .with_state(|| {
+                WaitForLoad::new_with_fn(|world| {
+                    world.read_resource::<EventReplay>().remaining() == 0
+                })
+            })
+    }
+
     /// Adds a state to run in the Amethyst application.
     ///
     /// # Parameters
@@ -1198,4 +1217,39 @@ mod test {
     impl Component for ComponentZero {
         type Storage = DenseVecStorage<Self>;
     }
+
+    #[test]
+    fn replay_runs_before_following_states() -> Result<(), Error> {
+        use std::time::Duration;
+
+        use amethyst::{
+            input::{InputBundle, InputHandler, StringBindings},
+            replay::{EventReplay, RecordedEvent, RecordedFrame},
+            winit::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode},
+        };
+
+        let key = |state| RecordedFrame {
+            delta: Duration::from_millis(10),
+            events: vec![RecordedEvent::KeyboardInput(KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(VirtualKeyCode::Space),
+                modifiers: ModifiersState::default(),
+            })],
+        };
+        let replay = EventReplay::from_frames(vec![
+            key(ElementState::Released),
+            key(ElementState::Pressed),
+        ]);
+
+        AmethystApplication::blank()
+            .with_bundle(InputBundle::<StringBindings>::new())
+            .with_resource(ScreenDimensions::new(640, 480, 1.))
+            .with_replay(replay)
+            .with_assertion(|world| {
+                let input = world.read_resource::<InputHandler<StringBindings>>();
+                assert!(input.key_is_down(VirtualKeyCode::Space));
+            })
+            .run()
+    }
 }
@@ -1,7 +1,8 @@
-//! An example showing how to create a dispatcher inside of a State.
+//! An example showing how to attach systems to a State.
 
 use amethyst::{
-    ecs::{Dispatcher, DispatcherBuilder, WorldExt},
+    derive::SystemDesc,
+    ecs::{System, SystemData, WorldExt},
     prelude::*,
     shrev::EventChannel,
     utils::application_root_dir,
@@ -10,6 +11,18 @@ use amethyst::{
 
 use std::marker::PhantomData;
 
+/// Only runs while `StateB` is active.
+#[derive(Default, SystemDesc)]
+struct StateBSystem;
+
+impl<'a> System<'a> for StateBSystem {
+    type SystemData = ();
+
+    fn run(&mut self, _: Self::SystemData) {
+        println!("StateBSystem::run()");
+    }
+}
+
 struct StateA;
 
 impl SimpleState for StateA {
@@ -31,23 +44,29 @@ impl SimpleState for StateA {
 
 /// StateB isn't Send + Sync
 struct StateB<'a> {
-    dispatcher: Dispatcher<'static, 'static>,
     _phantom: &'a PhantomData<()>,
 }
 
 impl<'a> Default for StateB<'a> {
     fn default() -> Self {
         StateB {
-            dispatcher: DispatcherBuilder::new().build(),
             _phantom: &PhantomData,
         }
     }
 }
 
 impl<'a> SimpleState for StateB<'a> {
-    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+    // The systems are set up when the state starts and disposed when it stops.
+    fn systems(&mut self) -> Option<StateSystems<'static, 'static>> {
+        Some(StateSystems::new(GameDataBuilder::default().with(
+            StateBSystem,
+            "state_b_system",
+            &[],
+        )))
+    }
+
+    fn update(&mut self, _: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
         println!("StateB::update()");
-        self.dispatcher.dispatch(&data.world);
         Trans::Quit
     }
 }
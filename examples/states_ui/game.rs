@@ -86,6 +86,8 @@ impl SimpleState for Game {
                 log::info!("Input Event detected: {:?}.", input);
                 Trans::None
             }
+            #[cfg(feature = "saveload")]
+            StateEvent::SaveLoad(_) => Trans::None,
         }
     }
 
@@ -71,6 +71,8 @@ impl SimpleState for Example {
                 info!("Input Event detected: {:?}.", input);
                 Trans::None
             }
+            #[cfg(feature = "saveload")]
+            StateEvent::SaveLoad(_) => Trans::None,
         }
     }
 
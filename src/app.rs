@@ -1,10 +1,10 @@
 //! The core engine framework.
 
-use std::{env, marker::PhantomData, path::Path, sync::Arc, time::Duration};
+use std::{collections::HashMap, env, marker::PhantomData, path::Path, sync::Arc, time::Duration};
 
 use crate::shred::Resource;
 use derivative::Derivative;
-use log::{debug, info, log_enabled, trace, Level};
+use log::{debug, info, log_enabled, trace, warn, Level};
 use rayon::ThreadPoolBuilder;
 #[cfg(feature = "sentry")]
 use sentry::integrations::panic::register_panic_handler;
@@ -25,11 +25,18 @@ use crate::{
     ecs::prelude::{Component, Read, World, WorldExt, Write},
     error::Error,
     game_data::{DataDispose, DataInit},
-    state::{State, StateData, StateMachine, TransEvent},
+    replay::{EventRecorder, EventReplay},
+    state::{
+        NamedState, State, StateData, StateMachine, StateRequest, StateStack, StateTransitionEvent,
+        Trans, TransEvent,
+    },
     state_event::{StateEvent, StateEventReader},
     ui::UiEvent,
 };
 
+/// Creates a new instance of a state registered with `ApplicationBuilder::with_named_state`.
+type StateFactory<T, E> = Box<dyn Fn() -> Box<dyn State<T, E>>>;
+
 /// `CoreApplication` is the application implementation for the game engine. This is fully generic
 /// over the state type and event type.
 ///
@@ -58,9 +65,17 @@ where
     event_reader_id: ReaderId<Event>,
     #[derivative(Debug = "ignore")]
     trans_reader_id: ReaderId<TransEvent<T, E>>,
+    request_reader_id: ReaderId<StateRequest>,
+    #[derivative(Debug = "ignore")]
+    state_factories: HashMap<String, StateFactory<T, E>>,
     states: StateMachine<'a, T, E>,
     ignore_window_close: bool,
     data: T,
+    virtual_frame_time: Option<Duration>,
+    frame_budget: Option<u64>,
+    frames: u64,
+    started: bool,
+    stopped: bool,
 }
 
 /// An Application is the root object of the game engine. It binds the OS
@@ -237,35 +252,148 @@ where
         };
 
         self.initialize();
-        self.world.write_resource::<Stopwatch>().start();
         while self.states.is_running() {
-            self.advance_frame();
-            {
-                #[cfg(feature = "profiler")]
-                profile_scope!("frame_limiter wait");
-                self.world.write_resource::<FrameLimiter>().wait();
-            }
-            {
-                let elapsed = self.world.read_resource::<Stopwatch>().elapsed();
-                let mut time = self.world.write_resource::<Time>();
-                time.increment_frame_number();
-                time.set_delta_time(elapsed);
-            }
-            let mut stopwatch = self.world.write_resource::<Stopwatch>();
-            stopwatch.stop();
-            stopwatch.restart();
+            self.step();
         }
 
         self.shutdown();
     }
 
-    /// Sets up the application.
+    /// Runs at most `frames` frames of the gameloop and returns how many frames were run.
+    ///
+    /// Starts the application on the first call. Fewer frames are run if the game stops, in
+    /// which case the application is shut down as it would be by `run`. Together with
+    /// [`ApplicationBuilder::with_virtual_clock`](struct.ApplicationBuilder.html#method.with_virtual_clock)
+    /// this allows stepping whole game loops from tests.
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// use std::time::Duration;
+    ///
+    /// use amethyst::{core::Time, prelude::*};
+    ///
+    /// struct NullState;
+    /// impl EmptyState for NullState {}
+    ///
+    /// # fn main() -> amethyst::Result<()> {
+    /// #
+    /// let mut game = Application::build("assets/", NullState)?
+    ///     .with_virtual_clock(Duration::from_millis(20))
+    ///     .build(())?;
+    /// assert_eq!(game.run_frames(50), 50);
+    /// assert_eq!(game.world().read_resource::<Time>().frame_number(), 50);
+    /// #     Ok(())
+    /// # }
+    /// ~~~
+    pub fn run_frames(&mut self, frames: u64) -> u64
+    where
+        for<'b> R: EventReader<'b, Event = E>,
+    {
+        self.initialize();
+        let mut ran = 0;
+        while ran < frames && self.states.is_running() {
+            self.step();
+            ran += 1;
+        }
+        if !self.states.is_running() {
+            self.shutdown();
+        }
+        ran
+    }
+
+    /// Checks whether the game is still running, i.e. has not been stopped by its states.
+    pub fn is_running(&self) -> bool {
+        !self.stopped && (!self.started || self.states.is_running())
+    }
+
+    /// The `World` of the application.
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// The `World` of the application, mutably.
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Sets up the application, unless it has already been set up.
     fn initialize(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+
         #[cfg(feature = "profiler")]
         profile_scope!("initialize");
+        if let Some(mut recorder) = self.world.try_fetch_mut::<EventRecorder>() {
+            recorder.register(&mut self.world.write_resource::<EventChannel<Event>>());
+        }
         self.states
             .start(StateData::new(&mut self.world, &mut self.data))
             .expect("Tried to start state machine without any states present");
+        self.world.write_resource::<Stopwatch>().start();
+    }
+
+    /// Runs one frame and advances `Time`.
+    fn step(&mut self)
+    where
+        for<'b> R: EventReader<'b, Event = E>,
+    {
+        self.advance_frame();
+        self.frames += 1;
+        if let Some(budget) = self.frame_budget {
+            if self.frames >= budget && self.states.is_running() {
+                info!("Frame budget of {} frames used up", budget);
+                self.states
+                    .stop(StateData::new(&mut self.world, &mut self.data));
+            }
+        }
+
+        let delta = if let Some(frame_time) = self.virtual_frame_time {
+            frame_time
+        } else {
+            {
+                #[cfg(feature = "profiler")]
+                profile_scope!("frame_limiter wait");
+                self.world.write_resource::<FrameLimiter>().wait();
+            }
+            self.world.read_resource::<Stopwatch>().elapsed()
+        };
+        let delta = self.replay_frame().unwrap_or(delta);
+        {
+            let mut time = self.world.write_resource::<Time>();
+            time.increment_frame_number();
+            time.set_delta_time(delta);
+        }
+        self.record_frame(delta);
+        let mut stopwatch = self.world.write_resource::<Stopwatch>();
+        stopwatch.stop();
+        stopwatch.restart();
+    }
+
+    /// Writes the events of the next frame of the `EventReplay`, if any, and returns the
+    /// recorded frame time.
+    fn replay_frame(&mut self) -> Option<Duration> {
+        let (delta, finished) = {
+            let mut replay = self.world.try_fetch_mut::<EventReplay>()?;
+            let mut events = self.world.write_resource::<EventChannel<Event>>();
+            (replay.replay_frame(&mut events), replay.finished())
+        };
+        if finished && self.states.is_running() {
+            info!("Replay finished");
+            self.states
+                .stop(StateData::new(&mut self.world, &mut self.data));
+        }
+        delta
+    }
+
+    /// Records the events of the frame if there is an `EventRecorder`.
+    fn record_frame(&mut self, delta: Duration) {
+        if let Some(mut recorder) = self.world.try_fetch_mut::<EventRecorder>() {
+            let mut events = self.world.write_resource::<EventChannel<Event>>();
+            recorder.record_frame(&mut events, delta);
+        }
     }
 
     // React to window close events
@@ -302,6 +430,36 @@ where
         }
     }
 
+    /// Converts a `StateRequest` into the `Trans` it stands for, or `None` if it refers to
+    /// an unknown state.
+    fn request_trans(&self, request: StateRequest) -> Option<Trans<T, E>> {
+        let create = |name: String| match self.state_factories.get(&name) {
+            Some(factory) => {
+                let state = NamedState::new(name, factory());
+                Some(Box::new(state) as Box<dyn State<T, E>>)
+            }
+            None => {
+                warn!("Requested state `{}` has not been registered", name);
+                None
+            }
+        };
+        match request {
+            StateRequest::Pop => Some(Trans::Pop),
+            StateRequest::Quit => Some(Trans::Quit),
+            StateRequest::Push(name) => create(name).map(Trans::Push),
+            StateRequest::Switch(name) => create(name).map(Trans::Switch),
+            StateRequest::Replace(name) => create(name).map(Trans::Replace),
+            StateRequest::PopTo(name) => {
+                let stack = self.world.read_resource::<StateStack>();
+                let pops = stack.names().rev().position(|n| n == name);
+                if pops.is_none() {
+                    warn!("Requested state `{}` is not on the state stack", name);
+                }
+                pops.map(|pops| Trans::Sequence((0..pops).map(|_| Trans::Pop).collect()))
+            }
+        }
+    }
+
     /// Advances the game world by one tick.
     fn advance_frame(&mut self)
     where
@@ -330,6 +488,22 @@ where
             }
         }
 
+        // Read the StateRequest queue and apply changes.
+        {
+            let requests = self
+                .world
+                .read_resource::<EventChannel<StateRequest>>()
+                .read(&mut self.request_reader_id)
+                .cloned()
+                .collect::<Vec<_>>();
+            for request in requests {
+                if let Some(tr) = self.request_trans(request) {
+                    self.states
+                        .transition(tr, StateData::new(&mut self.world, &mut self.data));
+                }
+            }
+        }
+
         {
             #[cfg(feature = "profiler")]
             profile_scope!("run_callback_queue");
@@ -367,6 +541,7 @@ where
             while { self.world.write_resource::<Time>().step_fixed_update() } {
                 self.states
                     .fixed_update(StateData::new(&mut self.world, &mut self.data));
+                self.data.fixed_update(&self.world);
             }
             {
                 self.world.write_resource::<Time>().finish_fixed_update();
@@ -384,8 +559,12 @@ where
         self.world.maintain();
     }
 
-    /// Cleans up after the quit signal is received.
+    /// Cleans up after the quit signal is received, unless that has already been done.
     fn shutdown(&mut self) {
+        if self.stopped {
+            return;
+        }
+        self.stopped = true;
         info!("Engine is shutting down");
         self.data.dispose(&mut self.world);
     }
@@ -416,7 +595,10 @@ pub struct ApplicationBuilder<S, T, E, R> {
     initial_state: S,
     /// Used by bundles to access the world directly
     pub world: World,
+    state_factories: HashMap<String, StateFactory<T, E>>,
     ignore_window_close: bool,
+    virtual_frame_time: Option<Duration>,
+    frame_budget: Option<u64>,
     phantom: PhantomData<(T, E, R)>,
 }
 
@@ -550,13 +732,19 @@ where
         world.insert(Stopwatch::default());
         world.insert(Time::default());
         world.insert(CallbackQueue::default());
+        world.insert(StateStack::default());
+        world.insert(EventChannel::<StateTransitionEvent>::with_capacity(8));
+        world.insert(EventChannel::<StateRequest>::with_capacity(8));
 
         world.register::<Named>();
 
         Ok(Self {
             initial_state,
             world,
+            state_factories: HashMap::new(),
             ignore_window_close: false,
+            virtual_frame_time: None,
+            frame_budget: None,
             phantom: PhantomData,
         })
     }
@@ -838,6 +1026,88 @@ where
         self
     }
 
+    /// Drives the application with a virtual clock instead of the wall clock.
+    ///
+    /// Every frame advances `Time` by exactly `frame_time`, however long it actually took, and
+    /// the frame limiter is not used, so frames run as fast as possible. This makes the number
+    /// of fixed updates per frame and everything derived from `Time` reproducible, e.g. for
+    /// headless simulations, servers and tests. Systems are dispatched on a single thread as
+    /// well, so that systems which could run in parallel always run in the same order.
+    ///
+    /// Asset loading still happens in the background; states should wait for their
+    /// `ProgressCounter` rather than for a number of frames.
+    ///
+    /// # Parameters
+    ///
+    /// `frame_time`: The time every frame takes.
+    ///
+    /// # Returns
+    ///
+    /// This function returns the ApplicationBuilder after modifying it.
+    pub fn with_virtual_clock(mut self, frame_time: Duration) -> Self {
+        self.virtual_frame_time = Some(frame_time);
+        self
+    }
+
+    /// Registers a state under the given name, so that it can be entered with a `StateRequest`.
+    ///
+    /// A new instance is created by `factory` for each request; the state is listed under
+    /// `name` in the `StateStack`.
+    ///
+    /// # Parameters
+    ///
+    /// `name`: The name to request the state by.
+    /// `factory`: Creates the state.
+    ///
+    /// # Returns
+    ///
+    /// This function returns the ApplicationBuilder after modifying it.
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// use amethyst::prelude::*;
+    ///
+    /// struct LoadingState;
+    /// impl EmptyState for LoadingState {}
+    /// struct PauseMenu;
+    /// impl EmptyState for PauseMenu {}
+    ///
+    /// # fn main() -> amethyst::Result<()> {
+    /// let mut game = Application::build("assets/", LoadingState)?
+    ///     .with_named_state("pause_menu", || PauseMenu)
+    ///     .build(())?;
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn with_named_state<N, F>(mut self, name: &str, factory: F) -> Self
+    where
+        N: State<T, E> + 'static,
+        F: Fn() -> N + 'static,
+        E: Send + Sync + 'static,
+    {
+        self.state_factories.insert(
+            name.to_string(),
+            Box::new(move || Box::new(factory()) as Box<dyn State<T, E>>),
+        );
+        self
+    }
+
+    /// Stops the application after the given number of frames, as if the states returned
+    /// `Trans::Quit`.
+    ///
+    /// # Parameters
+    ///
+    /// `frames`: The number of frames to run.
+    ///
+    /// # Returns
+    ///
+    /// This function returns the ApplicationBuilder after modifying it.
+    pub fn with_frame_budget(mut self, frames: u64) -> Self {
+        self.frame_budget = Some(frames);
+        self
+    }
+
     /// Tells the resulting application window to ignore close events if ignore is true.
     /// This will make your game window unresponsive to operating system close commands.
     /// Use with caution.
@@ -862,8 +1132,8 @@ where
     ///
     /// # Errors
     ///
-    /// This function currently will not produce an error, returning a result
-    /// type was strictly for future possibilities.
+    /// This function returns an error if the single threaded pool of
+    /// [`with_virtual_clock`](#method.with_virtual_clock) can't be created.
     ///
     /// # Notes
     ///
@@ -889,6 +1159,14 @@ where
         #[cfg(feature = "profiler")]
         profile_scope!("new");
 
+        if self.virtual_frame_time.is_some() {
+            let pool: ArcThreadPool = ThreadPoolBuilder::new()
+                .num_threads(1)
+                .build()
+                .map(Arc::new)?;
+            self.world.insert(pool);
+        }
+
         let mut reader = X::default();
         reader.setup(&mut self.world);
         let data = init.build(&mut self.world);
@@ -899,6 +1177,9 @@ where
         let trans_reader_id = self
             .world
             .exec(|mut ev: Write<'_, EventChannel<TransEvent<T, E>>>| ev.register_reader());
+        let request_reader_id = self
+            .world
+            .exec(|mut ev: Write<'_, EventChannel<StateRequest>>| ev.register_reader());
 
         Ok(CoreApplication {
             world: self.world,
@@ -909,6 +1190,145 @@ where
             data,
             event_reader_id,
             trans_reader_id,
+            request_reader_id,
+            state_factories: self.state_factories,
+            virtual_frame_time: self.virtual_frame_time,
+            frame_budget: self.frame_budget,
+            frames: 0,
+            started: false,
+            stopped: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::{EmptyState, EmptyTrans, Trans},
+        GameData, GameDataBuilder,
+    };
+
+    #[derive(Default)]
+    struct Counts {
+        updates: u32,
+        fixed_updates: u32,
+    }
+
+    struct Counting;
+
+    impl EmptyState for Counting {
+        fn on_start(&mut self, data: StateData<'_, ()>) {
+            data.world.insert(Counts::default());
+        }
+
+        fn fixed_update(&mut self, data: StateData<'_, ()>) -> EmptyTrans {
+            data.world.write_resource::<Counts>().fixed_updates += 1;
+            Trans::None
+        }
+
+        fn update(&mut self, data: StateData<'_, ()>) -> EmptyTrans {
+            data.world.write_resource::<Counts>().updates += 1;
+            Trans::None
+        }
+    }
+
+    #[test]
+    fn virtual_clock_and_frame_budget() {
+        let mut game = Application::build(".", Counting)
+            .unwrap()
+            .with_virtual_clock(Duration::from_millis(30))
+            .with_fixed_step_length(Duration::from_millis(10))
+            .with_frame_budget(10)
+            .build(())
+            .unwrap();
+
+        assert_eq!(game.run_frames(4), 4);
+        assert!(game.is_running());
+        assert_eq!(
+            game.world().read_resource::<Time>().absolute_time(),
+            Duration::from_millis(120)
+        );
+
+        // The budget stops the game after 6 more frames.
+        assert_eq!(game.run_frames(100), 6);
+        assert!(!game.is_running());
+        let counts = game.world().read_resource::<Counts>();
+        assert_eq!(counts.updates, 10);
+        // No fixed updates are due in the first frame, three in each of the others.
+        assert_eq!(counts.fixed_updates, 27);
+    }
+
+    struct Menu;
+
+    impl EmptyState for Menu {}
+
+    fn request(game: &mut Application<'_, ()>, request: StateRequest) {
+        game.world_mut()
+            .write_resource::<EventChannel<StateRequest>>()
+            .single_write(request);
+        game.run_frames(1);
+    }
+
+    #[test]
+    fn state_requests() {
+        let mut game = Application::build(".", Counting)
+            .unwrap()
+            .with_virtual_clock(Duration::from_millis(10))
+            .with_named_state("menu", || Menu)
+            .build(())
+            .unwrap();
+        game.run_frames(1);
+
+        let stack = |game: &Application<'_, ()>| {
+            let stack = game.world().read_resource::<StateStack>();
+            stack.names().map(String::from).collect::<Vec<_>>()
+        };
+        let counting = std::any::type_name::<Counting>().to_string();
+        assert_eq!(stack(&game), vec![counting.clone()]);
+
+        request(&mut game, StateRequest::Push("menu".into()));
+        request(&mut game, StateRequest::Push("menu".into()));
+        request(&mut game, StateRequest::Push("unknown".into()));
+        assert_eq!(
+            stack(&game),
+            vec![counting.clone(), "menu".into(), "menu".into()]
+        );
+
+        request(&mut game, StateRequest::PopTo(counting.clone()));
+        assert_eq!(stack(&game), vec![counting]);
+
+        request(&mut game, StateRequest::Quit);
+        assert!(!game.is_running());
+    }
+
+    struct CountFixed;
+
+    impl<'a> crate::ecs::System<'a> for CountFixed {
+        type SystemData = Write<'a, Counts>;
+
+        fn run(&mut self, mut counts: Self::SystemData) {
+            counts.fixed_updates += 1;
+        }
+    }
+
+    /// A state on `GameData` which isn't a `SimpleState`.
+    struct Custom;
+
+    impl State<GameData<'static, 'static>, StateEvent> for Custom {}
+
+    #[test]
+    fn fixed_systems_run_for_any_state() {
+        let mut game = Application::build(".", Custom)
+            .unwrap()
+            .with_resource(Counts::default())
+            .with_virtual_clock(Duration::from_millis(30))
+            .with_fixed_step_length(Duration::from_millis(10))
+            .build(GameDataBuilder::default().with_fixed(CountFixed, "count_fixed", &[]))
+            .unwrap();
+
+        game.run_frames(3);
+        // No fixed updates are due in the first frame, three in each of the others.
+        assert_eq!(game.world().read_resource::<Counts>().fixed_updates, 6);
+    }
+}
@@ -0,0 +1,46 @@
+//! Cooks an assets directory ahead of time.
+//!
+//! Usage: `amethyst_cook <assets directory> <cache directory>`
+//!
+//! Textures and OBJ meshes are imported with the default options of their format, so
+//! games loading them with `Loader::load_cooked` and the same formats use the cache.
+
+use std::{env, process};
+
+use amethyst::{
+    assets::{CookCache, Cooker},
+    renderer::{formats::mesh::ObjFormat, types::MeshData, types::TextureData, ImageFormat},
+    start_logger,
+};
+
+fn main() {
+    start_logger(Default::default());
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 2 {
+        eprintln!("Usage: amethyst_cook <assets directory> <cache directory>");
+        process::exit(2);
+    }
+
+    let mut cooker =
+        Cooker::new(CookCache::new(&args[1])).with_format::<MeshData, _>("obj", ObjFormat);
+    for extension in &["png", "jpg", "jpeg", "bmp", "tga", "gif"] {
+        cooker.add_format::<TextureData, _>(extension, ImageFormat::default());
+    }
+
+    match cooker.cook_dir(&args[0]) {
+        Ok(report) => {
+            for (path, error) in &report.failed {
+                eprintln!("{:?}: {}", path, error);
+            }
+            println!("{}", report);
+            if !report.failed.is_empty() {
+                process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Validates an assets directory, reporting all broken assets at once.
+//!
+//! Usage: `amethyst_validate [--sprite-sheets <pattern>]... [--sprite-prefabs <pattern>]...
+//! <assets directory>`
+//!
+//! Textures, meshes, fonts, sounds, locales and UI files are imported with the formats
+//! registered with `register_format!`, and the sub-assets UI files refer to are checked.
+//! Sprite sheet definitions, by default `texture/*.ron`, are parsed, and sprite prefabs
+//! (`SpriteScenePrefab`) are imported together with the textures and prefabs they refer
+//! to. Prefabs of game specific types need a `Validator` knowing their type, e.g. in a
+//! binary of the game itself.
+
+use std::{env, process};
+
+use amethyst::{
+    assets::{Asset, FormatRegisteredData, Prefab, RonFormat, Validator},
+    error::Error,
+    renderer::{
+        sprite::{prefab::SpriteScenePrefab, Sprites},
+        Mesh, Texture,
+    },
+    start_logger,
+    ui::{FontAsset, NoCustomUi, UiFormat, UiPrefab},
+};
+
+/// Registers the registered `format` for all files matching one of `patterns`.
+fn with_registered<A>(
+    mut validator: Validator,
+    patterns: &[&str],
+    format: &str,
+) -> Result<Validator, Error>
+where
+    A: Asset,
+    A::Data: FormatRegisteredData,
+{
+    for pattern in patterns {
+        validator = validator.with_registered_format::<A::Data>(pattern, format)?;
+    }
+    Ok(validator)
+}
+
+const USAGE: &str = "Usage: amethyst_validate [--sprite-sheets <pattern>]... \
+                     [--sprite-prefabs <pattern>]... <assets directory>";
+
+/// Command line arguments.
+struct Args {
+    dir: String,
+    sprite_sheets: Vec<String>,
+    sprite_prefabs: Vec<String>,
+}
+
+impl Args {
+    fn parse<I>(args: I) -> Option<Args>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter();
+        let mut dir = None;
+        let mut sprite_sheets = Vec::new();
+        let mut sprite_prefabs = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sprite-sheets" => sprite_sheets.push(args.next()?),
+                "--sprite-prefabs" => sprite_prefabs.push(args.next()?),
+                _ if dir.is_none() && !arg.starts_with("--") => dir = Some(arg),
+                _ => return None,
+            }
+        }
+        if sprite_sheets.is_empty() {
+            sprite_sheets.push("texture/*.ron".to_string());
+        }
+        Some(Args {
+            dir: dir?,
+            sprite_sheets,
+            sprite_prefabs,
+        })
+    }
+}
+
+fn validator(args: &Args) -> Result<Validator, Error> {
+    let mut validator =
+        Validator::new().with_format::<UiPrefab, _>("ui/*.ron", UiFormat::<NoCustomUi>::default());
+    for pattern in &args.sprite_sheets {
+        validator = validator.with_format::<Sprites, _>(pattern, RonFormat);
+    }
+    for pattern in &args.sprite_prefabs {
+        validator = validator.with_format::<Prefab<SpriteScenePrefab>, _>(pattern, RonFormat);
+    }
+    validator = with_registered::<Texture>(
+        validator,
+        &["*.png", "*.jpg", "*.jpeg", "*.bmp", "*.tga", "*.gif"],
+        r#"("IMAGE", ())"#,
+    )?;
+    validator = with_registered::<Mesh>(validator, &["*.obj"], r#"("OBJ", ())"#)?;
+    validator = with_registered::<FontAsset>(validator, &["*.ttf", "*.otf"], r#"("TTF", ())"#)?;
+    #[cfg(feature = "audio")]
+    {
+        use amethyst::audio::Source;
+
+        validator = with_registered::<Source>(validator, &["*.wav"], r#"("WAV", ())"#)?;
+        validator = with_registered::<Source>(validator, &["*.ogg"], r#"("OGG", ())"#)?;
+        validator = with_registered::<Source>(validator, &["*.flac"], r#"("FLAC", ())"#)?;
+        validator = with_registered::<Source>(validator, &["*.mp3"], r#"("MP3", ())"#)?;
+    }
+    #[cfg(feature = "locale")]
+    {
+        use amethyst::locale::Locale;
+
+        validator = with_registered::<Locale>(validator, &["*.ftl"], r#"("FTL", ())"#)?;
+    }
+    Ok(validator)
+}
+
+fn main() {
+    start_logger(Default::default());
+
+    let args = match Args::parse(env::args().skip(1)) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match validator(&args).and_then(|validator| validator.validate_dir(&args.dir)) {
+        Ok(report) => {
+            for (path, error) in &report.problems {
+                let causes = error.causes().map(|e| e.to_string()).collect::<Vec<_>>();
+                eprintln!("{:?}: {}", path, causes.join(": "));
+            }
+            println!("{}", report);
+            if !report.is_valid() {
+                process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
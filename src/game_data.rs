@@ -22,15 +22,34 @@ pub trait DataInit<T> {
 pub trait DataDispose {
     /// Perform disposal
     fn dispose(&mut self, world: &mut World);
+
+    /// Run one fixed time step, doing nothing by default.
+    ///
+    /// `CoreApplication` calls this once per fixed step, after `State::fixed_update`,
+    /// whichever state is active.
+    fn fixed_update(&mut self, _world: &World) {}
 }
 
 /// Default game data.
 ///
+/// Besides its own systems, `GameData` runs the systems attached to the states on the state
+/// stack, see `SimpleState::systems`. Systems added with `GameDataBuilder::with_fixed` and
+/// friends run on the fixed time step instead of once per frame.
+///
 /// The lifetimes are for the systems inside and can be `'static` unless a system has a borrowed
 /// field.
 #[allow(missing_debug_implementations)]
 pub struct GameData<'a, 'b> {
     dispatcher: Option<Dispatcher<'a, 'b>>,
+    fixed_dispatcher: Option<Dispatcher<'a, 'b>>,
+    state_systems: Vec<Option<StateDispatcher<'a, 'b>>>,
+}
+
+/// Dispatcher of the systems attached to a state.
+struct StateDispatcher<'a, 'b> {
+    data: GameData<'a, 'b>,
+    run_while_paused: bool,
+    paused: bool,
 }
 
 impl<'a, 'b> GameData<'a, 'b> {
@@ -38,22 +57,151 @@ impl<'a, 'b> GameData<'a, 'b> {
     pub fn new(dispatcher: Dispatcher<'a, 'b>) -> Self {
         GameData {
             dispatcher: Some(dispatcher),
+            fixed_dispatcher: None,
+            state_systems: Vec::new(),
+        }
+    }
+
+    /// Create new game data with a dispatcher for the fixed time step
+    pub fn with_fixed_dispatcher(
+        dispatcher: Dispatcher<'a, 'b>,
+        fixed_dispatcher: Dispatcher<'a, 'b>,
+    ) -> Self {
+        GameData {
+            fixed_dispatcher: Some(fixed_dispatcher),
+            ..GameData::new(dispatcher)
         }
     }
 
     /// Update game data
+    ///
+    /// Runs the systems attached to states first, from the bottom of the state stack to the
+    /// top, followed by the systems of the game data itself.
     pub fn update(&mut self, world: &World) {
+        for state in self.state_systems.iter_mut().flatten() {
+            if state.run_while_paused || !state.paused {
+                state.data.update(world);
+            }
+        }
         if let Some(dispatcher) = &mut self.dispatcher {
             dispatcher.dispatch(&world);
         }
     }
 
+    /// Run one fixed time step of the game data
+    ///
+    /// Like `update`, runs the fixed systems attached to states first, followed by the fixed
+    /// systems of the game data itself. This is called by `CoreApplication` after every
+    /// `State::fixed_update`, i.e. as often per frame as `Time::step_fixed_update` allows.
+    pub fn fixed_update(&mut self, world: &World) {
+        for state in self.state_systems.iter_mut().flatten() {
+            if state.run_while_paused || !state.paused {
+                state.data.fixed_update(world);
+            }
+        }
+        if let Some(dispatcher) = &mut self.fixed_dispatcher {
+            dispatcher.dispatch(world);
+        }
+    }
+
     /// Dispose game data, dropping the dispatcher
+    ///
+    /// Disposes the systems of all states still on the stack, too.
     pub fn dispose(&mut self, mut world: &mut World) {
+        while !self.state_systems.is_empty() {
+            self.pop_state_systems(world);
+        }
+        if let Some(dispatcher) = self.fixed_dispatcher.take() {
+            dispatcher.dispose(world);
+        }
         if let Some(dispatcher) = self.dispatcher.take() {
             dispatcher.dispose(&mut world);
         }
     }
+
+    /// Sets up the systems of a state which has just been pushed onto the state stack.
+    ///
+    /// Has to be called for every state, even ones without systems, as the systems are
+    /// kept in a stack mirroring the state stack.
+    pub(crate) fn push_state_systems(
+        &mut self,
+        world: &mut World,
+        systems: Option<StateSystems<'a, 'b>>,
+    ) {
+        let state = systems.map(|systems| StateDispatcher {
+            data: systems.builder.build(world),
+            run_while_paused: systems.run_while_paused,
+            paused: false,
+        });
+        self.state_systems.push(state);
+    }
+
+    /// Disposes the systems of the state which has just been removed from the stack.
+    pub(crate) fn pop_state_systems(&mut self, world: &mut World) {
+        if let Some(Some(mut state)) = self.state_systems.pop() {
+            DataDispose::dispose(&mut state.data, world);
+        }
+    }
+
+    /// Pauses or resumes the systems of the topmost state.
+    pub(crate) fn set_state_systems_paused(&mut self, paused: bool) {
+        if let Some(Some(state)) = self.state_systems.last_mut() {
+            state.paused = paused;
+        }
+    }
+}
+
+/// Systems attached to a state, returned by `SimpleState::systems`.
+///
+/// The systems are set up when the state starts, run while the state is active and are
+/// disposed when the state stops. With `with_run_while_paused`, they run while the state is
+/// paused as well, i.e. as long as it is anywhere on the state stack.
+///
+/// # Examples
+///
+/// ~~~no_run
+/// use amethyst::core::SystemDesc;
+/// use amethyst::derive::SystemDesc;
+/// use amethyst::prelude::*;
+/// use amethyst::ecs::prelude::{System, SystemData, World};
+///
+/// #[derive(SystemDesc)]
+/// struct MenuSystem;
+/// impl<'a> System<'a> for MenuSystem {
+///     type SystemData = ();
+///     fn run(&mut self, _: Self::SystemData) {}
+/// }
+///
+/// struct Menu;
+///
+/// impl SimpleState for Menu {
+///     fn systems(&mut self) -> Option<StateSystems<'static, 'static>> {
+///         Some(StateSystems::new(
+///             GameDataBuilder::default().with(MenuSystem, "menu", &[]),
+///         ))
+///     }
+/// }
+/// ~~~
+#[allow(missing_debug_implementations)]
+pub struct StateSystems<'a, 'b> {
+    builder: GameDataBuilder<'a, 'b>,
+    run_while_paused: bool,
+}
+
+impl<'a, 'b> StateSystems<'a, 'b> {
+    /// Attaches the systems and bundles of `builder` to a state.
+    pub fn new(builder: GameDataBuilder<'a, 'b>) -> Self {
+        StateSystems {
+            builder,
+            run_while_paused: false,
+        }
+    }
+
+    /// Sets whether the systems keep running while the state is paused, `false` by default.
+    pub fn with_run_while_paused(mut self, run_while_paused: bool) -> Self {
+        self.run_while_paused = run_while_paused;
+        self
+    }
 }
 
 impl DataDispose for () {
@@ -64,12 +212,17 @@ impl DataDispose for GameData<'_, '_> {
     fn dispose(&mut self, world: &mut World) {
         self.dispose(world);
     }
+
+    fn fixed_update(&mut self, world: &World) {
+        self.fixed_update(world);
+    }
 }
 
 /// Builder for default game data
 #[allow(missing_debug_implementations)]
 pub struct GameDataBuilder<'a, 'b> {
     dispatcher_operations: Vec<Box<dyn DispatcherOperation<'a, 'b>>>,
+    fixed_dispatcher_operations: Vec<Box<dyn DispatcherOperation<'a, 'b>>>,
     disp_builder: DispatcherBuilder<'a, 'b>,
 }
 
@@ -84,6 +237,7 @@ impl<'a, 'b> GameDataBuilder<'a, 'b> {
     pub fn new() -> Self {
         GameDataBuilder {
             dispatcher_operations: Vec::new(),
+            fixed_dispatcher_operations: Vec::new(),
             disp_builder: DispatcherBuilder::new(),
         }
     }
@@ -421,6 +575,124 @@ impl<'a, 'b> GameDataBuilder<'a, 'b> {
         Ok(self)
     }
 
+    /// Inserts a barrier into the fixed time step dispatcher.
+    ///
+    /// See [`with_barrier`](#method.with_barrier) and [`with_fixed`](#method.with_fixed).
+    pub fn with_fixed_barrier(mut self) -> Self {
+        self.fixed_dispatcher_operations.push(Box::new(AddBarrier));
+        self
+    }
+
+    /// Adds a given system which runs on the fixed time step.
+    ///
+    /// Fixed systems are run by a separate dispatcher once for every `State::fixed_update`,
+    /// i.e. zero or more times per frame depending on the frame time, which makes them suited for
+    /// physics and networking. They run before the systems added with [`with`](#method.with),
+    /// can use `Time::fixed_seconds` as their delta time and may depend on other fixed systems
+    /// only.
+    ///
+    /// See [`with`](#method.with) for the parameters and panics.
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// use amethyst::core::{SystemDesc, Time};
+    /// use amethyst::derive::SystemDesc;
+    /// use amethyst::prelude::*;
+    /// use amethyst::ecs::prelude::{Read, System, SystemData, World};
+    ///
+    /// #[derive(SystemDesc)]
+    /// struct PhysicsSystem;
+    /// impl<'a> System<'a> for PhysicsSystem {
+    ///     type SystemData = Read<'a, Time>;
+    ///     fn run(&mut self, time: Self::SystemData) {
+    ///         let _dt = time.fixed_seconds();
+    ///     }
+    /// }
+    ///
+    /// GameDataBuilder::default()
+    ///     // Runs 60 times per second, however fast the game renders.
+    ///     .with_fixed(PhysicsSystem, "physics", &[]);
+    /// ~~~
+    pub fn with_fixed<S, N>(mut self, system: S, name: N, dependencies: &[N]) -> Self
+    where
+        S: for<'c> System<'c> + 'static + Send,
+        N: Into<String> + Clone,
+    {
+        let name = Into::<String>::into(name);
+        let dependencies = dependencies
+            .iter()
+            .map(Clone::clone)
+            .map(Into::<String>::into)
+            .collect::<Vec<String>>();
+        let dispatcher_operation = Box::new(AddSystem {
+            system,
+            name,
+            dependencies,
+        }) as Box<dyn DispatcherOperation<'a, 'b> + 'static>;
+        self.fixed_dispatcher_operations.push(dispatcher_operation);
+        self
+    }
+
+    /// Adds a system descriptor whose system runs on the fixed time step.
+    ///
+    /// See [`with_system_desc`](#method.with_system_desc) and [`with_fixed`](#method.with_fixed).
+    pub fn with_fixed_system_desc<SD, S, N>(
+        mut self,
+        system_desc: SD,
+        name: N,
+        dependencies: &[N],
+    ) -> Self
+    where
+        SD: SystemDesc<'a, 'b, S> + 'static,
+        S: for<'c> System<'c> + 'static + Send,
+        N: Into<String> + Clone,
+    {
+        let name = Into::<String>::into(name);
+        let dependencies = dependencies
+            .iter()
+            .map(Clone::clone)
+            .map(Into::<String>::into)
+            .collect::<Vec<String>>();
+        let dispatcher_operation = Box::new(AddSystemDesc {
+            system_desc,
+            name,
+            dependencies,
+            marker: PhantomData::<S>,
+        }) as Box<dyn DispatcherOperation<'a, 'b> + 'static>;
+        self.fixed_dispatcher_operations.push(dispatcher_operation);
+        self
+    }
+
+    /// Add a given thread-local system which runs on the fixed time step.
+    ///
+    /// See [`with_thread_local`](#method.with_thread_local) and
+    /// [`with_fixed`](#method.with_fixed).
+    pub fn with_fixed_thread_local<S>(mut self, system: S) -> Self
+    where
+        S: for<'c> RunNow<'c> + 'static,
+    {
+        self.fixed_dispatcher_operations
+            .push(Box::new(AddThreadLocal { system }));
+        self
+    }
+
+    /// Add a given ECS bundle whose systems run on the fixed time step.
+    ///
+    /// See [`with_bundle`](#method.with_bundle) and [`with_fixed`](#method.with_fixed).
+    ///
+    /// # Errors
+    ///
+    /// See each individual bundle for a description of the errors it could produce.
+    pub fn with_fixed_bundle<B>(mut self, bundle: B) -> Result<Self, Error>
+    where
+        B: SystemBundle<'a, 'b> + 'static,
+    {
+        self.fixed_dispatcher_operations
+            .push(Box::new(AddBundle { bundle }));
+        Ok(self)
+    }
+
     // /// Create a basic renderer with a single given `Pass`, and optional support for the `DrawUi` pass.
     // ///
     // /// Will set the clear color to black.
@@ -475,11 +747,29 @@ impl<'a, 'b> DataInit<GameData<'a, 'b>> for GameDataBuilder<'a, 'b> {
             .unwrap_or_else(|e| panic!("Failed to set up dispatcher: {}", e));
 
         #[cfg(not(no_threading))]
-        let mut dispatcher = dispatcher_builder.with_pool(pool).build();
+        let mut dispatcher = dispatcher_builder.with_pool(pool.clone()).build();
         #[cfg(no_threading)]
         let mut dispatcher = dispatcher_builder.build();
         dispatcher.setup(&mut world);
-        GameData::new(dispatcher)
+
+        if self.fixed_dispatcher_operations.is_empty() {
+            return GameData::new(dispatcher);
+        }
+
+        let mut fixed_dispatcher_builder = DispatcherBuilder::new();
+        self.fixed_dispatcher_operations
+            .into_iter()
+            .try_for_each(|dispatcher_operation| {
+                dispatcher_operation.exec(world, &mut fixed_dispatcher_builder)
+            })
+            .unwrap_or_else(|e| panic!("Failed to set up fixed dispatcher: {}", e));
+
+        #[cfg(not(no_threading))]
+        let mut fixed_dispatcher = fixed_dispatcher_builder.with_pool(pool).build();
+        #[cfg(no_threading)]
+        let mut fixed_dispatcher = fixed_dispatcher_builder.build();
+        fixed_dispatcher.setup(world);
+        GameData::with_fixed_dispatcher(dispatcher, fixed_dispatcher)
     }
 }
 
@@ -96,11 +96,11 @@ pub use self::{
     app::{Application, ApplicationBuilder, CoreApplication},
     callback_queue::{Callback, CallbackQueue},
     error::Error,
-    game_data::{DataDispose, DataInit, GameData, GameDataBuilder},
+    game_data::{DataDispose, DataInit, GameData, GameDataBuilder, StateSystems},
     logger::{start_logger, LevelFilter as LogLevelFilter, Logger, LoggerConfig, StdoutLog},
     state::{
-        EmptyState, EmptyTrans, SimpleState, SimpleTrans, State, StateData, StateMachine, Trans,
-        TransEvent,
+        EmptyState, EmptyTrans, SimpleState, SimpleTrans, State, StateData, StateMachine,
+        StateRequest, StateStack, StateTransitionEvent, StateTransitionKind, Trans, TransEvent,
     },
     state_event::{StateEvent, StateEventReader},
 };
@@ -109,6 +109,7 @@ pub use self::{
 pub type Result<T> = std::result::Result<T, error::Error>;
 
 pub mod prelude;
+pub mod replay;
 
 mod app;
 mod callback_queue;
@@ -6,7 +6,7 @@ pub use crate::{
     config::Config,
     core::{SystemDesc, SystemExt, WithNamed},
     ecs::prelude::{Builder, World, WorldExt},
-    game_data::{DataInit, GameData, GameDataBuilder},
+    game_data::{DataInit, GameData, GameDataBuilder, StateSystems},
     state::{
         EmptyState, EmptyTrans, SimpleState, SimpleTrans, State, StateData, Trans, TransEvent,
     },
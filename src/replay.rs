@@ -0,0 +1,522 @@
+//! Recording and replaying the events driving the game loop.
+//!
+//! An `EventRecorder` resource makes `CoreApplication` write the window and device events of
+//! every frame, together with the frame time, to a file. An `EventReplay` resource feeds such a
+//! recording back: each frame the recorded events are written to the `EventChannel<Event>` and
+//! `Time` is advanced by the recorded frame time. As input, UI and state events are all derived
+//! from the window events, replaying them reproduces the `StateEvent`s and `InputHandler` state
+//! of the recorded run.
+//!
+//! Recordings contain one RON encoded `RecordedFrame` per line, so a recording cut short by a
+//! crash can still be replayed.
+//!
+//! # Examples
+//!
+//! ~~~no_run
+//! use std::time::Duration;
+//!
+//! use amethyst::{prelude::*, replay::{EventRecorder, EventReplay}};
+//!
+//! struct GameState;
+//! impl SimpleState for GameState {}
+//!
+//! # fn main() -> amethyst::Result<()> {
+//! // Record a session...
+//! let mut game = Application::build("assets/", GameState)?
+//!     .with_resource(EventRecorder::create("session.ron")?)
+//!     .build(GameDataBuilder::default())?;
+//! game.run();
+//!
+//! // ...and replay it headless, e.g. in a test.
+//! let mut game = Application::build("assets/", GameState)?
+//!     .with_resource(EventReplay::open("session.ron")?)
+//!     .with_virtual_clock(Duration::from_millis(16))
+//!     .build(GameDataBuilder::default())?;
+//! game.run();
+//! # Ok(())
+//! # }
+//! ~~~
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::{LogicalPosition, LogicalSize},
+    DeviceEvent, DeviceId, ElementState, Event, KeyboardInput, ModifiersState, MouseButton,
+    MouseScrollDelta, TouchPhase, WindowEvent, WindowId,
+};
+
+use crate::{
+    core::shrev::{EventChannel, ReaderId},
+    error::{format_err, Error, ResultExt},
+};
+
+/// The events of a single frame and the time the frame took.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Unscaled time between this frame and the next one.
+    pub delta: Duration,
+    /// Events written to the `EventChannel<Event>` during the frame.
+    #[serde(default)]
+    pub events: Vec<RecordedEvent>,
+}
+
+/// Serializable form of the `winit` events which can be recorded.
+///
+/// Window and device ids are not recorded, replayed events use dummy ids. Touch, touchpad
+/// pressure and raw axis events are not recorded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[allow(missing_docs)]
+pub enum RecordedEvent {
+    Resized(LogicalSize),
+    Moved(LogicalPosition),
+    CloseRequested,
+    Destroyed,
+    DroppedFile(PathBuf),
+    HoveredFile(PathBuf),
+    HoveredFileCancelled,
+    ReceivedCharacter(char),
+    Focused(bool),
+    KeyboardInput(KeyboardInput),
+    CursorMoved {
+        position: LogicalPosition,
+        modifiers: ModifiersState,
+    },
+    CursorEntered,
+    CursorLeft,
+    MouseWheel {
+        delta: MouseScrollDelta,
+        phase: TouchPhase,
+        modifiers: ModifiersState,
+    },
+    MouseInput {
+        state: ElementState,
+        button: MouseButton,
+        modifiers: ModifiersState,
+    },
+    Refresh,
+    HiDpiFactorChanged(f64),
+    /// `DeviceEvent::MouseMotion`
+    MouseMotion {
+        delta: (f64, f64),
+    },
+    /// `DeviceEvent::MouseWheel`
+    DeviceMouseWheel {
+        delta: MouseScrollDelta,
+    },
+    /// `DeviceEvent::Key`
+    DeviceKey(KeyboardInput),
+    /// `DeviceEvent::Text`
+    DeviceText {
+        codepoint: char,
+    },
+    Awakened,
+    Suspended(bool),
+}
+
+impl RecordedEvent {
+    /// Converts a `winit` event, returns `None` for events which can't be recorded.
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let recorded = match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::Resized(size) => RecordedEvent::Resized(*size),
+                WindowEvent::Moved(position) => RecordedEvent::Moved(*position),
+                WindowEvent::CloseRequested => RecordedEvent::CloseRequested,
+                WindowEvent::Destroyed => RecordedEvent::Destroyed,
+                WindowEvent::DroppedFile(path) => RecordedEvent::DroppedFile(path.clone()),
+                WindowEvent::HoveredFile(path) => RecordedEvent::HoveredFile(path.clone()),
+                WindowEvent::HoveredFileCancelled => RecordedEvent::HoveredFileCancelled,
+                WindowEvent::ReceivedCharacter(c) => RecordedEvent::ReceivedCharacter(*c),
+                WindowEvent::Focused(focused) => RecordedEvent::Focused(*focused),
+                WindowEvent::KeyboardInput { input, .. } => RecordedEvent::KeyboardInput(*input),
+                WindowEvent::CursorMoved {
+                    position,
+                    modifiers,
+                    ..
+                } => RecordedEvent::CursorMoved {
+                    position: *position,
+                    modifiers: *modifiers,
+                },
+                WindowEvent::CursorEntered { .. } => RecordedEvent::CursorEntered,
+                WindowEvent::CursorLeft { .. } => RecordedEvent::CursorLeft,
+                WindowEvent::MouseWheel {
+                    delta,
+                    phase,
+                    modifiers,
+                    ..
+                } => RecordedEvent::MouseWheel {
+                    delta: *delta,
+                    phase: *phase,
+                    modifiers: *modifiers,
+                },
+                WindowEvent::MouseInput {
+                    state,
+                    button,
+                    modifiers,
+                    ..
+                } => RecordedEvent::MouseInput {
+                    state: *state,
+                    button: *button,
+                    modifiers: *modifiers,
+                },
+                WindowEvent::Refresh => RecordedEvent::Refresh,
+                WindowEvent::HiDpiFactorChanged(factor) => {
+                    RecordedEvent::HiDpiFactorChanged(*factor)
+                }
+                WindowEvent::TouchpadPressure { .. }
+                | WindowEvent::AxisMotion { .. }
+                | WindowEvent::Touch(_) => return None,
+            },
+            Event::DeviceEvent { event, .. } => match event {
+                DeviceEvent::MouseMotion { delta } => RecordedEvent::MouseMotion { delta: *delta },
+                DeviceEvent::MouseWheel { delta } => {
+                    RecordedEvent::DeviceMouseWheel { delta: *delta }
+                }
+                DeviceEvent::Key(input) => RecordedEvent::DeviceKey(*input),
+                DeviceEvent::Text { codepoint } => RecordedEvent::DeviceText {
+                    codepoint: *codepoint,
+                },
+                DeviceEvent::Added
+                | DeviceEvent::Removed
+                | DeviceEvent::Motion { .. }
+                | DeviceEvent::Button { .. } => return None,
+            },
+            Event::Awakened => RecordedEvent::Awakened,
+            Event::Suspended(suspended) => RecordedEvent::Suspended(*suspended),
+        };
+        Some(recorded)
+    }
+
+    /// Converts back to a `winit` event with dummy window and device ids.
+    pub fn into_event(self) -> Event {
+        // The dummy ids are never passed to winit, only compared by systems.
+        let window_id = unsafe { WindowId::dummy() };
+        let device_id = unsafe { DeviceId::dummy() };
+        let window_event = |event| Event::WindowEvent { window_id, event };
+        let device_event = |event| Event::DeviceEvent { device_id, event };
+        match self {
+            RecordedEvent::Resized(size) => window_event(WindowEvent::Resized(size)),
+            RecordedEvent::Moved(position) => window_event(WindowEvent::Moved(position)),
+            RecordedEvent::CloseRequested => window_event(WindowEvent::CloseRequested),
+            RecordedEvent::Destroyed => window_event(WindowEvent::Destroyed),
+            RecordedEvent::DroppedFile(path) => window_event(WindowEvent::DroppedFile(path)),
+            RecordedEvent::HoveredFile(path) => window_event(WindowEvent::HoveredFile(path)),
+            RecordedEvent::HoveredFileCancelled => window_event(WindowEvent::HoveredFileCancelled),
+            RecordedEvent::ReceivedCharacter(c) => window_event(WindowEvent::ReceivedCharacter(c)),
+            RecordedEvent::Focused(focused) => window_event(WindowEvent::Focused(focused)),
+            RecordedEvent::KeyboardInput(input) => {
+                window_event(WindowEvent::KeyboardInput { device_id, input })
+            }
+            RecordedEvent::CursorMoved {
+                position,
+                modifiers,
+            } => window_event(WindowEvent::CursorMoved {
+                device_id,
+                position,
+                modifiers,
+            }),
+            RecordedEvent::CursorEntered => window_event(WindowEvent::CursorEntered { device_id }),
+            RecordedEvent::CursorLeft => window_event(WindowEvent::CursorLeft { device_id }),
+            RecordedEvent::MouseWheel {
+                delta,
+                phase,
+                modifiers,
+            } => window_event(WindowEvent::MouseWheel {
+                device_id,
+                delta,
+                phase,
+                modifiers,
+            }),
+            RecordedEvent::MouseInput {
+                state,
+                button,
+                modifiers,
+            } => window_event(WindowEvent::MouseInput {
+                device_id,
+                state,
+                button,
+                modifiers,
+            }),
+            RecordedEvent::Refresh => window_event(WindowEvent::Refresh),
+            RecordedEvent::HiDpiFactorChanged(factor) => {
+                window_event(WindowEvent::HiDpiFactorChanged(factor))
+            }
+            RecordedEvent::MouseMotion { delta } => {
+                device_event(DeviceEvent::MouseMotion { delta })
+            }
+            RecordedEvent::DeviceMouseWheel { delta } => {
+                device_event(DeviceEvent::MouseWheel { delta })
+            }
+            RecordedEvent::DeviceKey(input) => device_event(DeviceEvent::Key(input)),
+            RecordedEvent::DeviceText { codepoint } => {
+                device_event(DeviceEvent::Text { codepoint })
+            }
+            RecordedEvent::Awakened => Event::Awakened,
+            RecordedEvent::Suspended(suspended) => Event::Suspended(suspended),
+        }
+    }
+}
+
+/// Resource which makes `CoreApplication` record the events and frame times of every frame.
+///
+/// The recorder has to be inserted before the application starts for the recording to be
+/// replayable from the start of the game.
+///
+/// Recording stops with an error being logged if the recording can't be written.
+#[allow(missing_debug_implementations)]
+pub struct EventRecorder {
+    writer: Option<Box<dyn Write + Send + Sync>>,
+    reader: Option<ReaderId<Event>>,
+    frames: u64,
+}
+
+impl EventRecorder {
+    /// Records to a new file at `path`, replacing an existing one.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|_| format_err!("Failed to create recording {:?}", path))?;
+        Ok(EventRecorder::new(BufWriter::new(file)))
+    }
+
+    /// Records to the given writer.
+    pub fn new<W: Write + Send + Sync + 'static>(writer: W) -> Self {
+        EventRecorder {
+            writer: Some(Box::new(writer)),
+            reader: None,
+            frames: 0,
+        }
+    }
+
+    /// Number of frames recorded so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Starts listening to the events, called when the application starts.
+    pub(crate) fn register(&mut self, events: &mut EventChannel<Event>) {
+        if self.reader.is_none() {
+            self.reader = Some(events.register_reader());
+        }
+    }
+
+    /// Records the events written since the last frame.
+    pub(crate) fn record_frame(&mut self, events: &mut EventChannel<Event>, delta: Duration) {
+        self.register(events);
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("Reader has just been registered");
+        let frame = RecordedFrame {
+            delta,
+            events: events
+                .read(reader)
+                .filter_map(RecordedEvent::from_event)
+                .collect(),
+        };
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+        let written = ron::ser::to_string(&frame)
+            .map_err(Error::new)
+            .and_then(|line| {
+                writeln!(writer, "{}", line)?;
+                // Flushed every frame so the recording survives a crash.
+                writer.flush()?;
+                Ok(())
+            });
+        match written {
+            Ok(()) => self.frames += 1,
+            Err(e) => {
+                error!("Failed to write recording, stopping to record: {}", e);
+                self.writer = None;
+            }
+        }
+    }
+}
+
+/// Resource which makes `CoreApplication` replay a recording made with `EventRecorder`.
+///
+/// Every frame, the recorded events are written to the `EventChannel<Event>` and `Time` is
+/// advanced by the recorded frame time instead of the wall or virtual clock. Events from a
+/// window, if any, are not suppressed.
+#[derive(Debug)]
+pub struct EventReplay {
+    frames: VecDeque<RecordedFrame>,
+    stop_at_end: bool,
+}
+
+impl EventReplay {
+    /// Loads the recording at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|_| format_err!("Failed to open recording {:?}", path))?;
+        EventReplay::from_reader(BufReader::new(file))
+            .with_context(|_| format_err!("Failed to load recording {:?}", path))
+    }
+
+    /// Loads a recording from the given reader.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let frames = reader
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|(n, line)| {
+                let line = line?;
+                ron::de::from_str(&line)
+                    .with_context(|_| format_err!("Invalid frame in line {}", n + 1))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(EventReplay::from_frames(frames))
+    }
+
+    /// Replays the given frames.
+    pub fn from_frames(frames: Vec<RecordedFrame>) -> Self {
+        EventReplay {
+            frames: frames.into(),
+            stop_at_end: true,
+        }
+    }
+
+    /// Sets whether the application stops once all frames have been replayed, which is the
+    /// default. Otherwise the game continues with the regular clock.
+    pub fn with_stop_at_end(mut self, stop_at_end: bool) -> Self {
+        self.stop_at_end = stop_at_end;
+        self
+    }
+
+    /// Number of frames left to replay.
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether the application should stop as all frames have been replayed.
+    pub(crate) fn finished(&self) -> bool {
+        self.stop_at_end && self.frames.is_empty()
+    }
+
+    /// Writes the events of the next frame and returns its frame time.
+    pub(crate) fn replay_frame(&mut self, events: &mut EventChannel<Event>) -> Option<Duration> {
+        let frame = self.frames.pop_front()?;
+        debug!("Replaying {} events", frame.events.len());
+        events.iter_write(frame.events.into_iter().map(RecordedEvent::into_event));
+        Some(frame.delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use winit::VirtualKeyCode;
+
+    use super::*;
+    use crate::{
+        core::Time,
+        ecs::WorldExt,
+        state::{EmptyState, EmptyTrans, StateData, Trans},
+        state_event::StateEvent,
+        Application,
+    };
+
+    /// Shared buffer the recorder writes to.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Logs the events and frame times it sees, optionally sending key presses itself.
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    struct Logging {
+        send_keys: bool,
+    }
+
+    fn key(virtual_keycode: VirtualKeyCode) -> Event {
+        RecordedEvent::KeyboardInput(KeyboardInput {
+            scancode: 0,
+            state: ElementState::Pressed,
+            virtual_keycode: Some(virtual_keycode),
+            modifiers: ModifiersState::default(),
+        })
+        .into_event()
+    }
+
+    impl EmptyState for Logging {
+        fn on_start(&mut self, data: StateData<'_, ()>) {
+            data.world.insert(Log::default());
+        }
+
+        fn handle_event(&mut self, data: StateData<'_, ()>, event: StateEvent) -> EmptyTrans {
+            if let StateEvent::Window(event) = event {
+                let entry = format!("{:?}", RecordedEvent::from_event(&event));
+                data.world.write_resource::<Log>().0.push(entry);
+            }
+            Trans::None
+        }
+
+        fn update(&mut self, data: StateData<'_, ()>) -> EmptyTrans {
+            let time = data.world.read_resource::<Time>();
+            let entry = format!("{} {:?}", time.frame_number(), time.delta_real_time());
+            data.world.write_resource::<Log>().0.push(entry);
+            if self.send_keys && time.frame_number() % 2 == 1 {
+                let keys = [VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C];
+                let event = key(keys[time.frame_number() as usize % keys.len()]);
+                data.world
+                    .write_resource::<EventChannel<Event>>()
+                    .single_write(event);
+            }
+            Trans::None
+        }
+    }
+
+    #[test]
+    fn replay_reproduces_recording() {
+        let buffer = Buffer::default();
+        let mut game = Application::build(".", Logging { send_keys: true })
+            .unwrap()
+            .with_resource(EventRecorder::new(buffer.clone()))
+            .with_virtual_clock(Duration::from_millis(10))
+            .build(())
+            .unwrap();
+        game.run_frames(5);
+        assert_eq!(game.world().read_resource::<EventRecorder>().frames(), 5);
+        let recorded = game.world_mut().remove::<Log>().unwrap().0;
+
+        let recording = buffer.0.lock().unwrap().clone();
+        let replay = EventReplay::from_reader(Cursor::new(recording)).unwrap();
+        assert_eq!(replay.remaining(), 5);
+        let mut game = Application::build(".", Logging { send_keys: false })
+            .unwrap()
+            .with_resource(replay)
+            .with_virtual_clock(Duration::from_millis(25))
+            .build(())
+            .unwrap();
+        game.run();
+        let replayed = game.world_mut().remove::<Log>().unwrap().0;
+
+        assert_eq!(recorded, replayed);
+        assert!(replayed.iter().any(|entry| entry.contains("Some(A)")));
+    }
+}
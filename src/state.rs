@@ -4,7 +4,7 @@ use amethyst_input::is_close_requested;
 
 use derivative::Derivative;
 
-use crate::{ecs::World, GameData, StateEvent};
+use crate::{core::shrev::EventChannel, ecs::World, GameData, StateEvent, StateSystems};
 
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
@@ -100,8 +100,107 @@ pub type EmptyTrans = Trans<(), StateEvent>;
 /// By default it contains a `GameData` as its `StateData` and doesn't have a custom event type.
 pub type SimpleTrans = Trans<GameData<'static, 'static>, StateEvent>;
 
+/// Transition request which, unlike `TransEvent`, can be sent without knowing the game data and
+/// event types, e.g. from systems or the UI.
+///
+/// States are referred to by name and created by the factories registered with
+/// `ApplicationBuilder::with_named_state`. Requests are executed by `CoreApplication` after the
+/// `TransEvent`s.
+/// # Example:
+/// ```rust, ignore
+/// world.write_resource::<EventChannel<StateRequest>>().single_write(StateRequest::Push("pause_menu".into()));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateRequest {
+    /// Same as `Trans::Pop`.
+    Pop,
+    /// Pops states until the topmost state with the given name is active.
+    PopTo(String),
+    /// Same as `Trans::Push` with a new instance of the named state.
+    Push(String),
+    /// Same as `Trans::Switch` with a new instance of the named state.
+    Switch(String),
+    /// Same as `Trans::Replace` with a new instance of the named state.
+    Replace(String),
+    /// Same as `Trans::Quit`.
+    Quit,
+}
+
+/// Kind of a `StateTransitionEvent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateTransitionKind {
+    /// The state machine has started with its initial state.
+    Start,
+    /// A state has been pushed.
+    Push,
+    /// The active state has been popped.
+    Pop,
+    /// The active state has been switched for another one.
+    Switch,
+    /// All states have been replaced by a single state.
+    Replace,
+    /// All states have been replaced by a new stack.
+    NewStack,
+    /// All states have been stopped.
+    Stop,
+}
+
+/// Event written to the `EventChannel<StateTransitionEvent>` after every state transition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateTransitionEvent {
+    /// The kind of transition.
+    pub kind: StateTransitionKind,
+    /// Name of the state active before the transition, if any.
+    pub from: Option<String>,
+    /// Name of the state active after the transition, if any.
+    pub to: Option<String>,
+}
+
+/// Resource listing the names of the states on the stack of the `StateMachine`, from the bottom
+/// to the active state at the top.
+///
+/// It is updated by the state machine after every transition.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateStack {
+    names: Vec<String>,
+}
+
+impl StateStack {
+    /// Name of the active state, if any.
+    pub fn active(&self) -> Option<&str> {
+        self.names.last().map(String::as_str)
+    }
+
+    /// Names of all states on the stack, from the bottom to the top.
+    pub fn names(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// Checks whether a state with the given name is on the stack.
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    /// Number of states on the stack.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Checks whether the stack is empty, i.e. the state machine is not running.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
 /// A trait which defines game states that can be used by the state machine.
 pub trait State<T, E: Send + Sync + 'static> {
+    /// Name of the state, as listed by the `StateStack` and used in `StateTransitionEvent`s.
+    ///
+    /// Defaults to the type name.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
     /// Executed when the game state begins.
     fn on_start(&mut self, _data: StateData<'_, T>) {}
 
@@ -146,6 +245,11 @@ pub trait State<T, E: Send + Sync + 'static> {
 
 /// An empty `State` trait. It contains no `StateData` or custom `StateEvent`.
 pub trait EmptyState {
+    /// Name of the state, see `State::name`.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
     /// Executed when the game state begins.
     fn on_start(&mut self, _data: StateData<'_, ()>) {}
 
@@ -195,6 +299,11 @@ pub trait EmptyState {
 }
 
 impl<T: EmptyState> State<(), StateEvent> for T {
+    /// Name of the state.
+    fn name(&self) -> &str {
+        EmptyState::name(self)
+    }
+
     /// Executed when the game state begins.
     fn on_start(&mut self, data: StateData<'_, ()>) {
         self.on_start(data)
@@ -249,6 +358,20 @@ impl<T: EmptyState> State<(), StateEvent> for T {
 
 /// A simple `State` trait. It contains `GameData` as its `StateData` and no custom `StateEvent`.
 pub trait SimpleState {
+    /// Systems which only run while this state is active.
+    ///
+    /// Called once when the state begins. The systems are set up before `on_start` and
+    /// disposed after `on_stop`; see `StateSystems` to keep them running while the state is
+    /// paused.
+    fn systems(&mut self) -> Option<StateSystems<'static, 'static>> {
+        None
+    }
+
+    /// Name of the state, see `State::name`.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
     /// Executed when the game state begins.
     fn on_start(&mut self, _data: StateData<'_, GameData<'_, '_>>) {}
 
@@ -304,23 +427,35 @@ pub trait SimpleState {
 impl<T: SimpleState> State<GameData<'static, 'static>, StateEvent> for T {
     //pub trait SimpleState<'a,'b>: State<GameData<'a,'b>,()> {
 
+    /// Name of the state.
+    fn name(&self) -> &str {
+        SimpleState::name(self)
+    }
+
     /// Executed when the game state begins.
-    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+    fn on_start(&mut self, data: StateData<'_, GameData<'static, 'static>>) {
+        let systems = self.systems();
+        data.data.push_state_systems(data.world, systems);
         self.on_start(data)
     }
 
     /// Executed when the game state exits.
-    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
-        self.on_stop(data)
+    fn on_stop(&mut self, data: StateData<'_, GameData<'static, 'static>>) {
+        let StateData { world, data } = data;
+        self.on_stop(StateData::new(world, data));
+        data.pop_state_systems(world);
     }
 
     /// Executed when a different game state is pushed onto the stack.
-    fn on_pause(&mut self, data: StateData<'_, GameData<'_, '_>>) {
-        self.on_pause(data)
+    fn on_pause(&mut self, data: StateData<'_, GameData<'static, 'static>>) {
+        let StateData { world, data } = data;
+        self.on_pause(StateData::new(world, data));
+        data.set_state_systems_paused(true);
     }
 
     /// Executed when the application returns to this game state once again.
-    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+    fn on_resume(&mut self, data: StateData<'_, GameData<'static, 'static>>) {
+        data.data.set_state_systems_paused(false);
         self.on_resume(data)
     }
 
@@ -362,6 +497,61 @@ impl<T: SimpleState> State<GameData<'static, 'static>, StateEvent> for T {
     }
 }
 
+/// State created from a factory registered with `ApplicationBuilder::with_named_state`, named
+/// after its registration.
+pub(crate) struct NamedState<T, E> {
+    name: String,
+    state: Box<dyn State<T, E>>,
+}
+
+impl<T, E> NamedState<T, E> {
+    pub(crate) fn new(name: String, state: Box<dyn State<T, E>>) -> Self {
+        NamedState { name, state }
+    }
+}
+
+impl<T, E: Send + Sync + 'static> State<T, E> for NamedState<T, E> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_start(&mut self, data: StateData<'_, T>) {
+        self.state.on_start(data)
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, T>) {
+        self.state.on_stop(data)
+    }
+
+    fn on_pause(&mut self, data: StateData<'_, T>) {
+        self.state.on_pause(data)
+    }
+
+    fn on_resume(&mut self, data: StateData<'_, T>) {
+        self.state.on_resume(data)
+    }
+
+    fn handle_event(&mut self, data: StateData<'_, T>, event: E) -> Trans<T, E> {
+        self.state.handle_event(data, event)
+    }
+
+    fn fixed_update(&mut self, data: StateData<'_, T>) -> Trans<T, E> {
+        self.state.fixed_update(data)
+    }
+
+    fn update(&mut self, data: StateData<'_, T>) -> Trans<T, E> {
+        self.state.update(data)
+    }
+
+    fn shadow_fixed_update(&mut self, data: StateData<'_, T>) {
+        self.state.shadow_fixed_update(data)
+    }
+
+    fn shadow_update(&mut self, data: StateData<'_, T>) {
+        self.state.shadow_update(data)
+    }
+}
+
 /// A simple stack-based state machine (pushdown automaton).
 #[derive(Derivative)]
 #[derivative(Debug)]
@@ -385,15 +575,42 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
         self.running
     }
 
+    /// Name of the active state.
+    fn active_name(&self) -> Option<String> {
+        self.state_stack
+            .last()
+            .map(|state| state.name().to_string())
+    }
+
+    /// Updates the `StateStack` and announces the transition.
+    fn transitioned(&self, world: &mut World, kind: StateTransitionKind, from: Option<String>) {
+        let names = self
+            .state_stack
+            .iter()
+            .map(|state| state.name().to_string())
+            .collect::<Vec<_>>();
+        let to = names.last().cloned();
+        world
+            .entry::<StateStack>()
+            .or_insert_with(Default::default)
+            .names = names;
+        world
+            .entry::<EventChannel<StateTransitionEvent>>()
+            .or_insert_with(Default::default)
+            .single_write(StateTransitionEvent { kind, from, to });
+    }
+
     /// Initializes the state machine.
     pub fn start(&mut self, data: StateData<'_, T>) -> Result<(), StateError> {
         if !self.running {
+            let StateData { world, data } = data;
             let state = self
                 .state_stack
                 .last_mut()
                 .ok_or(StateError::NoStatesPresent)?;
-            state.on_start(data);
+            state.on_start(StateData { world, data });
             self.running = true;
+            self.transitioned(world, StateTransitionKind::Start, None);
         }
         Ok(())
     }
@@ -494,6 +711,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
     fn switch(&mut self, state: Box<dyn State<T, E>>, data: StateData<'_, T>) {
         if self.running {
             let StateData { world, data } = data;
+            let from = self.active_name();
             if let Some(mut state) = self.state_stack.pop() {
                 state.on_stop(StateData { world, data });
             }
@@ -503,6 +721,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
             //State was just pushed, thus pop will always succeed
             let new_state = self.state_stack.last_mut().unwrap();
             new_state.on_start(StateData { world, data });
+            self.transitioned(world, StateTransitionKind::Switch, from);
         }
     }
 
@@ -510,6 +729,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
     fn push(&mut self, state: Box<dyn State<T, E>>, data: StateData<'_, T>) {
         if self.running {
             let StateData { world, data } = data;
+            let from = self.active_name();
             if let Some(state) = self.state_stack.last_mut() {
                 state.on_pause(StateData { world, data });
             }
@@ -519,6 +739,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
             //State was just pushed, thus pop will always succeed
             let new_state = self.state_stack.last_mut().unwrap();
             new_state.on_start(StateData { world, data });
+            self.transitioned(world, StateTransitionKind::Push, from);
         }
     }
 
@@ -527,6 +748,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
     fn pop(&mut self, data: StateData<'_, T>) {
         if self.running {
             let StateData { world, data } = data;
+            let from = self.active_name();
             if let Some(mut state) = self.state_stack.pop() {
                 state.on_stop(StateData { world, data });
             }
@@ -536,6 +758,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
             } else {
                 self.running = false;
             }
+            self.transitioned(world, StateTransitionKind::Pop, from);
         }
     }
 
@@ -544,6 +767,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
         if self.running {
             //Pemove all current states
             let StateData { world, data } = data;
+            let from = self.active_name();
             while let Some(mut state) = self.state_stack.pop() {
                 state.on_stop(StateData { world, data });
             }
@@ -554,6 +778,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
             //State was just pushed, thus pop will always succeed
             let new_state = self.state_stack.last_mut().unwrap();
             new_state.on_start(StateData { world, data });
+            self.transitioned(world, StateTransitionKind::Replace, from);
         }
     }
 
@@ -562,6 +787,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
         if self.running {
             //remove all current states
             let StateData { world, data } = data;
+            let from = self.active_name();
             while let Some(mut state) = self.state_stack.pop() {
                 state.on_stop(StateData { world, data });
             }
@@ -579,6 +805,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
                     new_state.on_pause(StateData { world, data });
                 }
             }
+            self.transitioned(world, StateTransitionKind::NewStack, from);
         }
     }
 
@@ -586,11 +813,13 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
     pub(crate) fn stop(&mut self, data: StateData<'_, T>) {
         if self.running {
             let StateData { world, data } = data;
+            let from = self.active_name();
             while let Some(mut state) = self.state_stack.pop() {
                 state.on_stop(StateData { world, data });
             }
 
             self.running = false;
+            self.transitioned(world, StateTransitionKind::Stop, from);
         }
     }
 }
@@ -598,6 +827,7 @@ impl<'a, T, E: Send + Sync + 'static> StateMachine<'a, T, E> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::GameDataBuilder;
 
     struct State0;
     struct State1(u8);
@@ -624,6 +854,10 @@ mod tests {
     }
 
     impl State<(), ()> for State2 {
+        fn name(&self) -> &str {
+            "state2"
+        }
+
         fn update(&mut self, _: StateData<'_, ()>) -> Trans<(), ()> {
             Trans::Pop
         }
@@ -724,4 +958,168 @@ mod tests {
         sm.update(StateData::new(&mut world, &mut ()));
         assert_eq!(sm.state_stack.len(), 1);
     }
+
+    #[test]
+    fn stack_and_transition_events() {
+        use crate::ecs::prelude::{World, WorldExt};
+
+        let mut world = World::new();
+        let mut reader = world
+            .entry::<EventChannel<StateTransitionEvent>>()
+            .or_insert_with(Default::default)
+            .register_reader();
+
+        let mut sm = StateMachine::new(State1(0));
+        sm.start(StateData::new(&mut world, &mut ())).unwrap();
+        let state1 = std::any::type_name::<State1>();
+        assert_eq!(world.read_resource::<StateStack>().active(), Some(state1));
+
+        sm.update(StateData::new(&mut world, &mut ()));
+        {
+            let stack = world.read_resource::<StateStack>();
+            assert_eq!(stack.names().collect::<Vec<_>>(), vec!["state2"]);
+            assert!(!stack.contains(state1));
+        }
+
+        sm.update(StateData::new(&mut world, &mut ()));
+        assert!(world.read_resource::<StateStack>().is_empty());
+
+        let events = world
+            .read_resource::<EventChannel<StateTransitionEvent>>()
+            .read(&mut reader)
+            .cloned()
+            .collect::<Vec<_>>();
+        let event = |kind, from: Option<&str>, to: Option<&str>| StateTransitionEvent {
+            kind,
+            from: from.map(String::from),
+            to: to.map(String::from),
+        };
+        assert_eq!(
+            events,
+            vec![
+                event(StateTransitionKind::Start, None, Some(state1)),
+                event(StateTransitionKind::Switch, Some(state1), Some("state2")),
+                event(StateTransitionKind::Pop, Some("state2"), None),
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct Ran(Vec<&'static str>);
+
+    struct RanSystem(&'static str);
+
+    impl<'a> crate::ecs::System<'a> for RanSystem {
+        type SystemData = crate::ecs::Write<'a, Ran>;
+
+        fn run(&mut self, mut ran: Self::SystemData) {
+            ran.0.push(self.0);
+        }
+    }
+
+    struct Outer {
+        while_paused: bool,
+        pushed: bool,
+    }
+    struct Inner;
+
+    impl SimpleState for Outer {
+        fn systems(&mut self) -> Option<StateSystems<'static, 'static>> {
+            let builder = GameDataBuilder::default().with(RanSystem("outer"), "outer", &[]);
+            Some(StateSystems::new(builder).with_run_while_paused(self.while_paused))
+        }
+
+        fn update(&mut self, _: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+            if self.pushed {
+                Trans::Quit
+            } else {
+                self.pushed = true;
+                Trans::Push(Box::new(Inner))
+            }
+        }
+    }
+
+    impl SimpleState for Inner {
+        fn systems(&mut self) -> Option<StateSystems<'static, 'static>> {
+            let builder = GameDataBuilder::default().with(RanSystem("inner"), "inner", &[]);
+            Some(StateSystems::new(builder))
+        }
+
+        fn update(&mut self, _: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+            Trans::Pop
+        }
+    }
+
+    fn run_state_systems(while_paused: bool) -> Vec<&'static str> {
+        use crate::{
+            core::ArcThreadPool,
+            ecs::prelude::{World, WorldExt},
+            DataInit,
+        };
+
+        let mut world = World::new();
+        world.insert(ArcThreadPool::new(
+            rayon::ThreadPoolBuilder::new().build().unwrap(),
+        ));
+        world.insert(Ran::default());
+        let mut data = GameDataBuilder::default().build(&mut world);
+
+        let mut sm = StateMachine::new(Outer {
+            while_paused,
+            pushed: false,
+        });
+        sm.start(StateData::new(&mut world, &mut data)).unwrap();
+        // Outer pushes Inner, Inner pops, Outer quits.
+        for _ in 0..3 {
+            sm.update(StateData::new(&mut world, &mut data));
+        }
+        assert!(!sm.is_running());
+        let ran = world.remove::<Ran>().unwrap().0;
+        data.dispose(&mut world);
+        ran
+    }
+
+    #[test]
+    fn state_systems() {
+        assert_eq!(run_state_systems(false), vec!["outer", "inner", "outer"]);
+        assert_eq!(
+            run_state_systems(true),
+            vec!["outer", "outer", "inner", "outer"]
+        );
+    }
+
+    #[test]
+    fn fixed_systems() {
+        use crate::{
+            core::ArcThreadPool,
+            ecs::prelude::{World, WorldExt},
+            DataInit,
+        };
+
+        struct Idle;
+        impl SimpleState for Idle {}
+
+        let mut world = World::new();
+        world.insert(ArcThreadPool::new(
+            rayon::ThreadPoolBuilder::new().build().unwrap(),
+        ));
+        world.insert(Ran::default());
+        let mut data = GameDataBuilder::default()
+            .with(RanSystem("update"), "update", &[])
+            .with_fixed(RanSystem("fixed"), "fixed", &[])
+            .build(&mut world);
+
+        let mut sm = StateMachine::new(Idle);
+        sm.start(StateData::new(&mut world, &mut data)).unwrap();
+        for _ in 0..2 {
+            sm.fixed_update(StateData::new(&mut world, &mut data));
+            data.fixed_update(&world);
+        }
+        sm.update(StateData::new(&mut world, &mut data));
+        assert_eq!(
+            world.read_resource::<Ran>().0,
+            vec!["fixed", "fixed", "update"]
+        );
+        data.dispose(&mut world);
+    }
 }
@@ -1,6 +1,8 @@
 use derivative::Derivative;
 use winit::Event;
 
+#[cfg(feature = "saveload")]
+use crate::assets::SaveLoadEvent;
 use crate::{
     core::{
         ecs::{Read, SystemData, World},
@@ -12,19 +14,35 @@ use crate::{
     ui::UiEvent,
 };
 
-/// The enum holding the different types of event that can be received in a `State` in the
-/// `handle_event` method.
-#[derive(Debug, Derivative, EventReader)]
-#[derivative(Clone(bound = ""))]
-#[reader(StateEventReader)]
-pub enum StateEvent<T = StringBindings>
-where
-    T: BindingTypes,
-{
-    /// Events sent by the winit window.
-    Window(Event),
-    /// Events sent by the ui system.
-    Ui(UiEvent),
-    /// Events sent by the input system.
-    Input(InputEvent<T>),
+/// Defines `StateEvent` with the given variants in addition to the ones it always has, as
+/// `EventReader` can't be derived for enums with conditional variants.
+macro_rules! state_event {
+    ($($(#[$attr:meta])* $variant:ident($event:ty),)*) => {
+        /// The enum holding the different types of event that can be received in a `State` in the
+        /// `handle_event` method.
+        #[derive(Debug, Derivative, EventReader)]
+        #[derivative(Clone(bound = ""))]
+        #[reader(StateEventReader)]
+        pub enum StateEvent<T = StringBindings>
+        where
+            T: BindingTypes,
+        {
+            /// Events sent by the winit window.
+            Window(Event),
+            /// Events sent by the ui system.
+            Ui(UiEvent),
+            /// Events sent by the input system.
+            Input(InputEvent<T>),
+            $($(#[$attr])* $variant($event),)*
+        }
+    };
 }
+
+#[cfg(feature = "saveload")]
+state_event! {
+    /// Events sent by the system added with `SaveLoadBundle`.
+    SaveLoad(SaveLoadEvent),
+}
+
+#[cfg(not(feature = "saveload"))]
+state_event! {}